@@ -10,12 +10,27 @@ pub struct Cli {
     /// Enable debug mode with verbose logging in a separate terminal
     #[arg(long, global = true)]
     pub debug: bool,
+
+    /// Use this cookbook directory instead of ~/.config/kitchn (or $KITCHN_HOME/config).
+    /// Runs isolated: without system cookbooks and with a lock of its own
+    #[arg(long, global = true, value_name = "DIR")]
+    pub config_dir: Option<PathBuf>,
+
+    /// Keep the pantry in this directory instead of ~/.local/share/kitchn (or $KITCHN_HOME/data).
+    /// Runs isolated, like --config-dir
+    #[arg(long, global = true, value_name = "DIR")]
+    pub data_dir: Option<PathBuf>,
 }
 
 #[derive(Subcommand, Debug)]
 pub enum Commands {
     /// Stock .ing ingredients or .bag packages into the pantry
-    Stock { path: PathBuf },
+    Stock {
+        path: PathBuf,
+        /// Keep following the source file: `kitchn watch` re-stocks it on change
+        #[arg(long)]
+        link: bool,
+    },
     /// Wrap .ing ingredients from a directory into a .bag package
     Wrap {
         /// Directory containing .ing files
@@ -40,15 +55,127 @@ pub enum Commands {
     },
     /// Bake cookbook into binary pastry for faster startup
     Bake,
+    /// Manage the theme library in the `themes/` config directory
+    Theme {
+        #[command(subcommand)]
+        command: ThemeCommands,
+    },
+    /// Read or change one cookbook setting, e.g. `theme.colors.primary`
+    Config {
+        #[command(subcommand)]
+        command: ConfigCommands,
+    },
+    /// Re-bake and re-cook automatically when the cookbook or linked ingredients change
+    Watch {
+        /// Quiet period in milliseconds before changes are applied
+        #[arg(long, default_value_t = 300)]
+        debounce: u64,
+    },
+    /// Show which ingredient wrote a file, and when
+    Which { path: PathBuf },
+    /// Show every managed file, its owner and whether it drifted
+    Status {
+        /// Machine-readable output: `<state>\t<ingredient>\t<path>` per line
+        #[arg(long)]
+        porcelain: bool,
+    },
+    /// Check the whole setup: cookbook, presets, ingredients, log dir and cache
+    Doctor,
+    /// Print the JSON Schema of a config or ingredient file, for editor
+    /// completion and validation (taplo, Even Better TOML)
+    Schema {
+        #[arg(value_parser = ["theme", "icons", "layout", "cookbook", "ingredient"])]
+        kind: String,
+    },
     /// Internal command to watch logs via socket (Hidden)
     #[command(hide = true)]
     InternalWatch { socket_path: PathBuf },
 }
 
+#[derive(Subcommand, Debug)]
+pub enum ThemeCommands {
+    /// List the themes in the library
+    List,
+    /// Switch to a theme, then re-bake and re-cook
+    Use {
+        #[arg(required_unless_present = "default")]
+        name: Option<String>,
+        /// Drop the library selection and go back to theme.toml
+        #[arg(long, conflicts_with = "name")]
+        default: bool,
+    },
+    /// Print the name (and variant) of the active theme
+    Current,
+    /// Switch the active theme between its light and dark variants
+    Toggle,
+    /// Generate a theme from the dominant colors of a wallpaper
+    Extract {
+        image: PathBuf,
+        /// Theme name (defaults to the image file name)
+        #[arg(long)]
+        name: Option<String>,
+        /// Light background instead of dark
+        #[arg(long)]
+        light: bool,
+        /// Overwrite an existing theme of the same name
+        #[arg(long)]
+        force: bool,
+    },
+    /// Convert a base16/base24, Alacritty, kitty, Xresources or Windows Terminal scheme
+    Import {
+        file: PathBuf,
+        /// Theme name (defaults to the scheme's own name)
+        #[arg(long)]
+        name: Option<String>,
+        /// Source format, when it cannot be told from the file
+        #[arg(long, value_parser = ["base16", "alacritty", "kitty", "xresources", "windows-terminal"])]
+        format: Option<String>,
+        /// Scheme to pick from a file that holds several (Windows Terminal)
+        #[arg(long)]
+        scheme: Option<String>,
+        /// Lighten or darken colors that fail `kitchn theme check`
+        #[arg(long)]
+        fix_contrast: bool,
+        /// Overwrite an existing theme of the same name
+        #[arg(long)]
+        force: bool,
+    },
+    /// Check WCAG contrast of the active theme and suggest fixes
+    Check,
+    /// Write the active theme as base16, Xresources, JSON, CSS, SCSS, a GIMP palette or an OSC script
+    Export {
+        #[arg(long, value_parser = ["base16", "xresources", "json", "css", "scss", "gpl", "osc"])]
+        format: String,
+        /// Write to a file instead of stdout
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum ConfigCommands {
+    /// Print the value of a setting, as loaded
+    Get {
+        /// `<theme|icons|layout|cookbook>.<key>...`
+        key: String,
+    },
+    /// Change a setting in your cookbook file, keeping its comments, then re-bake
+    Set {
+        /// `<theme|icons|layout|cookbook>.<key>...`
+        key: String,
+        value: String,
+        /// Re-cook all ingredients afterwards
+        #[arg(long)]
+        cook: bool,
+    },
+}
+
 #[derive(Subcommand, Debug)]
 pub enum PantryCommands {
-    /// Remove all ingredients from the pantry
+    /// Remove all ingredients from the pantry, along with their files
     Clean,
+    /// Remove an ingredient and delete (or restore) the files it wrote
+    Remove { name: String },
     /// Enable an ingredient (remove ignored status)
     Enable { name: String },
     /// Disable an ingredient (set ignored status)
@@ -1,8 +1,7 @@
 use anyhow::{Context, Result};
-use directories::ProjectDirs;
 use serde::{Deserialize, Serialize};
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 #[derive(Debug, Serialize, Deserialize, Default)]
 pub struct CliConfig {
@@ -10,8 +9,8 @@ pub struct CliConfig {
 }
 
 impl CliConfig {
-    pub fn load() -> Result<Self> {
-        let path = Self::get_config_path()?;
+    pub fn load(config_dir: &Path) -> Result<Self> {
+        let path = Self::get_config_path(config_dir);
         if !path.exists() {
             return Ok(Self::default());
         }
@@ -23,8 +22,8 @@ impl CliConfig {
         Ok(config)
     }
 
-    pub fn save(&self) -> Result<()> {
-        let path = Self::get_config_path()?;
+    pub fn save(&self, config_dir: &Path) -> Result<()> {
+        let path = Self::get_config_path(config_dir);
         if let Some(parent) = path.parent() {
             fs::create_dir_all(parent).context("Failed to create CLI config directory")?;
         }
@@ -36,9 +35,7 @@ impl CliConfig {
         Ok(())
     }
 
-    fn get_config_path() -> Result<PathBuf> {
-        let dirs = ProjectDirs::from("", "", "kitchn")
-            .context("Could not determine project directories")?;
-        Ok(dirs.config_dir().join("cli.toml"))
+    fn get_config_path(config_dir: &Path) -> PathBuf {
+        config_dir.join("cli.toml")
     }
 }
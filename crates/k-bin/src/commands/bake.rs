@@ -1,33 +1,39 @@
 use crate::logging::{log, log_msg};
 use anyhow::{Result, anyhow};
-use directories::ProjectDirs;
 use k_lib::config::Cookbook;
+use k_lib::dirs::Dirs;
 use std::fs;
 
-pub fn execute(dirs: &ProjectDirs, config: &Cookbook) -> Result<()> {
+pub fn execute(dirs: &Dirs, config: &Cookbook) -> Result<()> {
     log(config, "bake_start");
-    let config_dir = dirs.config_dir();
-    let cache_dir = dirs.cache_dir();
+    let config_dir = &dirs.config;
 
     log_msg(config, "bake_scan", &config_dir.to_string_lossy());
 
-    let files = ["theme.toml", "icons.toml", "layout.toml", "cookbook.toml"];
-    for f in files {
-        let p = config_dir.join(f);
+    let theme = Cookbook::theme_path(dirs).unwrap_or_else(|_| config_dir.join("theme.toml"));
+    let files = [
+        theme,
+        config_dir.join("icons.toml"),
+        config_dir.join("layout.toml"),
+        config_dir.join("cookbook.toml"),
+    ];
+    // System layers show with their full path
+    for p in files.iter().flat_map(|p| Cookbook::layers(dirs, p)) {
         if p.exists() {
-            log_msg(config, "bake_file", f);
+            let shown = p.strip_prefix(config_dir).unwrap_or(&p);
+            log_msg(config, "bake_file", &shown.to_string_lossy());
         }
     }
 
-    let bin_path = cache_dir.join("pastry.bin");
+    let bin_path = dirs.pastry_path();
     if bin_path.exists() {
         let _ = fs::remove_file(&bin_path);
     }
 
-    match Cookbook::load_from_dir(config_dir) {
+    match Cookbook::load_with_cache(dirs, &bin_path, true) {
         Ok(new_config) => {
             log_msg(config, "bake_save", &bin_path.to_string_lossy());
-            if let Err(e) = new_config.save_binary(&bin_path) {
+            if let Err(e) = new_config.save_binary(&bin_path, dirs) {
                 log(config, "bake_fail");
                 return Err(anyhow!("Failed to save binary config: {}", e));
             }
@@ -0,0 +1,51 @@
+use crate::args::ConfigCommands;
+use crate::commands::{bake, cook};
+use crate::logging::log_msg;
+use anyhow::{Context, Result};
+use k_lib::config::Cookbook;
+use k_lib::db::Pantry;
+use k_lib::dirs::Dirs;
+
+pub fn execute(
+    command: ConfigCommands,
+    dirs: &Dirs,
+    db: &mut Pantry,
+    config: &Cookbook,
+) -> Result<()> {
+    match command {
+        ConfigCommands::Get { key } => match config.get_value(&key)? {
+            toml::Value::String(value) => println!("{}", value),
+            toml::Value::Table(table) => print!("{}", toml::to_string_pretty(&table)?),
+            value => println!("{}", value),
+        },
+        ConfigCommands::Set { key, value, cook } => {
+            let set = Cookbook::set_value(dirs, &key, &value)?;
+            let shown = set.file.strip_prefix(&dirs.config).unwrap_or(&set.file);
+            log_msg(
+                config,
+                "config_set",
+                &format!(
+                    "<primary>{}</primary> = {} in {}",
+                    key,
+                    value,
+                    shown.display()
+                ),
+            );
+            if let Some(winner) = &set.shadowed_by {
+                log_msg(
+                    config,
+                    "config_shadowed",
+                    &format!("<primary>{}</primary> still comes from {}", key, winner),
+                );
+            }
+
+            bake::execute(dirs, config)?;
+            if cook {
+                let fresh = Cookbook::load_in(dirs).context("Failed to load Kitchn cookbook")?;
+                cook::execute(db, &fresh, false)?;
+                db.save()?;
+            }
+        }
+    }
+    Ok(())
+}
@@ -2,10 +2,11 @@ use crate::logging::{log, log_msg};
 use anyhow::Result;
 use k_lib::config::Cookbook;
 use k_lib::db::Pantry;
+use k_lib::ingredient::Ingredient;
 use k_lib::processor;
 
-pub fn execute(db: &Pantry, config: &Cookbook, force: bool) -> Result<()> {
-    let ingredients = db.list();
+pub fn execute(db: &mut Pantry, config: &Cookbook, force: bool) -> Result<()> {
+    let ingredients: Vec<Ingredient> = db.list().into_iter().cloned().collect();
     if ingredients.is_empty() {
         log(config, "cook_empty");
         return Ok(());
@@ -15,12 +16,15 @@ pub fn execute(db: &Pantry, config: &Cookbook, force: bool) -> Result<()> {
     let mut hook_failures = 0;
     let mut skipped = 0;
 
-    for pkg in ingredients {
+    for pkg in &ingredients {
         if pkg.meta.ignored {
             log_msg(
                 config,
                 "cook_skip",
-                &format!("ignoring <secondary>{}</secondary> (disabled)", pkg.meta.name),
+                &format!(
+                    "ignoring <secondary>{}</secondary> (disabled)",
+                    pkg.meta.name
+                ),
             );
             skipped += 1;
             continue;
@@ -31,7 +35,7 @@ pub fn execute(db: &Pantry, config: &Cookbook, force: bool) -> Result<()> {
             "cook_start",
             &format!("simmering <primary>{}</primary>", pkg.meta.name),
         );
-        if !processor::apply(pkg, config, force)? {
+        if !processor::apply_tracked(pkg, config, force, db)? {
             hook_failures += 1;
         }
     }
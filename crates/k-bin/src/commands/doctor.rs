@@ -0,0 +1,379 @@
+use crate::logging::log_msg;
+use anyhow::{Result, anyhow};
+use colored::*;
+use k_lib::color::Color;
+use k_lib::config::{Cookbook, THEME_SELECTION_FILE};
+use k_lib::db::Pantry;
+use k_lib::dirs::Dirs;
+use k_lib::format::{FormatError, PASTRY};
+use k_lib::{logger, processor};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Display;
+use std::fs;
+use std::path::Path;
+
+/// Every preset the `kitchn` binary logs with. A missing preset (or one
+/// without a scope) is dropped silently, so new ones belong here too.
+const PRESETS: &[&str] = &[
+    "bake_fail",
+    "bake_file",
+    "bake_ok",
+    "bake_save",
+    "bake_scan",
+    "bake_start",
+    "config_set",
+    "config_shadowed",
+    "cook_empty",
+    "cook_ok",
+    "cook_skip",
+    "cook_start",
+    "doctor_ok",
+    "error",
+    "info",
+    "pantry_clean_ok",
+    "pantry_empty",
+    "pantry_fail",
+    "pantry_ok",
+    "remove_fail",
+    "remove_file",
+    "remove_ok",
+    "remove_restore",
+    "status_empty",
+    "stock_ok",
+    "theme_check",
+    "theme_contrast",
+    "theme_empty",
+    "theme_export",
+    "theme_extract",
+    "theme_gap",
+    "theme_import",
+    "theme_use",
+    "warn",
+    "watch_change",
+    "watch_fail",
+    "watch_start",
+    "wrap_ok",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Outcome {
+    Pass,
+    Warn,
+    Fail,
+}
+
+impl Outcome {
+    fn colored(self) -> ColoredString {
+        match self {
+            Outcome::Pass => "pass".green(),
+            Outcome::Warn => "warn".yellow(),
+            Outcome::Fail => "fail".red(),
+        }
+    }
+}
+
+#[derive(Default)]
+struct Report {
+    warnings: usize,
+    failures: usize,
+}
+
+impl Report {
+    fn section(&self, title: &str) {
+        println!("{}", title.bold());
+    }
+
+    fn record(&mut self, outcome: Outcome, subject: &str, detail: impl Display) {
+        match outcome {
+            Outcome::Pass => {}
+            Outcome::Warn => self.warnings += 1,
+            Outcome::Fail => self.failures += 1,
+        }
+        println!("  {}  {:<28} {}", outcome.colored(), subject, detail);
+    }
+}
+
+pub fn execute(dirs: &Dirs) -> Result<()> {
+    let bin_path = &dirs.pastry_path();
+    let mut report = Report::default();
+
+    report.section("cookbook");
+    let config = check_cookbook(&mut report, dirs, bin_path);
+
+    if let Some(config) = &config {
+        report.section("presets");
+        check_presets(&mut report, config);
+
+        report.section("pantry");
+        check_pantry(&mut report, config, &dirs.pantry_path());
+    }
+
+    report.section("system");
+    if let Some(config) = &config {
+        check_log_dir(&mut report, config);
+    }
+    check_cache(&mut report, dirs, bin_path);
+
+    if report.failures > 0 {
+        return Err(anyhow!(
+            "checks failed: {}, warnings: {}",
+            report.failures,
+            report.warnings
+        ));
+    }
+    if let Some(config) = &config {
+        log_msg(
+            config,
+            "doctor_ok",
+            &format!("all checks passed ({} warnings)", report.warnings),
+        );
+    }
+    Ok(())
+}
+
+/// Parses every cookbook file, loads the cookbook from them and flags keys
+/// kitchn does not read. Returns the cookbook if it loads.
+fn check_cookbook(report: &mut Report, dirs: &Dirs, bin_path: &Path) -> Option<Cookbook> {
+    let config_dir = &dirs.config;
+    let shown = |path: &Path| {
+        path.strip_prefix(config_dir)
+            .unwrap_or(path)
+            .to_string_lossy()
+            .into_owned()
+    };
+
+    // An unreadable file stops the include walk; the top-level files still
+    // get checked one by one so the report names the broken one. A complete
+    // walk only lists missing files that may be missing (`?` includes).
+    let (files, complete) = match Cookbook::source_files(dirs) {
+        Ok(files) => (files, true),
+        Err(_) => {
+            let theme =
+                Cookbook::theme_path(dirs).unwrap_or_else(|_| config_dir.join("theme.toml"));
+            let files = vec![
+                config_dir.join(THEME_SELECTION_FILE),
+                theme,
+                config_dir.join("icons.toml"),
+                config_dir.join("layout.toml"),
+                config_dir.join("cookbook.toml"),
+            ];
+            (files, false)
+        }
+    };
+    let optional = [
+        config_dir.join(THEME_SELECTION_FILE),
+        config_dir.join("cookbook.toml"),
+    ];
+    let mut broken = false;
+    for path in &files {
+        if !path.exists() {
+            if !complete && !optional.contains(path) {
+                report.record(Outcome::Fail, &shown(path), "missing");
+                broken = true;
+            }
+            continue;
+        }
+        match parse(path) {
+            Ok(()) => report.record(Outcome::Pass, &shown(path), "parses"),
+            Err(e) => {
+                report.record(Outcome::Fail, &shown(path), e);
+                broken = true;
+            }
+        }
+    }
+
+    // Straight from the TOMLs: the cache is checked on its own
+    let config = match Cookbook::load_with_cache(dirs, bin_path, true) {
+        Ok(config) => config,
+        Err(e) => {
+            // A file that does not parse was already reported above
+            if !broken {
+                report.record(Outcome::Fail, "cookbook", format!("does not load: {}", e));
+            }
+            return None;
+        }
+    };
+
+    match Cookbook::unknown_keys(dirs) {
+        Ok(unknown) if unknown.is_empty() => {
+            report.record(Outcome::Pass, "keys", "no unknown keys");
+        }
+        Ok(unknown) => {
+            for (path, key) in unknown {
+                report.record(
+                    Outcome::Warn,
+                    &shown(&path),
+                    format!("unknown key '{}' is ignored", key),
+                );
+            }
+        }
+        Err(e) => report.record(Outcome::Fail, "keys", e),
+    }
+
+    Some(config)
+}
+
+/// Parses `path` as TOML, with errors shortened to one line.
+fn parse(path: &Path) -> Result<(), String> {
+    let content = fs::read_to_string(path).map_err(|e| e.to_string())?;
+    toml::from_str::<toml::Value>(&content)
+        .map(|_| ())
+        .map_err(|e| match e.span() {
+            Some(span) => {
+                let line = content[..span.start].lines().count().max(1);
+                format!("line {}: {}", line, e.message().replace('\n', ", "))
+            }
+            None => e.message().replace('\n', ", "),
+        })
+}
+
+fn check_presets(report: &mut Report, config: &Cookbook) {
+    let presets = &config.dictionary.presets;
+    let chain = &config.theme.settings.active_icons;
+    for set in chain.sets() {
+        if !config.icons.sets.contains_key(set) {
+            report.record(
+                Outcome::Warn,
+                &format!("icon set {}", set),
+                "not defined in icons.toml",
+            );
+        }
+    }
+
+    // Tag colors that resolve to nothing fall back to the level color
+    let tag_levels: BTreeMap<_, _> = config.layout.tag.levels.iter().collect();
+    for (level, tag) in tag_levels {
+        for color in [&tag.fg, &tag.bg].into_iter().flatten() {
+            if !config.theme.colors.contains_key(color) && color.parse::<Color>().is_err() {
+                report.record(
+                    Outcome::Warn,
+                    &format!("tag {}", level),
+                    format!("'{}' is neither a theme color nor a color", color),
+                );
+            }
+        }
+    }
+
+    let levels: BTreeSet<&str> = presets.values().map(|p| p.level.as_str()).collect();
+    for level in levels {
+        // Every set in the chain should have it, so falling back loses nothing
+        let mut missing: Vec<String> = chain
+            .sets()
+            .iter()
+            .filter(|set| {
+                config
+                    .icons
+                    .sets
+                    .get(*set)
+                    .is_some_and(|icons| !icons.contains_key(level))
+            })
+            .map(|set| format!("{} icon", set))
+            .collect();
+        if missing.is_empty() && config.icons.resolve(chain, level).is_none() {
+            missing.push("icon".to_string());
+        }
+        if !config.theme.colors.contains_key(level) {
+            missing.push("color".to_string());
+        }
+        let subject = format!("level {}", level);
+        if missing.is_empty() {
+            report.record(Outcome::Pass, &subject, "icons and color");
+        } else {
+            report.record(
+                Outcome::Warn,
+                &subject,
+                format!("no {}", missing.join(", ")),
+            );
+        }
+    }
+
+    let mut broken = 0;
+    for key in PRESETS {
+        let problem = match presets.get(*key) {
+            None => "missing, its messages are dropped",
+            Some(preset) if preset.scope.is_none() => "no scope, its messages are dropped",
+            Some(_) => continue,
+        };
+        report.record(Outcome::Fail, &format!("preset {}", key), problem);
+        broken += 1;
+    }
+    if broken == 0 {
+        report.record(
+            Outcome::Pass,
+            "presets",
+            format!("all {} used by kitchn are defined", PRESETS.len()),
+        );
+    }
+}
+
+fn check_pantry(report: &mut Report, config: &Cookbook, db_path: &Path) {
+    let db = match Pantry::load(db_path) {
+        Ok(db) => db,
+        Err(e) => {
+            report.record(Outcome::Fail, "pantry.db", format!("{:#}", e));
+            return;
+        }
+    };
+
+    let ingredients = db.list();
+    if ingredients.is_empty() {
+        report.record(Outcome::Pass, "pantry", "no ingredients stocked");
+    }
+    for pkg in ingredients {
+        if pkg.meta.ignored {
+            report.record(Outcome::Pass, &pkg.meta.name, "disabled, not rendered");
+            continue;
+        }
+        match processor::render(pkg, config) {
+            Ok(_) => report.record(Outcome::Pass, &pkg.meta.name, "renders"),
+            Err(e) => report.record(Outcome::Fail, &pkg.meta.name, format!("{:#}", e)),
+        }
+    }
+}
+
+fn check_log_dir(report: &mut Report, config: &Cookbook) {
+    let probe = |dir: &Path| -> std::io::Result<()> {
+        fs::create_dir_all(dir)?;
+        let file = dir.join(".kitchn-doctor");
+        fs::write(&file, b"")?;
+        fs::remove_file(&file)
+    };
+    match logger::log_dir(config) {
+        Ok(dir) => match probe(&dir) {
+            Ok(()) => report.record(Outcome::Pass, "log dir", dir.display()),
+            Err(e) => report.record(
+                Outcome::Fail,
+                "log dir",
+                format!("{} is not writable: {}", dir.display(), e),
+            ),
+        },
+        Err(e) => report.record(Outcome::Fail, "log dir", format!("{:#}", e)),
+    }
+}
+
+fn check_cache(report: &mut Report, dirs: &Dirs, bin_path: &Path) {
+    if !bin_path.exists() {
+        report.record(Outcome::Warn, "pastry.bin", "not baked (run `kitchn bake`)");
+        return;
+    }
+    let header = fs::File::open(bin_path)
+        .map_err(FormatError::from)
+        .and_then(|mut file| PASTRY.read_current(&mut file));
+    if let Err(e) = header {
+        report.record(
+            Outcome::Warn,
+            "pastry.bin",
+            format!("{} (run `kitchn bake`)", e),
+        );
+        return;
+    }
+    match Cookbook::is_cache_fresh(bin_path, dirs) {
+        Ok(true) => report.record(Outcome::Pass, "pastry.bin", "fresh"),
+        Ok(false) => report.record(
+            Outcome::Warn,
+            "pastry.bin",
+            "the cookbook changed since it was baked (run `kitchn bake`)",
+        ),
+        Err(e) => report.record(Outcome::Fail, "pastry.bin", e),
+    }
+}
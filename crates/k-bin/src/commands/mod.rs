@@ -1,27 +1,41 @@
 pub mod bake;
+pub mod config;
 pub mod cook;
+pub mod doctor;
 pub mod pantry;
+pub mod schema;
+pub mod status;
 pub mod stock;
+pub mod theme;
+pub mod watch;
+pub mod which;
 pub mod wrap;
 
 use crate::args::Commands;
 use crate::logging::log_msg;
 use anyhow::{Context, Result};
-use directories::ProjectDirs;
 use k_lib::config::Cookbook;
 use k_lib::db::Pantry;
+use k_lib::dirs::Dirs;
 use k_lib::processor;
+use std::time::Duration;
 
-pub fn process_command(cmd: Commands) -> Result<()> {
-    let dirs = ProjectDirs::from("", "", "kitchn").context("Could not determine project dirs")?;
-    let data_dir = dirs.data_dir();
-    let db_path = data_dir.join("pantry.db");
+pub fn process_command(cmd: Commands, dirs: &Dirs) -> Result<()> {
+    // These run before the cookbook loads: `doctor` reports why it does
+    // not, and a schema helps fix it
+    match &cmd {
+        Commands::Doctor => return doctor::execute(dirs),
+        Commands::Schema { kind } => return schema::execute(kind),
+        _ => {}
+    }
+
+    let db_path = dirs.pantry_path();
     let mut db = Pantry::load(&db_path)?;
-    let config = Cookbook::load().context("Failed to load Kitchn cookbook")?;
+    let config = Cookbook::load_in(dirs).context("Failed to load Kitchn cookbook")?;
 
     match cmd {
-        Commands::Stock { path } => {
-            let installed = stock::stock_pantry(&path, &mut db, &config)?;
+        Commands::Stock { path, link } => {
+            let installed = stock::stock_pantry(&path, &mut db, &config, link)?;
             db.save()?;
 
             for pkg in installed {
@@ -29,7 +43,10 @@ pub fn process_command(cmd: Commands) -> Result<()> {
                     log_msg(
                         &config,
                         "cook_skip",
-                        &format!("ignoring <secondary>{}</secondary> (disabled)", pkg.meta.name),
+                        &format!(
+                            "ignoring <secondary>{}</secondary> (disabled)",
+                            pkg.meta.name
+                        ),
                     );
                     continue;
                 }
@@ -38,8 +55,9 @@ pub fn process_command(cmd: Commands) -> Result<()> {
                     "cook_start",
                     &format!("simmering {}", pkg.meta.name),
                 );
-                let _ = processor::apply(&pkg, &config, false)?;
+                let _ = processor::apply_tracked(&pkg, &config, false, &mut db)?;
             }
+            db.save()?;
         }
         Commands::Wrap { input, output } => {
             wrap::execute(input, output, &config)?;
@@ -54,7 +72,7 @@ pub fn process_command(cmd: Commands) -> Result<()> {
 
             // Handle Toggle
             if toggle_force {
-                let mut cli_conf = CliConfig::load().unwrap_or_default();
+                let mut cli_conf = CliConfig::load(&dirs.config).unwrap_or_default();
                 cli_conf.force_cooking = !cli_conf.force_cooking;
 
                 if cli_conf.force_cooking {
@@ -63,7 +81,7 @@ pub fn process_command(cmd: Commands) -> Result<()> {
                     log_msg(&config, "info", "FORCE MODE DISABLED (persistent)");
                 }
 
-                if let Err(e) = cli_conf.save() {
+                if let Err(e) = cli_conf.save(&dirs.config) {
                     log_msg(
                         &config,
                         "error",
@@ -77,7 +95,10 @@ pub fn process_command(cmd: Commands) -> Result<()> {
                 }
             } else {
                 // If not toggling, checking persistent state
-                if CliConfig::load().unwrap_or_default().force_cooking {
+                if CliConfig::load(&dirs.config)
+                    .unwrap_or_default()
+                    .force_cooking
+                {
                     current_force = true;
                 }
             }
@@ -85,7 +106,7 @@ pub fn process_command(cmd: Commands) -> Result<()> {
             // If force is active, we MUST reload the cookbook ignoring cache
             let final_config = if current_force {
                 // Reload config forcing cache bypass
-                match Cookbook::load_no_cache() {
+                match Cookbook::load_no_cache_in(dirs) {
                     Ok(c) => c,
                     Err(e) => {
                         log_msg(
@@ -104,15 +125,31 @@ pub fn process_command(cmd: Commands) -> Result<()> {
                 log_msg(&final_config, "warn", "COOKING WITH FORCE (Cache bypassed)");
             }
 
-            cook::execute(&db, &final_config, current_force)?;
+            cook::execute(&mut db, &final_config, current_force)?;
+            db.save()?;
         }
         Commands::Pantry { command } => {
             pantry::execute(command, &mut db, &config)?;
         }
         Commands::Bake => {
-            bake::execute(&dirs, &config)?;
+            bake::execute(dirs, &config)?;
+        }
+        Commands::Theme { command } => {
+            theme::execute(command, dirs, &mut db, &config)?;
+        }
+        Commands::Config { command } => {
+            config::execute(command, dirs, &mut db, &config)?;
+        }
+        Commands::Status { porcelain } => {
+            status::execute(&db, &config, porcelain)?;
+        }
+        Commands::Watch { debounce } => {
+            watch::execute(dirs, &db_path, config, Duration::from_millis(debounce))?;
+        }
+        Commands::Which { path } => {
+            which::execute(&path, &db)?;
         }
-        Commands::InternalWatch { .. } => {}
+        Commands::Doctor | Commands::Schema { .. } | Commands::InternalWatch { .. } => {}
     }
     Ok(())
 }
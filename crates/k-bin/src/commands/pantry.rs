@@ -1,30 +1,57 @@
 use crate::args::PantryCommands;
 use crate::logging::{log, log_msg};
-use anyhow::Result;
+use anyhow::{Result, anyhow};
 use colored::*;
 use k_lib::config::Cookbook;
-use k_lib::db::Pantry;
+use k_lib::db::{Pantry, Removal};
+use k_lib::processor;
 
 pub fn execute(command: Option<PantryCommands>, db: &mut Pantry, config: &Cookbook) -> Result<()> {
     match command {
         Some(PantryCommands::Clean) => {
-            let count = db.list().len();
-            if count == 0 {
+            let names: Vec<String> = db.list().iter().map(|p| p.meta.name.clone()).collect();
+            if names.is_empty() {
                 log_msg(config, "pantry_empty", "pantry is already empty");
             } else {
-                db.clean();
-                db.save()?;
+                let mut stuck = Vec::new();
+                for name in &names {
+                    if remove_ingredient(name, db, config).is_err() {
+                        stuck.push(name.as_str());
+                    }
+                }
+                if !stuck.is_empty() {
+                    return Err(anyhow!("could not fully remove: {}", stuck.join(", ")));
+                }
                 log_msg(
                     config,
                     "pantry_clean_ok",
-                    &format!("removed {} ingredients", count),
+                    &format!("removed {} ingredients", names.len()),
+                );
+            }
+        }
+        Some(PantryCommands::Remove { name }) => {
+            if remove_ingredient(&name, db, config)? {
+                log_msg(
+                    config,
+                    "remove_ok",
+                    &format!("removed ingredient <primary>{}</primary>", name),
+                );
+            } else {
+                log_msg(
+                    config,
+                    "remove_fail",
+                    &format!("ingredient '{}' not found", name),
                 );
             }
         }
         Some(PantryCommands::Enable { name }) => {
             if db.set_ignored(&name, false)? {
                 db.save()?;
-                log_msg(config, "pantry_ok", &format!("enabled ingredient '{}'", name));
+                log_msg(
+                    config,
+                    "pantry_ok",
+                    &format!("enabled ingredient '{}'", name),
+                );
             } else {
                 log_msg(
                     config,
@@ -56,6 +83,66 @@ pub fn execute(command: Option<PantryCommands>, db: &mut Pantry, config: &Cookbo
     Ok(())
 }
 
+/// Deletes or restores everything `name` wrote, persists the pantry, then runs
+/// the ingredient's `on_remove` hook. Returns `false` if it was not stocked.
+///
+/// Targets that could not be handled are reported and keep the ingredient
+/// stocked; the pantry is saved either way so it matches the disk.
+fn remove_ingredient(name: &str, db: &mut Pantry, config: &Cookbook) -> Result<bool> {
+    let Some((pkg, removals)) = db.remove(name)? else {
+        return Ok(false);
+    };
+
+    let mut failed = 0;
+    for removal in removals {
+        match removal {
+            Removal::Deleted(path) => log_msg(
+                config,
+                "remove_file",
+                &format!("deleted {}", path.display()),
+            ),
+            Removal::Restored(path) => log_msg(
+                config,
+                "remove_restore",
+                &format!("restored original {}", path.display()),
+            ),
+            Removal::Missing(path) => log_msg(
+                config,
+                "remove_file",
+                &format!("{} was already gone", path.display()),
+            ),
+            Removal::Shared(path, owner) => log_msg(
+                config,
+                "remove_file",
+                &format!("kept {}, still owned by {}", path.display(), owner),
+            ),
+            Removal::Failed(path, error) => {
+                failed += 1;
+                log_msg(
+                    config,
+                    "remove_fail",
+                    &format!("could not remove {}: {}", path.display(), error),
+                );
+            }
+        }
+    }
+    db.save()?;
+
+    if failed > 0 {
+        return Err(anyhow!(
+            "{} of {}'s files could not be removed; it stays stocked",
+            failed,
+            name
+        ));
+    }
+
+    if let Some(cmd) = &pkg.hooks.on_remove {
+        processor::run_hook(config, cmd)?;
+    }
+
+    Ok(true)
+}
+
 fn list_pantry(db: &Pantry, config: &Cookbook) {
     println!("{}", "\nStocked Ingredients (Pantry):\n".bold().underline());
 
@@ -0,0 +1,8 @@
+use anyhow::Result;
+use k_lib::schema::{self, Kind};
+
+pub fn execute(kind: &str) -> Result<()> {
+    let kind: Kind = kind.parse()?;
+    println!("{}", serde_json::to_string_pretty(&schema::schema(kind))?);
+    Ok(())
+}
@@ -0,0 +1,144 @@
+use crate::logging::log;
+use anyhow::Result;
+use colored::*;
+use k_lib::config::Cookbook;
+use k_lib::db::{Pantry, content_hash};
+use k_lib::processor;
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+use tracing::warn;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TargetState {
+    /// On disk exactly as kitchn last wrote it, and still current.
+    Clean,
+    /// Edited by hand since the last cook.
+    Modified,
+    /// Deleted since the last cook.
+    Missing,
+    /// Untouched, but the current cookbook would render something else.
+    Outdated,
+    /// Present, but its state cannot be told: the ingredient no longer
+    /// renders, or the record was migrated from a pantry without hashes.
+    Unknown,
+}
+
+impl TargetState {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            TargetState::Clean => "clean",
+            TargetState::Modified => "modified",
+            TargetState::Missing => "missing",
+            TargetState::Outdated => "outdated",
+            TargetState::Unknown => "unknown",
+        }
+    }
+
+    fn colored(self) -> ColoredString {
+        let label = format!("{:<9}", self.as_str());
+        match self {
+            TargetState::Clean => label.green(),
+            TargetState::Modified => label.yellow(),
+            TargetState::Missing => label.red(),
+            TargetState::Outdated => label.cyan(),
+            TargetState::Unknown => label.magenta(),
+        }
+    }
+}
+
+pub struct Entry {
+    pub ingredient: String,
+    pub path: PathBuf,
+    pub state: TargetState,
+}
+
+/// Compares every recorded target with the disk and with a fresh render.
+pub fn collect(db: &Pantry, config: &Cookbook) -> Vec<Entry> {
+    let mut entries = Vec::new();
+
+    for pkg in db.list() {
+        let records = db.targets(&pkg.meta.name);
+        if records.is_empty() {
+            continue;
+        }
+
+        // A broken template must not hide the state of everything else
+        let current: Option<HashMap<PathBuf, String>> = match processor::render(pkg, config) {
+            Ok(rendered) => Some(
+                rendered
+                    .into_iter()
+                    .map(|(path, content)| (path, content_hash(content.as_bytes())))
+                    .collect(),
+            ),
+            Err(e) => {
+                warn!("Could not render {}: {:#}", pkg.meta.name, e);
+                None
+            }
+        };
+
+        for record in records {
+            let state = match fs::read(&record.path) {
+                Err(_) => TargetState::Missing,
+                Ok(_) if record.hash.is_empty() => TargetState::Unknown,
+                Ok(bytes) if content_hash(&bytes) != record.hash => TargetState::Modified,
+                Ok(_) => match current.as_ref().map(|c| c.get(&record.path)) {
+                    None => TargetState::Unknown,
+                    Some(Some(hash)) if *hash != record.hash => TargetState::Outdated,
+                    Some(_) => TargetState::Clean,
+                },
+            };
+            entries.push(Entry {
+                ingredient: pkg.meta.name.clone(),
+                path: record.path.clone(),
+                state,
+            });
+        }
+    }
+
+    entries
+}
+
+pub fn execute(db: &Pantry, config: &Cookbook, porcelain: bool) -> Result<()> {
+    let entries = collect(db, config);
+
+    if porcelain {
+        for entry in &entries {
+            println!(
+                "{}\t{}\t{}",
+                entry.state.as_str(),
+                entry.ingredient,
+                entry.path.display()
+            );
+        }
+        return Ok(());
+    }
+
+    println!("{}", "\nManaged Files:\n".bold().underline());
+
+    if entries.is_empty() {
+        log(config, "status_empty");
+        return Ok(());
+    }
+
+    let home = directories::UserDirs::new().map(|d| d.home_dir().to_path_buf());
+    let mut current = None;
+    for entry in &entries {
+        if current != Some(&entry.ingredient) {
+            if current.is_some() {
+                println!();
+            }
+            println!("  {}", entry.ingredient.blue().bold());
+            current = Some(&entry.ingredient);
+        }
+
+        let shown = match home.as_ref().and_then(|h| entry.path.strip_prefix(h).ok()) {
+            Some(rel) => format!("~/{}", rel.display()),
+            None => entry.path.display().to_string(),
+        };
+        println!("    {} {}", entry.state.colored(), shown);
+    }
+    println!();
+
+    Ok(())
+}
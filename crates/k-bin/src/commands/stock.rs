@@ -7,7 +7,12 @@ use std::fs;
 use std::io::Read;
 use std::path::Path;
 
-pub fn stock_pantry(path: &Path, db: &mut Pantry, config: &Cookbook) -> Result<Vec<Ingredient>> {
+pub fn stock_pantry(
+    path: &Path,
+    db: &mut Pantry,
+    config: &Cookbook,
+    link: bool,
+) -> Result<Vec<Ingredient>> {
     let mut installed_list = Vec::new();
 
     if !path.exists() {
@@ -52,5 +57,13 @@ pub fn stock_pantry(path: &Path, db: &mut Pantry, config: &Cookbook) -> Result<V
         db.store(pkg)?;
         installed_list.push(pkg_clone);
     }
+
+    if link {
+        let source = fs::canonicalize(path)?;
+        for pkg in &installed_list {
+            db.link(&pkg.meta.name, &source);
+        }
+    }
+
     Ok(installed_list)
 }
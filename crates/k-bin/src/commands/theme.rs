@@ -0,0 +1,324 @@
+use crate::args::ThemeCommands;
+use crate::commands::{bake, cook};
+use crate::logging::{log, log_msg};
+use anyhow::{Context, Result, anyhow};
+use colored::*;
+use k_lib::audit::{self, Check};
+use k_lib::color::{Palette, THEME_KEYS};
+use k_lib::config::{ConfigError, Cookbook, THEMES_DIR};
+use k_lib::db::Pantry;
+use k_lib::dirs::Dirs;
+use k_lib::exporter;
+use k_lib::extractor;
+use k_lib::importer::{self, Format};
+use k_lib::scheduler;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+pub fn execute(
+    command: ThemeCommands,
+    dirs: &Dirs,
+    db: &mut Pantry,
+    config: &Cookbook,
+) -> Result<()> {
+    let config_dir = &dirs.config;
+    match command {
+        ThemeCommands::List => {
+            println!("{}", "\nTheme Library:\n".bold().underline());
+
+            let themes = Cookbook::themes(dirs)?;
+            if themes.is_empty() {
+                log(config, "theme_empty");
+                return Ok(());
+            }
+
+            let active = Cookbook::selected_theme(config_dir)?;
+            for name in themes {
+                if active.as_deref() == Some(name.as_str()) {
+                    println!("  {} {}", "*".green().bold(), name.blue().bold());
+                } else {
+                    println!("    {}", name);
+                }
+            }
+            println!();
+        }
+        // clap only leaves `name` empty for --default
+        ThemeCommands::Use { name, .. } => {
+            match Cookbook::select_theme(dirs, name.as_deref()) {
+                Ok(()) => {}
+                Err(ConfigError::ThemeNotFound(_)) => {
+                    return Err(anyhow!(
+                        "theme '{}' not found in {}",
+                        name.unwrap_or_default(),
+                        config_dir.join(THEMES_DIR).display()
+                    ));
+                }
+                Err(e) => {
+                    return Err(e)
+                        .context(format!("Theme '{}' is not valid", name.unwrap_or_default()));
+                }
+            }
+            let shown = name.unwrap_or_else(|| "theme.toml".to_string());
+            log_msg(
+                config,
+                "theme_use",
+                &format!("switched to <primary>{}</primary>", shown),
+            );
+            apply(dirs, db, config)?;
+        }
+        ThemeCommands::Current => {
+            // No library theme selected: theme.toml is in effect
+            let name = Cookbook::selected_theme(config_dir)?
+                .unwrap_or_else(|| config.theme.meta.name.clone());
+            match &config.theme.variant {
+                Some(variant) => println!("{} ({})", name, variant),
+                None => println!("{}", name),
+            }
+        }
+        ThemeCommands::Toggle => {
+            let next = if config.theme.variant.as_deref() == Some(scheduler::DARK) {
+                scheduler::LIGHT
+            } else {
+                scheduler::DARK
+            };
+            if !config.theme.variants.contains_key(next) {
+                return Err(anyhow!(
+                    "theme '{}' has no [variants.{}]",
+                    config.theme.meta.name,
+                    next
+                ));
+            }
+
+            Cookbook::select_variant(config_dir, Some(next))?;
+            log_msg(
+                config,
+                "theme_use",
+                &format!("switched to the <primary>{}</primary> variant", next),
+            );
+            apply(dirs, db, config)?;
+        }
+        ThemeCommands::Extract {
+            image,
+            name,
+            light,
+            force,
+        } => {
+            let name = match name {
+                Some(name) => name,
+                None => image
+                    .file_stem()
+                    .map(|s| s.to_string_lossy().into_owned())
+                    .context("Cannot derive a theme name from the image path, use --name")?,
+            };
+            let path = new_theme_path(config_dir, &name, force)?;
+
+            // Recorded in the generated file, so keep it meaningful from anywhere
+            let image = std::path::absolute(&image)?;
+            let palette = extractor::extract(&image, light)?;
+            let header = format!(
+                "Generated by `kitchn theme extract` from {}",
+                image.display()
+            );
+            write_theme(&path, &palette.to_theme_toml(&name, &header, &config.theme))?;
+
+            print_swatches(&palette);
+            log_msg(
+                config,
+                "theme_extract",
+                &format!(
+                    "wrote <primary>{}</primary>, apply it with `kitchn theme use {}`",
+                    path.display(),
+                    name
+                ),
+            );
+        }
+        ThemeCommands::Import {
+            file,
+            name,
+            format,
+            scheme,
+            fix_contrast,
+            force,
+        } => {
+            let format = format.as_deref().map(str::parse::<Format>).transpose()?;
+            let file = std::path::absolute(&file)?;
+            let mut imported = importer::import(&file, format, scheme.as_deref())?;
+
+            // Scheme names like "Tokyo Night" become file names like tokyo-night
+            let display_name = name
+                .clone()
+                .or_else(|| imported.name.clone())
+                .or_else(|| file.file_stem().map(|s| s.to_string_lossy().into_owned()))
+                .context("Cannot derive a theme name from the file, use --name")?;
+            let name = name.unwrap_or_else(|| slug(&display_name));
+            let path = new_theme_path(config_dir, &name, force)?;
+
+            let failed: Vec<Check> = if fix_contrast {
+                audit::enforce(&mut imported.palette)
+            } else {
+                audit::check(&imported.palette)
+                    .into_iter()
+                    .filter(|c| !c.passed())
+                    .collect()
+            };
+
+            let header = format!(
+                "Imported from {} ({}) by `kitchn theme import`",
+                file.display(),
+                imported.format
+            );
+            write_theme(
+                &path,
+                &imported
+                    .palette
+                    .to_theme_toml(&display_name, &header, &config.theme),
+            )?;
+
+            for gap in &imported.gaps {
+                let msg = match gap.filled_from {
+                    Some(from) => format!("{}: not in the scheme, using {}", gap.key, from),
+                    None => format!("{}: not in the scheme, left out", gap.key),
+                };
+                log_msg(config, "theme_gap", &msg);
+            }
+            for check in &failed {
+                let msg = match (fix_contrast, check.fix) {
+                    (true, Some(fix)) => format!("{}, changed to {}", describe(check), fix),
+                    (true, None) => format!("{}, no lightness reaches it", describe(check)),
+                    (false, _) => format!("{} (use --fix-contrast)", describe(check)),
+                };
+                log_msg(config, "theme_contrast", &msg);
+            }
+            print_swatches(&imported.palette);
+            log_msg(
+                config,
+                "theme_import",
+                &format!(
+                    "wrote <primary>{}</primary>, apply it with `kitchn theme use {}`",
+                    path.display(),
+                    name
+                ),
+            );
+        }
+        ThemeCommands::Check => {
+            println!("{}", "\nContrast (WCAG 2.x):\n".bold().underline());
+
+            let checks = audit::check(&Palette::from_colors(&config.theme.colors));
+            for check in &checks {
+                let pair = format!("{} on {}", check.pair.fg, check.pair.bg);
+                let status = if check.passed() {
+                    "pass".green()
+                } else {
+                    "FAIL".red().bold()
+                };
+                let fix = match check.fix {
+                    Some(fix) => {
+                        format!("  try {} {}", fix, "   ".on_truecolor(fix.r, fix.g, fix.b))
+                    }
+                    None => String::new(),
+                };
+                println!(
+                    "  {}  {:<30} {:>5.2}:1  (min {}:1){}",
+                    status, pair, check.ratio, check.pair.min, fix
+                );
+            }
+            println!();
+
+            let failed = checks.iter().filter(|c| !c.passed()).count();
+            if failed == 0 {
+                log_msg(
+                    config,
+                    "theme_check",
+                    &format!("all {} pairs meet their minimum", checks.len()),
+                );
+            } else {
+                log_msg(
+                    config,
+                    "theme_contrast",
+                    &format!("{} of {} pairs below their minimum", failed, checks.len()),
+                );
+                return Err(anyhow!("contrast check failed"));
+            }
+        }
+        ThemeCommands::Export { format, output } => {
+            let format: exporter::Format = format.parse()?;
+            let contents = exporter::export(&config.theme, format);
+            let Some(path) = output else {
+                print!("{}", contents);
+                return Ok(());
+            };
+
+            fs::write(&path, &contents)
+                .with_context(|| format!("Failed to write {}", path.display()))?;
+            #[cfg(unix)]
+            if format == exporter::Format::Osc {
+                use std::os::unix::fs::PermissionsExt;
+                fs::set_permissions(&path, fs::Permissions::from_mode(0o755))?;
+            }
+            log_msg(
+                config,
+                "theme_export",
+                &format!("wrote <primary>{}</primary> as {}", path.display(), format),
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Where a new library theme called `name` goes. Fails if the name is
+/// unusable or, without `force`, already taken.
+fn new_theme_path(config_dir: &Path, name: &str, force: bool) -> Result<PathBuf> {
+    if name.is_empty() || name.contains(['/', '\\']) {
+        return Err(anyhow!("'{}' is not a valid theme name", name));
+    }
+
+    let path = config_dir.join(THEMES_DIR).join(format!("{}.toml", name));
+    if path.exists() && !force {
+        return Err(anyhow!("theme '{}' already exists (use --force)", name));
+    }
+    Ok(path)
+}
+
+fn write_theme(path: &Path, contents: &str) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(path, contents).with_context(|| format!("Failed to write {}", path.display()))
+}
+
+fn slug(name: &str) -> String {
+    name.to_lowercase()
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|part| !part.is_empty())
+        .collect::<Vec<_>>()
+        .join("-")
+}
+
+/// `blue on bg: 2.10:1, below 4.5:1`
+fn describe(check: &Check) -> String {
+    format!(
+        "{} on {}: {:.2}:1, below {}:1",
+        check.pair.fg, check.pair.bg, check.ratio, check.pair.min
+    )
+}
+
+fn print_swatches(palette: &Palette) {
+    println!();
+    for (_, keys) in THEME_KEYS {
+        let line: Vec<String> = keys
+            .iter()
+            .filter_map(|key| palette.get(key))
+            .map(|c| "   ".on_truecolor(c.r, c.g, c.b).to_string())
+            .collect();
+        println!("  {}", line.join(""));
+    }
+    println!();
+}
+
+/// Re-bakes with the new selection and re-cooks everything against it.
+fn apply(dirs: &Dirs, db: &mut Pantry, config: &Cookbook) -> Result<()> {
+    bake::execute(dirs, config)?;
+    let fresh = Cookbook::load_in(dirs).context("Failed to load Kitchn cookbook")?;
+    cook::execute(db, &fresh, false)?;
+    db.save()
+}
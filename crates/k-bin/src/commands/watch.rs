@@ -0,0 +1,542 @@
+use crate::commands::{bake, stock};
+use crate::logging::log_msg;
+use anyhow::{Context, Result, anyhow};
+use chrono::Local;
+use k_lib::config::{Cookbook, THEME_SELECTION_FILE};
+use k_lib::db::{Pantry, content_hash};
+use k_lib::dirs::Dirs;
+use k_lib::ingredient::Ingredient;
+use k_lib::processor;
+use k_lib::scheduler;
+use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::time::Duration;
+use tracing::{debug, warn};
+
+/// How often the variant schedule is checked while nothing else happens.
+const SCHEDULE_TICK: Duration = Duration::from_secs(30);
+
+/// Files that trigger a cycle, resolved to their real location so edits made
+/// through dotfile symlinks are noticed.
+#[derive(Default)]
+struct Sources {
+    config: HashSet<PathBuf>,
+    /// Linked ingredient source -> ingredients stocked from it
+    links: HashMap<PathBuf, Vec<String>>,
+}
+
+impl Sources {
+    fn scan(dirs: &Dirs, db: &Pantry, previous: &Sources) -> Self {
+        let config_dir = &dirs.config;
+        let config = match Cookbook::source_files(dirs) {
+            Ok(files) => files.iter().map(|p| resolve(p)).collect(),
+            Err(e) => {
+                // Mid-edit syntax errors are normal; keep watching what we knew
+                debug!("Could not resolve includes ({}), keeping previous set", e);
+                let mut files = previous.config.clone();
+                for f in [
+                    "theme.toml",
+                    "icons.toml",
+                    "layout.toml",
+                    "cookbook.toml",
+                    THEME_SELECTION_FILE,
+                ] {
+                    files.insert(resolve(&config_dir.join(f)));
+                }
+                files
+            }
+        };
+
+        let mut links: HashMap<PathBuf, Vec<String>> = HashMap::new();
+        for (name, source) in db.links() {
+            links.entry(resolve(source)).or_default().push(name.clone());
+        }
+
+        Sources { config, links }
+    }
+
+    fn contains(&self, path: &Path) -> bool {
+        self.config.contains(path) || self.links.contains_key(path)
+    }
+
+    fn dirs(&self) -> HashSet<PathBuf> {
+        self.config
+            .iter()
+            .chain(self.links.keys())
+            .filter_map(|p| p.parent().map(Path::to_path_buf))
+            // System dirs that were never created, usually
+            .filter(|dir| dir.is_dir())
+            .collect()
+    }
+}
+
+pub fn execute(
+    dirs: &Dirs,
+    db_path: &Path,
+    mut config: Cookbook,
+    debounce: Duration,
+) -> Result<()> {
+    let (tx, rx) = mpsc::channel();
+    let mut watcher = notify::recommended_watcher(tx).context("Failed to start file watcher")?;
+    let mut watched = HashSet::new();
+
+    let db = Pantry::load(db_path)?;
+    let mut sources = Sources::scan(dirs, &db, &Sources::default());
+    rewatch(&mut watcher, &mut watched, &sources);
+
+    log_msg(
+        &config,
+        "watch_start",
+        &format!(
+            "watching {} config files and {} linked ingredients",
+            sources.config.len(),
+            sources.links.len()
+        ),
+    );
+
+    let mut schedule = ScheduleState::default();
+    loop {
+        schedule.follow(&dirs.config, &config);
+
+        let tick = config
+            .theme
+            .settings
+            .schedule
+            .as_ref()
+            .map(|_| SCHEDULE_TICK);
+        let changed = next_batch(&rx, debounce, tick, &sources)?;
+        if changed.is_empty() {
+            continue;
+        }
+
+        for path in &changed {
+            log_msg(&config, "watch_change", &format!("{}", path.display()));
+        }
+
+        match cycle(dirs, db_path, &changed, &sources, &config) {
+            Ok(fresh) => config = fresh,
+            Err(e) => log_msg(&config, "watch_fail", &format!("{:#}", e)),
+        }
+
+        // Includes and links may have changed along with the files themselves
+        match Pantry::load(db_path) {
+            Ok(db) => {
+                sources = Sources::scan(dirs, &db, &sources);
+                rewatch(&mut watcher, &mut watched, &sources);
+            }
+            Err(e) => log_msg(&config, "watch_fail", &format!("{:#}", e)),
+        }
+    }
+}
+
+/// Variant switching for `[settings.schedule]`.
+#[derive(Default)]
+struct ScheduleState {
+    /// What the schedule asked for last time, so a manual `kitchn theme toggle`
+    /// holds until the next sunrise/sunset (or fixed time) comes around.
+    last: Option<&'static str>,
+    failing: bool,
+}
+
+impl ScheduleState {
+    /// Selects the scheduled variant when it changes. Writing the selection is
+    /// itself a watched change, so the next batch re-bakes and re-cooks.
+    fn follow(&mut self, config_dir: &Path, config: &Cookbook) {
+        let Some(schedule) = &config.theme.settings.schedule else {
+            return;
+        };
+        let wanted = match scheduler::variant_at(schedule, &Local::now()) {
+            Ok(wanted) => wanted,
+            Err(e) => {
+                if !self.failing {
+                    log_msg(config, "watch_fail", &format!("schedule: {:#}", e));
+                    self.failing = true;
+                }
+                return;
+            }
+        };
+        self.failing = false;
+
+        if self.last == Some(wanted) {
+            return;
+        }
+        self.last = Some(wanted);
+        if config.theme.variant.as_deref() == Some(wanted) {
+            return;
+        }
+
+        log_msg(
+            config,
+            "watch_change",
+            &format!("schedule: switching to the {} variant", wanted),
+        );
+        if let Err(e) = Cookbook::select_variant(config_dir, Some(wanted)) {
+            log_msg(config, "watch_fail", &format!("schedule: {}", e));
+        }
+    }
+}
+
+/// Blocks until something relevant changes (or `tick` passes), then waits for
+/// `debounce` of silence so editors that write in several steps trigger a
+/// single cycle.
+fn next_batch(
+    rx: &Receiver<notify::Result<Event>>,
+    debounce: Duration,
+    tick: Option<Duration>,
+    sources: &Sources,
+) -> Result<HashSet<PathBuf>> {
+    let mut changed = HashSet::new();
+    let first = match tick {
+        Some(tick) => match rx.recv_timeout(tick) {
+            Ok(event) => event,
+            Err(RecvTimeoutError::Timeout) => return Ok(changed),
+            Err(RecvTimeoutError::Disconnected) => {
+                return Err(anyhow!("File watcher stopped unexpectedly"));
+            }
+        },
+        None => rx
+            .recv()
+            .map_err(|_| anyhow!("File watcher stopped unexpectedly"))?,
+    };
+    collect(first, sources, &mut changed);
+    while let Ok(event) = rx.recv_timeout(debounce) {
+        collect(event, sources, &mut changed);
+    }
+    Ok(changed)
+}
+
+fn collect(event: notify::Result<Event>, sources: &Sources, changed: &mut HashSet<PathBuf>) {
+    match event {
+        Ok(event) if !matches!(event.kind, EventKind::Access(_)) => {
+            changed.extend(event.paths.into_iter().filter(|p| sources.contains(p)));
+        }
+        Ok(_) => {}
+        Err(e) => warn!("File watcher error: {}", e),
+    }
+}
+
+/// Re-stocks changed linked ingredients, re-bakes if the cookbook changed, and
+/// re-cooks every ingredient whose output would differ from what is on record.
+///
+/// An ingredient that fails to stock or cook is reported once the pantry is
+/// saved, so the records of everything else written in the cycle survive.
+fn cycle(
+    dirs: &Dirs,
+    db_path: &Path,
+    changed: &HashSet<PathBuf>,
+    sources: &Sources,
+    config: &Cookbook,
+) -> Result<Cookbook> {
+    let _lock = crate::acquire_lock(&dirs.runtime)?;
+    let mut db = Pantry::load(db_path)?;
+
+    let mut failures = Vec::new();
+    let mut restocked = HashSet::new();
+    for path in changed {
+        if sources.links.contains_key(path) {
+            match stock::stock_pantry(path, &mut db, config, true) {
+                Ok(stocked) => restocked.extend(stocked.into_iter().map(|pkg| pkg.meta.name)),
+                Err(e) => failures.push(format!("could not stock {}: {:#}", path.display(), e)),
+            }
+        }
+    }
+
+    if changed.iter().any(|p| sources.config.contains(p)) {
+        bake::execute(dirs, config)?;
+    }
+    let config = Cookbook::load_in(dirs).context("Failed to load Kitchn cookbook")?;
+
+    let ingredients: Vec<Ingredient> = db.list().into_iter().cloned().collect();
+    let mut cooked = 0;
+    for pkg in &ingredients {
+        if pkg.meta.ignored {
+            continue;
+        }
+        let affected = restocked.contains(&pkg.meta.name)
+            || match is_outdated(pkg, &db, &config) {
+                Ok(outdated) => outdated,
+                Err(e) => {
+                    log_msg(
+                        &config,
+                        "watch_fail",
+                        &format!("could not render {}: {:#}", pkg.meta.name, e),
+                    );
+                    false
+                }
+            };
+        if !affected {
+            continue;
+        }
+
+        log_msg(
+            &config,
+            "cook_start",
+            &format!("simmering <primary>{}</primary>", pkg.meta.name),
+        );
+        match processor::apply_tracked(pkg, &config, false, &mut db) {
+            Ok(_) => cooked += 1,
+            Err(e) => failures.push(format!("could not cook {}: {:#}", pkg.meta.name, e)),
+        }
+    }
+    db.save()?;
+
+    log_msg(
+        &config,
+        "cook_ok",
+        &format!("re-cooked {} ingredients", cooked),
+    );
+    for failure in &failures {
+        log_msg(&config, "watch_fail", failure);
+    }
+    Ok(config)
+}
+
+/// Whether cooking `pkg` now would write anything different from the last cook.
+fn is_outdated(pkg: &Ingredient, db: &Pantry, config: &Cookbook) -> Result<bool> {
+    let records = db.targets(&pkg.meta.name);
+    for (path, content) in processor::render(pkg, config)? {
+        let hash = content_hash(content.as_bytes());
+        if !records.iter().any(|r| r.path == path && r.hash == hash) {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+/// Watches directories rather than files: editors usually save by replacing
+/// the file, which would silently end a watch on the old inode.
+fn rewatch(watcher: &mut RecommendedWatcher, watched: &mut HashSet<PathBuf>, sources: &Sources) {
+    let wanted = sources.dirs();
+    for dir in watched.difference(&wanted) {
+        let _ = watcher.unwatch(dir);
+    }
+    watched.retain(|d| wanted.contains(d));
+
+    for dir in wanted {
+        if watched.contains(&dir) {
+            continue;
+        }
+        match watcher.watch(&dir, RecursiveMode::NonRecursive) {
+            Ok(()) => {
+                debug!("Watching {:?}", dir);
+                watched.insert(dir);
+            }
+            Err(e) => warn!("Cannot watch {:?}: {}", dir, e),
+        }
+    }
+}
+
+/// Canonical path of `path`, or of its parent if the file does not exist yet.
+fn resolve(path: &Path) -> PathBuf {
+    if let Ok(p) = fs::canonicalize(path) {
+        return p;
+    }
+    match (path.parent().map(fs::canonicalize), path.file_name()) {
+        (Some(Ok(parent)), Some(name)) => parent.join(name),
+        _ => path.to_path_buf(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use k_lib::ingredient::{Hooks, IngredientManifest, Template};
+    use notify::event::{AccessKind, ModifyKind};
+    use tempfile::tempdir;
+
+    fn write_cookbook(config_dir: &Path, bg: &str) {
+        fs::create_dir_all(config_dir).unwrap();
+        fs::write(
+            config_dir.join("theme.toml"),
+            format!(
+                "[meta]\nname = \"test\"\n[settings]\nactive_icons = \"none\"\n[colors]\nbg = \"{}\"\n[fonts]\n",
+                bg
+            ),
+        )
+        .unwrap();
+        fs::write(config_dir.join("icons.toml"), "[ascii]\n").unwrap();
+        fs::write(
+            config_dir.join("layout.toml"),
+            r#"
+[tag]
+prefix = ""
+suffix = ""
+transform = "none"
+min_width = 0
+alignment = "left"
+[labels]
+[structure]
+terminal = "{msg}"
+file = "{msg}"
+[logging]
+base_dir = "logs"
+path_structure = "sys.log"
+filename_structure = "log"
+timestamp_format = ""
+write_by_default = false
+"#,
+        )
+        .unwrap();
+    }
+
+    fn ingredient(name: &str, target: &Path) -> Ingredient {
+        Ingredient {
+            meta: IngredientManifest {
+                name: name.to_string(),
+                version: "0.1.0".to_string(),
+                authors: vec![],
+                description: "test".to_string(),
+                repository: None,
+                license: None,
+                ignored: false,
+            },
+            templates: vec![Template {
+                target: target.display().to_string(),
+                content: "bg={{ colors.bg }}".to_string(),
+                header: false,
+            }],
+            files: vec![],
+            hooks: Hooks::default(),
+        }
+    }
+
+    fn modified(path: &Path) -> notify::Result<Event> {
+        Ok(Event::new(EventKind::Modify(ModifyKind::Any)).add_path(path.to_path_buf()))
+    }
+
+    #[test]
+    fn test_is_outdated() {
+        let dir = tempdir().unwrap();
+        let config_dir = dir.path().join("config");
+        write_cookbook(&config_dir, "#000000");
+        let config = Cookbook::load_from_dir(&config_dir).unwrap();
+
+        let pkg = ingredient("app", &dir.path().join("out.conf"));
+        let mut db = Pantry::load(&dir.path().join("pantry.db")).unwrap();
+        db.store(pkg.clone()).unwrap();
+
+        // Never cooked
+        assert!(is_outdated(&pkg, &db, &config).unwrap());
+
+        for (path, content) in processor::render(&pkg, &config).unwrap() {
+            db.write_target(&pkg, "test", &path, &content).unwrap();
+        }
+        assert!(!is_outdated(&pkg, &db, &config).unwrap());
+
+        write_cookbook(&config_dir, "#ffffff");
+        let config = Cookbook::load_from_dir(&config_dir).unwrap();
+        assert!(is_outdated(&pkg, &db, &config).unwrap());
+    }
+
+    #[test]
+    fn test_cycle_keeps_records_when_an_ingredient_fails() {
+        let dir = tempdir().unwrap();
+        let dirs = Dirs::under(dir.path());
+        write_cookbook(&dirs.config, "#000000");
+        let config = Cookbook::load_from_dir(&dirs.config).unwrap();
+
+        // A regular file where the broken ingredient needs a directory
+        let blocker = dir.path().join("blocker");
+        fs::write(&blocker, "").unwrap();
+        let good = dir.path().join("good.conf");
+        let mut db = Pantry::load(&dirs.pantry_path()).unwrap();
+        db.store(ingredient("broken", &blocker.join("out.conf")))
+            .unwrap();
+        db.store(ingredient("good", &good)).unwrap();
+        db.save().unwrap();
+
+        cycle(
+            &dirs,
+            &dirs.pantry_path(),
+            &HashSet::new(),
+            &Sources::default(),
+            &config,
+        )
+        .unwrap();
+
+        let db = Pantry::load(&dirs.pantry_path()).unwrap();
+        assert_eq!(fs::read_to_string(&good).unwrap(), "bg=#000000");
+        assert_eq!(db.targets("good")[0].path, good);
+    }
+
+    #[test]
+    fn test_sources_scan_and_resolve() {
+        let dir = tempdir().unwrap();
+        let root = fs::canonicalize(dir.path()).unwrap();
+        let dirs = Dirs::under(&root);
+        let config_dir = &dirs.config;
+        write_cookbook(config_dir, "#000000");
+
+        // Linked through a dotfile symlink: the real file is what gets edited
+        let real = root.join("real.ing");
+        let link = root.join("link.ing");
+        fs::write(&real, "").unwrap();
+        std::os::unix::fs::symlink(&real, &link).unwrap();
+        assert_eq!(resolve(&link), real);
+        // Not created yet: resolved through its parent
+        assert_eq!(resolve(&root.join("new.ing")), root.join("new.ing"));
+
+        let mut db = Pantry::load(&root.join("pantry.db")).unwrap();
+        db.link("app", &link);
+
+        let sources = Sources::scan(&dirs, &db, &Sources::default());
+        assert!(sources.contains(&config_dir.join("theme.toml")));
+        assert!(sources.contains(&config_dir.join("cookbook.toml")));
+        assert!(sources.contains(&config_dir.join(THEME_SELECTION_FILE)));
+        assert_eq!(sources.links[&real], vec!["app".to_string()]);
+        assert!(!sources.contains(&link));
+        assert!(sources.dirs().contains(config_dir));
+        assert!(sources.dirs().contains(&root));
+
+        // A cookbook that does not parse keeps what was watched before
+        let extra = root.join("extra.toml");
+        let previous = Sources {
+            config: HashSet::from([extra.clone()]),
+            links: HashMap::new(),
+        };
+        fs::write(config_dir.join("theme.toml"), "include = [").unwrap();
+        let sources = Sources::scan(&dirs, &db, &previous);
+        assert!(sources.contains(&extra));
+        assert!(sources.contains(&config_dir.join("theme.toml")));
+    }
+
+    #[test]
+    fn test_next_batch_debounces() {
+        let dir = tempdir().unwrap();
+        let theme = dir.path().join("theme.toml");
+        let icons = dir.path().join("icons.toml");
+        let sources = Sources {
+            config: HashSet::from([theme.clone(), icons.clone()]),
+            links: HashMap::new(),
+        };
+        let debounce = Duration::from_millis(50);
+        let (tx, rx) = mpsc::channel();
+
+        // Several writes in a row make one batch; unrelated paths and reads
+        // are dropped
+        tx.send(modified(&theme)).unwrap();
+        tx.send(modified(&dir.path().join("unrelated"))).unwrap();
+        tx.send(Ok(
+            Event::new(EventKind::Access(AccessKind::Any)).add_path(icons.clone())
+        ))
+        .unwrap();
+        tx.send(modified(&theme)).unwrap();
+        let changed = next_batch(&rx, debounce, None, &sources).unwrap();
+        assert_eq!(changed, HashSet::from([theme.clone()]));
+
+        // Quiet for longer than the debounce: the next write is its own batch
+        tx.send(modified(&icons)).unwrap();
+        let changed = next_batch(&rx, debounce, None, &sources).unwrap();
+        assert_eq!(changed, HashSet::from([icons]));
+
+        // The schedule tick returns an empty batch
+        let changed = next_batch(&rx, debounce, Some(debounce), &sources).unwrap();
+        assert!(changed.is_empty());
+
+        drop(tx);
+        assert!(next_batch(&rx, debounce, None, &sources).is_err());
+    }
+}
@@ -0,0 +1,59 @@
+use anyhow::{Result, anyhow};
+use chrono::{DateTime, Local};
+use colored::*;
+use k_lib::db::Pantry;
+use std::fs;
+use std::path::Path;
+
+pub fn execute(path: &Path, db: &Pantry) -> Result<()> {
+    // Records hold absolute paths; accept relative input and symlinked locations
+    let absolute = std::path::absolute(path)?;
+    let found = db.owner_of(&absolute).or_else(|| {
+        let canonical = fs::canonicalize(&absolute).ok()?;
+        db.owner_of(&canonical).or_else(|| {
+            db.list().into_iter().find_map(|pkg| {
+                db.targets(&pkg.meta.name)
+                    .iter()
+                    .find(|t| fs::canonicalize(&t.path).ok().as_ref() == Some(&canonical))
+                    .map(|record| (pkg, record))
+            })
+        })
+    });
+
+    let Some((pkg, record)) = found else {
+        return Err(anyhow!("{} is not managed by kitchn", absolute.display()));
+    };
+
+    // Records migrated from older pantries carry no cook time or theme
+    let cooked_at = DateTime::from_timestamp(record.cooked_at, 0)
+        .filter(|_| record.cooked_at > 0)
+        .map(|t| {
+            t.with_timezone(&Local)
+                .format("%Y-%m-%d %H:%M:%S")
+                .to_string()
+        })
+        .unwrap_or_else(|| "unknown".to_string());
+
+    println!("{}", record.path.display().to_string().bold());
+    println!(
+        "  {:<11}{} {}",
+        "ingredient",
+        pkg.meta.name.blue().bold(),
+        format!("v{}", record.version).green()
+    );
+    println!("  {:<11}{}", "cooked", cooked_at);
+    let theme = match record.theme.as_str() {
+        "" => "unknown",
+        name => name,
+    };
+    println!("  {:<11}{}", "theme", theme);
+    if let Some(backup) = &record.backup {
+        println!(
+            "  {:<11}{}",
+            "original",
+            backup.display().to_string().dimmed()
+        );
+    }
+
+    Ok(())
+}
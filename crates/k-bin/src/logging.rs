@@ -49,10 +49,7 @@ pub fn log_msg(config: &Cookbook, preset_key: &str, msg_override: &str) {
     }
 }
 
-pub fn get_socket_path() -> PathBuf {
-    let runtime_dir = env::var("XDG_RUNTIME_DIR")
-        .map(PathBuf::from)
-        .unwrap_or_else(|_| env::temp_dir());
+pub fn get_socket_path(runtime_dir: &Path) -> PathBuf {
     runtime_dir.join("kitchn-debug.sock")
 }
 
@@ -176,13 +173,13 @@ fn strip_tags(msg: &str) -> String {
     result
 }
 
-pub fn init_logging(force_debug: bool) -> Result<bool> {
+pub fn init_logging(runtime_dir: &Path, force_debug: bool) -> Result<bool> {
     // Basic EnvFilter
     // Init LogTracer to bridge log crate events to tracing
     // Ignore error if already initialized (for tests/multiple calls safety)
     let _ = tracing_log::LogTracer::init();
 
-    let socket_path = get_socket_path();
+    let socket_path = get_socket_path(runtime_dir);
     let watcher_active = socket_path.exists();
 
     // Enable debug if flag is passed OR if the debug watcher is active/socket exists
@@ -208,8 +205,9 @@ pub fn init_logging(force_debug: bool) -> Result<bool> {
     Ok(true)
 }
 
-pub fn spawn_debug_viewer() -> Result<()> {
-    let socket_path = get_socket_path();
+pub fn spawn_debug_viewer(runtime_dir: &Path) -> Result<()> {
+    fs::create_dir_all(runtime_dir)?;
+    let socket_path = get_socket_path(runtime_dir);
 
     // Check if socket connectable
     if UnixStream::connect(&socket_path).is_ok() {
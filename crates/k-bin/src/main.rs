@@ -6,10 +6,11 @@ mod logging;
 use anyhow::{Context, Result, anyhow};
 use args::{Cli, Commands};
 use clap::{CommandFactory, Parser};
+use k_lib::dirs::Dirs;
 use logging::{init_logging, run_socket_watcher, spawn_debug_viewer};
-use std::env;
 use std::fs;
 use std::os::unix::io::AsRawFd;
+use std::path::Path;
 use tracing::{debug, warn};
 
 fn main() -> Result<()> {
@@ -20,23 +21,39 @@ fn main() -> Result<()> {
         return run_socket_watcher(socket_path);
     }
 
+    let dirs = Dirs::resolve()?.with_overrides(
+        cli.config_dir
+            .as_deref()
+            .map(std::path::absolute)
+            .transpose()?,
+        cli.data_dir
+            .as_deref()
+            .map(std::path::absolute)
+            .transpose()?,
+    );
+
     // 2. If --debug, spawn viewer (Server) if needed
     if cli.debug {
-        spawn_debug_viewer()?;
+        spawn_debug_viewer(&dirs.runtime)?;
     }
 
     // 3. Init Logging (Client Mode)
     // If --debug was set, we hopefully spawned the viewer and socket is ready.
     // init_logging will try to connect.
-    let logging_enabled = init_logging(cli.debug)?;
+    let logging_enabled = init_logging(&dirs.runtime, cli.debug)?;
 
     // Acquire global lock (clients only)
-    let _lock_file = match acquire_lock() {
-        Ok(f) => Some(f),
-        Err(e) => {
-            warn!("Failed to acquire global lock: {}", e);
-            eprintln!("Error: {}", e);
-            return Ok(());
+    // `watch` runs indefinitely and only takes the lock while it re-cooks
+    let _lock_file = if matches!(cli.command, Some(Commands::Watch { .. })) {
+        None
+    } else {
+        match acquire_lock(&dirs.runtime) {
+            Ok(f) => Some(f),
+            Err(e) => {
+                warn!("Failed to acquire global lock: {}", e);
+                eprintln!("Error: {}", e);
+                return Ok(());
+            }
         }
     };
 
@@ -52,22 +69,18 @@ fn main() -> Result<()> {
             if logging_enabled {
                 debug!("Executing command: {:?}", cmd);
             }
-            commands::process_command(cmd)?;
+            commands::process_command(cmd, &dirs)?;
         }
     }
 
     Ok(())
 }
 
-fn acquire_lock() -> Result<fs::File> {
-    let runtime_dir = directories::BaseDirs::new()
-        .and_then(|d| d.runtime_dir().map(|p| p.to_path_buf()))
-        .unwrap_or_else(env::temp_dir);
-
+fn acquire_lock(runtime_dir: &Path) -> Result<fs::File> {
     debug!("Using runtime directory for lock: {:?}", runtime_dir);
 
     if !runtime_dir.exists() {
-        let _ = fs::create_dir_all(&runtime_dir);
+        let _ = fs::create_dir_all(runtime_dir);
     }
 
     let lock_path = runtime_dir.join("kitchn.lock");
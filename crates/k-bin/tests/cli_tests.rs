@@ -58,6 +58,19 @@ write_by_default = false
     config_home
 }
 
+/// `kitchn` isolated to `root`, including the runtime dir so the global lock
+/// does not collide between tests running in parallel, and the system
+/// cookbooks so `/etc/xdg/kitchn` does not leak in.
+fn kitchn(root: &Path, config_home: &Path) -> assert_cmd::Command {
+    let mut cmd = cargo_bin_cmd!("kitchn");
+    cmd.env("XDG_CONFIG_HOME", config_home)
+        .env("XDG_CONFIG_DIRS", root.join("xdg"))
+        .env("XDG_CACHE_HOME", root.join("cache"))
+        .env("XDG_DATA_HOME", root.join("data"))
+        .env("XDG_RUNTIME_DIR", root);
+    cmd
+}
+
 #[test]
 fn test_cli_pack_and_install() {
     let dir = tempdir().unwrap();
@@ -106,3 +119,1034 @@ fn test_cli_help() {
         .success()
         .stdout(predicate::str::contains("Usage:"));
 }
+
+#[test]
+fn test_cli_pantry_remove_restores_original() {
+    let dir = tempdir().unwrap();
+    let config_home = setup_config(dir.path());
+    let target = dir.path().join("app.conf");
+    let marker = dir.path().join("removed");
+    fs::write(&target, "original").unwrap();
+
+    let ing = dir.path().join("app.ing");
+    fs::write(
+        &ing,
+        format!(
+            r#"[package]
+name = "app"
+version = "0.1.0"
+authors = ["Test"]
+description = "Test ingredient"
+
+[[templates]]
+target = "{}"
+content = "generated"
+
+[hooks]
+on_remove = "touch {}"
+"#,
+            target.display(),
+            marker.display()
+        ),
+    )
+    .unwrap();
+
+    kitchn(dir.path(), &config_home)
+        .args(["stock", ing.to_str().unwrap()])
+        .assert()
+        .success();
+    assert_eq!(fs::read_to_string(&target).unwrap(), "generated");
+
+    kitchn(dir.path(), &config_home)
+        .args(["which", target.to_str().unwrap()])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("app").and(predicate::str::contains("v0.1.0")));
+
+    kitchn(dir.path(), &config_home)
+        .args(["pantry", "remove", "app"])
+        .assert()
+        .success();
+    assert_eq!(fs::read_to_string(&target).unwrap(), "original");
+    assert!(marker.exists());
+
+    kitchn(dir.path(), &config_home)
+        .args(["which", target.to_str().unwrap()])
+        .assert()
+        .failure();
+}
+
+#[test]
+fn test_cli_status_porcelain() {
+    let dir = tempdir().unwrap();
+    let config_home = setup_config(dir.path());
+    let theme = config_home.join("kitchn/theme.toml");
+    let write_theme = |bg: &str| {
+        fs::write(
+            &theme,
+            format!(
+                "[meta]\nname = \"test\"\n[settings]\nactive_icons = \"none\"\n[colors]\nbg = \"{}\"\n[fonts]\n",
+                bg
+            ),
+        )
+        .unwrap();
+    };
+    write_theme("#000000");
+
+    let themed = dir.path().join("themed.conf");
+    let edited = dir.path().join("edited.conf");
+    let deleted = dir.path().join("deleted.conf");
+    let ing = dir.path().join("app.ing");
+    fs::write(
+        &ing,
+        format!(
+            r#"[package]
+name = "app"
+version = "0.1.0"
+authors = ["Test"]
+description = "Test ingredient"
+
+[[templates]]
+target = "{}"
+content = "bg={{{{ colors.bg }}}}"
+
+[[templates]]
+target = "{}"
+content = "static"
+
+[[templates]]
+target = "{}"
+content = "static"
+"#,
+            themed.display(),
+            edited.display(),
+            deleted.display()
+        ),
+    )
+    .unwrap();
+
+    kitchn(dir.path(), &config_home)
+        .args(["stock", ing.to_str().unwrap()])
+        .assert()
+        .success();
+
+    kitchn(dir.path(), &config_home)
+        .args(["status", "--porcelain"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(format!(
+            "clean\tapp\t{}",
+            themed.display()
+        )));
+
+    fs::write(&edited, "by hand").unwrap();
+    fs::remove_file(&deleted).unwrap();
+    write_theme("#ffffff");
+
+    let output = kitchn(dir.path(), &config_home)
+        .args(["status", "--porcelain"])
+        .output()
+        .unwrap();
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains(&format!("outdated\tapp\t{}", themed.display())));
+    assert!(stdout.contains(&format!("modified\tapp\t{}", edited.display())));
+    assert!(stdout.contains(&format!("missing\tapp\t{}", deleted.display())));
+
+    // A template that no longer renders must not report its untouched file as clean
+    fs::write(
+        &theme,
+        "[meta]\nname = \"test\"\n[settings]\nactive_icons = \"none\"\n[colors]\n[fonts]\n",
+    )
+    .unwrap();
+    let output = kitchn(dir.path(), &config_home)
+        .args(["status", "--porcelain"])
+        .output()
+        .unwrap();
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains(&format!("unknown\tapp\t{}", themed.display())));
+    assert!(stdout.contains(&format!("modified\tapp\t{}", edited.display())));
+}
+
+#[test]
+fn test_cli_theme_use() {
+    let dir = tempdir().unwrap();
+    let config_home = setup_config(dir.path());
+    let kitchn_config = config_home.join("kitchn");
+    let theme_before = fs::read_to_string(kitchn_config.join("theme.toml")).unwrap();
+
+    fs::create_dir_all(kitchn_config.join("themes")).unwrap();
+    fs::write(
+        kitchn_config.join("themes/night.toml"),
+        "[meta]\nname = \"Night\"\n[settings]\nactive_icons = \"none\"\n[colors]\nbg = \"#101010\"\n[fonts]\n",
+    )
+    .unwrap();
+
+    let target = dir.path().join("app.conf");
+    let ing = dir.path().join("app.ing");
+    fs::write(
+        &ing,
+        format!(
+            r#"[package]
+name = "app"
+version = "0.1.0"
+authors = ["Test"]
+description = "Test ingredient"
+
+[[templates]]
+target = "{}"
+content = "bg={{{{ colors.bg | default(value='none') }}}}"
+"#,
+            target.display()
+        ),
+    )
+    .unwrap();
+
+    kitchn(dir.path(), &config_home)
+        .args(["stock", ing.to_str().unwrap()])
+        .assert()
+        .success();
+    assert_eq!(fs::read_to_string(&target).unwrap(), "bg=none");
+
+    kitchn(dir.path(), &config_home)
+        .args(["theme", "list"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("night"));
+
+    kitchn(dir.path(), &config_home)
+        .args(["theme", "use", "night"])
+        .assert()
+        .success();
+    assert_eq!(fs::read_to_string(&target).unwrap(), "bg=#101010");
+
+    kitchn(dir.path(), &config_home)
+        .args(["theme", "current"])
+        .assert()
+        .success()
+        .stdout("night\n");
+
+    // Unknown themes fail and leave the selection alone
+    kitchn(dir.path(), &config_home)
+        .args(["theme", "use", "missing"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("theme 'missing' not found"));
+    kitchn(dir.path(), &config_home)
+        .args(["theme", "current"])
+        .assert()
+        .stdout("night\n");
+
+    assert_eq!(
+        fs::read_to_string(kitchn_config.join("theme.toml")).unwrap(),
+        theme_before
+    );
+
+    // --default goes back to theme.toml
+    kitchn(dir.path(), &config_home)
+        .args(["theme", "use", "--default"])
+        .assert()
+        .success();
+    assert_eq!(fs::read_to_string(&target).unwrap(), "bg=none");
+    kitchn(dir.path(), &config_home)
+        .args(["theme", "current"])
+        .assert()
+        .stdout("test\n");
+    kitchn(dir.path(), &config_home)
+        .args(["theme", "use"])
+        .assert()
+        .failure();
+}
+
+#[test]
+fn test_cli_theme_toggle_without_variants_fails() {
+    let dir = tempdir().unwrap();
+    let config_home = setup_config(dir.path());
+
+    kitchn(dir.path(), &config_home)
+        .args(["theme", "toggle"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("has no [variants.dark]"));
+}
+
+#[test]
+fn test_cli_theme_extract_rejects_unusable_names() {
+    let dir = tempdir().unwrap();
+    let config_home = setup_config(dir.path());
+    let themes = config_home.join("kitchn/themes");
+    fs::create_dir_all(&themes).unwrap();
+    fs::write(themes.join("taken.toml"), "# mine\n").unwrap();
+
+    // The name is checked before the image is read
+    let image = dir.path().join("wallpaper.png");
+    for (name, error) in [
+        ("a/b", "'a/b' is not a valid theme name"),
+        ("taken", "theme 'taken' already exists (use --force)"),
+    ] {
+        kitchn(dir.path(), &config_home)
+            .args(["theme", "extract", "--name", name])
+            .arg(&image)
+            .assert()
+            .failure()
+            .stderr(predicate::str::contains(error));
+    }
+    assert_eq!(
+        fs::read_to_string(themes.join("taken.toml")).unwrap(),
+        "# mine\n"
+    );
+}
+
+#[test]
+fn test_cli_theme_import() {
+    let dir = tempdir().unwrap();
+    let config_home = setup_config(dir.path());
+    let scheme = dir.path().join("tokyo.conf");
+    fs::write(
+        &scheme,
+        "## name: Tokyo Night\nbackground #1a1b26\nforeground #c0caf5\ncolor1 #f7768e\ncolor4 #7aa2f7\n",
+    )
+    .unwrap();
+
+    kitchn(dir.path(), &config_home)
+        .args(["theme", "import", scheme.to_str().unwrap()])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "cursor: not in the scheme, using fg",
+        ));
+
+    let theme = config_home.join("kitchn/themes/tokyo-night.toml");
+    let content = fs::read_to_string(&theme).unwrap();
+    assert!(content.contains("name = \"Tokyo Night\""));
+    assert!(content.contains("bg = \"#1A1B26\""));
+    assert!(content.contains("primary = \"#7AA2F7\""));
+
+    // Existing themes are kept unless --force is given
+    fs::write(&theme, "# edited by hand\n").unwrap();
+    kitchn(dir.path(), &config_home)
+        .args(["theme", "import", scheme.to_str().unwrap()])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains(
+            "theme 'tokyo-night' already exists (use --force)",
+        ));
+    assert_eq!(fs::read_to_string(&theme).unwrap(), "# edited by hand\n");
+    kitchn(dir.path(), &config_home)
+        .args([
+            "theme",
+            "import",
+            "--name",
+            "../up",
+            scheme.to_str().unwrap(),
+        ])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains(
+            "'../up' is not a valid theme name",
+        ));
+
+    kitchn(dir.path(), &config_home)
+        .args(["theme", "import", "--force", scheme.to_str().unwrap()])
+        .assert()
+        .success();
+
+    kitchn(dir.path(), &config_home)
+        .args(["theme", "use", "tokyo-night"])
+        .assert()
+        .success();
+    kitchn(dir.path(), &config_home)
+        .args(["theme", "current"])
+        .assert()
+        .stdout("tokyo-night\n");
+}
+
+#[test]
+fn test_cli_theme_export() {
+    let dir = tempdir().unwrap();
+    let config_home = setup_config(dir.path());
+    fs::write(
+        config_home.join("kitchn/theme.toml"),
+        r##"
+[meta]
+name = "test"
+[settings]
+active_icons = "none"
+[colors]
+bg = "#1A1B26"
+fg = "#C0CAF5"
+red = "#F7768E"
+[fonts]
+"##,
+    )
+    .unwrap();
+
+    kitchn(dir.path(), &config_home)
+        .args(["theme", "export", "--format", "xresources"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("*.background: #1A1B26\n"))
+        .stdout(predicate::str::contains("*.color1: #F7768E\n"));
+
+    let out = dir.path().join("theme.css");
+    kitchn(dir.path(), &config_home)
+        .args(["theme", "export", "--format", "css", "-o"])
+        .arg(&out)
+        .assert()
+        .success();
+    let css = fs::read_to_string(&out).unwrap();
+    assert!(css.contains("  --color-bg: #1A1B26;\n"));
+}
+
+#[test]
+fn test_cli_theme_check() {
+    let dir = tempdir().unwrap();
+    let config_home = setup_config(dir.path());
+    fs::write(
+        config_home.join("kitchn/theme.toml"),
+        r##"
+[meta]
+name = "test"
+[settings]
+active_icons = "none"
+[colors]
+bg = "#1A1B26"
+fg = "#C0CAF5"
+blue = "#2A3A8F"
+[fonts]
+"##,
+    )
+    .unwrap();
+
+    kitchn(dir.path(), &config_home)
+        .args(["theme", "check"])
+        .assert()
+        .failure()
+        .stdout(predicate::str::is_match(r"pass\s+fg on bg").unwrap())
+        .stdout(predicate::str::is_match(r"FAIL\s+blue on bg.*try #").unwrap());
+
+    // Imports keep failing colors unless asked to fix them
+    let scheme = dir.path().join("dim.conf");
+    fs::write(
+        &scheme,
+        "background #1a1b26\nforeground #c0caf5\ncolor4 #2a3a8f\n",
+    )
+    .unwrap();
+    kitchn(dir.path(), &config_home)
+        .args(["theme", "import", "--name", "dim", scheme.to_str().unwrap()])
+        .assert()
+        .success()
+        .stdout(
+            predicate::str::contains("blue on bg").and(predicate::str::contains("--fix-contrast")),
+        );
+    let theme = config_home.join("kitchn/themes/dim.toml");
+    assert!(
+        fs::read_to_string(&theme)
+            .unwrap()
+            .contains("blue = \"#2A3A8F\"")
+    );
+
+    kitchn(dir.path(), &config_home)
+        .args([
+            "theme",
+            "import",
+            "--name",
+            "dim",
+            "--force",
+            "--fix-contrast",
+        ])
+        .arg(&scheme)
+        .assert()
+        .success();
+    assert!(
+        !fs::read_to_string(&theme)
+            .unwrap()
+            .contains("blue = \"#2A3A8F\"")
+    );
+    // The fixed theme passes, and the check says so through its exit status
+    kitchn(dir.path(), &config_home)
+        .args(["theme", "use", "dim"])
+        .assert()
+        .success();
+    kitchn(dir.path(), &config_home)
+        .args(["theme", "check"])
+        .assert()
+        .success();
+}
+
+#[test]
+fn test_cli_doctor() {
+    let dir = tempdir().unwrap();
+    let config_home = setup_config(dir.path());
+    let kitchn_config = config_home.join("kitchn");
+    let write_theme = |colors: &str| {
+        fs::write(
+            kitchn_config.join("theme.toml"),
+            format!(
+                "[meta]\nname = \"test\"\n[settings]\nactive_icons = \"none\"\n[colors]\n{}\n[fonts]\n",
+                colors
+            ),
+        )
+        .unwrap();
+    };
+    write_theme("accent = \"#ff0000\"");
+    let layout = kitchn_config.join("layout.toml");
+    let mut content = fs::read_to_string(&layout).unwrap();
+    content.push_str("[tag.levels.error]\nfg = \"accent\"\nbg = \"nope\"\n");
+    fs::write(&layout, content).unwrap();
+
+    let ing = dir.path().join("app.ing");
+    fs::write(
+        &ing,
+        format!(
+            r#"[package]
+name = "app"
+version = "0.1.0"
+authors = ["Test"]
+description = "Test ingredient"
+
+[[templates]]
+target = "{}"
+content = "{{{{ colors.accent }}}}"
+"#,
+            dir.path().join("app.conf").display()
+        ),
+    )
+    .unwrap();
+    kitchn(dir.path(), &config_home)
+        .args(["stock", ing.to_str().unwrap()])
+        .assert()
+        .success();
+    kitchn(dir.path(), &config_home)
+        .arg("bake")
+        .assert()
+        .success();
+
+    // Levels without icons only warn
+    kitchn(dir.path(), &config_home)
+        .current_dir(dir.path())
+        .arg("doctor")
+        .assert()
+        .success()
+        .stdout(
+            predicate::str::contains("app")
+                .and(predicate::str::contains("renders"))
+                .and(predicate::str::contains("fresh"))
+                .and(predicate::str::contains("no icon"))
+                .and(predicate::str::contains("icon set none"))
+                .and(predicate::str::contains("'nope' is neither"))
+                .and(predicate::str::contains("'accent' is").not()),
+        );
+    assert!(dir.path().join("logs").is_dir());
+
+    // A set further down the chain still needs every level
+    let icons = kitchn_config.join("icons.toml");
+    fs::write(&icons, "[nerdfont]\nsuccess = \"S\"\n[ascii]\n").unwrap();
+    fs::write(
+        kitchn_config.join("theme.toml"),
+        "[meta]\nname = \"test\"\n[settings]\nactive_icons = [\"nerdfont\", \"ascii\"]\n[colors]\naccent = \"#ff0000\"\nsuccess = \"#00ff00\"\n[fonts]\n",
+    )
+    .unwrap();
+    kitchn(dir.path(), &config_home)
+        .current_dir(dir.path())
+        .arg("doctor")
+        .assert()
+        .success()
+        .stdout(
+            predicate::str::contains("no ascii icon")
+                .and(predicate::str::contains(
+                    "no nerdfont icon, ascii icon, color",
+                ))
+                .and(predicate::str::contains("icon set").not()),
+        );
+    fs::write(&icons, "[nerdfont]\n[ascii]\n").unwrap();
+
+    // A scope-less override silences a preset; a missing color breaks `app`
+    fs::write(
+        kitchn_config.join("cookbook.toml"),
+        "[presets.cook_ok]\nlevel = \"summary\"\nmsg = \"done\"\ncolour = \"red\"\n",
+    )
+    .unwrap();
+    write_theme("");
+    kitchn(dir.path(), &config_home)
+        .current_dir(dir.path())
+        .arg("doctor")
+        .assert()
+        .failure()
+        .stdout(
+            predicate::str::contains("unknown key 'presets.cook_ok.colour'")
+                .and(predicate::str::contains("preset cook_ok"))
+                .and(predicate::str::contains("no scope"))
+                .and(predicate::str::contains("changed since it was baked")),
+        )
+        .stderr(predicate::str::contains("checks failed: 2"));
+}
+
+#[test]
+fn test_cli_schema() {
+    let dir = tempdir().unwrap();
+    // Works without a cookbook, so it can help write one
+    let output = kitchn(dir.path(), &dir.path().join("config"))
+        .args(["schema", "theme"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let schema: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(schema["title"], "ThemeConfig");
+    assert!(schema["properties"]["colors"].is_object());
+
+    kitchn(dir.path(), &dir.path().join("config"))
+        .args(["schema", "pastry"])
+        .assert()
+        .failure();
+}
+
+#[test]
+fn test_cli_host_and_profile_overlays() {
+    let dir = tempdir().unwrap();
+    let config_home = setup_config(dir.path());
+    let kitchn_config = config_home.join("kitchn");
+    fs::write(
+        kitchn_config.join("theme.toml"),
+        "[meta]\nname = \"test\"\n[settings]\nactive_icons = \"none\"\n[colors]\nprimary = \"#000000\"\nfg = \"@primary\"\n[fonts]\n",
+    )
+    .unwrap();
+    fs::create_dir_all(kitchn_config.join("hosts")).unwrap();
+    fs::write(
+        kitchn_config.join("hosts/desk.toml"),
+        "[theme.colors]\nprimary = \"#112233\"\n",
+    )
+    .unwrap();
+    fs::create_dir_all(kitchn_config.join("profiles")).unwrap();
+    fs::write(
+        kitchn_config.join("profiles/demo.toml"),
+        "[theme.colors]\nprimary = \"#445566\"\n",
+    )
+    .unwrap();
+
+    let export = |host: &str, profile: Option<&str>| {
+        let mut cmd = kitchn(dir.path(), &config_home);
+        cmd.env("KITCHN_HOST", host)
+            .env_remove("KITCHN_PROFILE")
+            .args(["theme", "export", "--format", "json"]);
+        if let Some(profile) = profile {
+            cmd.env("KITCHN_PROFILE", profile);
+        }
+        cmd.assert()
+    };
+
+    export("other", None)
+        .success()
+        .stdout(predicate::str::contains("\"fg\": \"#000000\""));
+    // References follow the overlay
+    export("desk", None)
+        .success()
+        .stdout(predicate::str::contains("\"fg\": \"#112233\""));
+    // The profile wins over the host, even with a baked cache
+    kitchn(dir.path(), &config_home)
+        .env("KITCHN_HOST", "desk")
+        .arg("bake")
+        .assert()
+        .success();
+    export("desk", Some("demo"))
+        .success()
+        .stdout(predicate::str::contains("\"fg\": \"#445566\""));
+    export("desk", Some("nope"))
+        .failure()
+        .stderr(predicate::str::contains("Profile 'nope' not found"));
+}
+
+#[test]
+fn test_cli_env_overrides_skip_the_cache() {
+    let dir = tempdir().unwrap();
+    let config_home = setup_config(dir.path());
+    fs::write(
+        config_home.join("kitchn/theme.toml"),
+        "[meta]\nname = \"test\"\n[settings]\nactive_icons = \"none\"\n[colors]\nprimary = \"#000000\"\n[fonts]\n",
+    )
+    .unwrap();
+
+    // Baked with an override in the environment...
+    kitchn(dir.path(), &config_home)
+        .env("KITCHN__THEME__COLORS__PRIMARY", "#ff0000")
+        .arg("bake")
+        .assert()
+        .success();
+    kitchn(dir.path(), &config_home)
+        .env("KITCHN__THEME__COLORS__PRIMARY", "#ff0000")
+        .args(["theme", "export", "--format", "json"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("\"primary\": \"#FF0000\""));
+    // ...which does not end up in pastry.bin
+    kitchn(dir.path(), &config_home)
+        .args(["theme", "export", "--format", "json"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("\"primary\": \"#000000\""));
+
+    kitchn(dir.path(), &config_home)
+        .env("KITCHN__LAYOUT__LOGGING__WRITE_BY_DEFAULT", "maybe")
+        .args(["theme", "current"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains(
+            "KITCHN__LAYOUT__LOGGING__WRITE_BY_DEFAULT",
+        ));
+}
+
+#[test]
+fn test_cli_config_and_data_dir_isolate_instances() {
+    let dir = tempdir().unwrap();
+    let config_home = setup_config(dir.path());
+    let with_primary = |config_dir: &Path, primary: &str| {
+        fs::create_dir_all(config_dir).unwrap();
+        for file in ["icons.toml", "layout.toml"] {
+            fs::copy(config_home.join("kitchn").join(file), config_dir.join(file)).unwrap();
+        }
+        fs::write(
+            config_dir.join("theme.toml"),
+            format!(
+                "[meta]\nname = \"test\"\n[settings]\nactive_icons = \"none\"\n[colors]\nprimary = \"{}\"\n[fonts]\n",
+                primary
+            ),
+        )
+        .unwrap();
+    };
+    let a = dir.path().join("a");
+    let b = dir.path().join("b");
+    with_primary(&a, "#aa0000");
+    with_primary(&b, "#00bb00");
+    // A system cookbook the flags leave out
+    let system = dir.path().join("xdg/kitchn");
+    fs::create_dir_all(&system).unwrap();
+    fs::write(
+        system.join("theme.toml"),
+        "[colors]\nsecondary = \"#123456\"\n",
+    )
+    .unwrap();
+
+    // Both bake into the same cache dir without reading each other's cookbook
+    for config_dir in [&a, &b] {
+        kitchn(dir.path(), &config_home)
+            .arg("--config-dir")
+            .arg(config_dir)
+            .arg("bake")
+            .assert()
+            .success();
+    }
+    for (config_dir, primary) in [(&a, "#AA0000"), (&b, "#00BB00")] {
+        kitchn(dir.path(), &config_home)
+            .arg("--config-dir")
+            .arg(config_dir)
+            .args(["theme", "export", "--format", "json"])
+            .assert()
+            .success()
+            .stdout(predicate::str::contains(format!(
+                "\"primary\": \"{}\"",
+                primary
+            )))
+            .stdout(predicate::str::contains("secondary").not());
+    }
+
+    let data = dir.path().join("sandbox-data");
+    kitchn(dir.path(), &config_home)
+        .arg("--data-dir")
+        .arg(&data)
+        .arg("cook")
+        .assert()
+        .success();
+    assert!(data.join("pantry.db").exists());
+    assert!(!dir.path().join("data/kitchn/pantry.db").exists());
+    // ...behind a lock of its own, not the default instance's
+    assert!(!dir.path().join("kitchn.lock").exists());
+
+    // KITCHN_HOME moves everything at once
+    let home = dir.path().join("home");
+    with_primary(&home.join("config"), "#0000cc");
+    kitchn(dir.path(), &config_home)
+        .env("KITCHN_HOME", &home)
+        .args(["theme", "export", "--format", "json"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("\"primary\": \"#0000CC\""));
+    kitchn(dir.path(), &config_home)
+        .env("KITCHN_HOME", &home)
+        .arg("cook")
+        .assert()
+        .success();
+    assert!(home.join("data/pantry.db").exists());
+    assert!(home.join("run/kitchn.lock").exists());
+}
+
+#[test]
+fn test_cli_includes_expand_env_and_report_cycles() {
+    let dir = tempdir().unwrap();
+    let config_home = setup_config(dir.path());
+    let kitchn_config = config_home.join("kitchn");
+    let shared = dir.path().join("shared");
+    fs::create_dir_all(&shared).unwrap();
+    fs::write(
+        shared.join("palette.toml"),
+        "[colors]\nprimary = \"#123456\"\n",
+    )
+    .unwrap();
+    fs::write(
+        kitchn_config.join("theme.toml"),
+        "include = [\"$KITCHN_SHARED/palette.toml\", \"?local.toml\"]\n[meta]\nname = \"test\"\n[settings]\nactive_icons = \"none\"\n[colors]\n[fonts]\n",
+    )
+    .unwrap();
+
+    kitchn(dir.path(), &config_home)
+        .env("KITCHN_SHARED", &shared)
+        .args(["theme", "export", "--format", "json"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("\"primary\": \"#123456\""));
+    // The missing optional include is not a problem
+    kitchn(dir.path(), &config_home)
+        .env("KITCHN_SHARED", &shared)
+        .arg("doctor")
+        .assert()
+        .stdout(predicate::str::contains("local.toml").not());
+
+    fs::write(shared.join("palette.toml"), "include = [\"more.toml\"]\n").unwrap();
+    fs::write(shared.join("more.toml"), "include = [\"palette.toml\"]\n").unwrap();
+    kitchn(dir.path(), &config_home)
+        .env("KITCHN_SHARED", &shared)
+        .args(["theme", "current"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("Include cycle: theme.toml -> "))
+        .stderr(predicate::str::contains("shared/more.toml -> "));
+}
+
+#[test]
+fn test_cli_cache_follows_includes_and_overlays() {
+    let dir = tempdir().unwrap();
+    let config_home = setup_config(dir.path());
+    let kitchn_config = config_home.join("kitchn");
+    fs::write(
+        kitchn_config.join("theme.toml"),
+        "include = [\"palette.toml\"]\n[meta]\nname = \"test\"\n[settings]\nactive_icons = \"none\"\n[colors]\n[fonts]\n",
+    )
+    .unwrap();
+    fs::write(
+        kitchn_config.join("palette.toml"),
+        "[colors]\nprimary = \"#000000\"\n",
+    )
+    .unwrap();
+    fs::create_dir_all(kitchn_config.join("hosts")).unwrap();
+    fs::write(
+        kitchn_config.join("hosts/desk.toml"),
+        "[theme.colors]\nprimary = \"#112233\"\n",
+    )
+    .unwrap();
+    let export = |host: &str| {
+        kitchn(dir.path(), &config_home)
+            .env("KITCHN_HOST", host)
+            .args(["theme", "export", "--format", "json"])
+            .assert()
+            .success()
+    };
+
+    kitchn(dir.path(), &config_home)
+        .env("KITCHN_HOST", "other")
+        .arg("bake")
+        .assert()
+        .success();
+    export("other").stdout(predicate::str::contains("\"primary\": \"#000000\""));
+    // Baked for another host
+    export("desk").stdout(predicate::str::contains("\"primary\": \"#112233\""));
+
+    // An edit to an included file makes the cache stale
+    fs::write(
+        kitchn_config.join("palette.toml"),
+        "[colors]\nprimary = \"#ffffff\"\n",
+    )
+    .unwrap();
+    export("other").stdout(predicate::str::contains("\"primary\": \"#FFFFFF\""));
+    kitchn(dir.path(), &config_home)
+        .env("KITCHN_HOST", "other")
+        .arg("doctor")
+        .assert()
+        .stdout(predicate::str::contains("changed since it was baked"));
+}
+
+#[test]
+fn test_cli_rejects_headerless_cache() {
+    let dir = tempdir().unwrap();
+    let config_home = setup_config(dir.path());
+    kitchn(dir.path(), &config_home)
+        .arg("bake")
+        .assert()
+        .success();
+    let bin = fs::read_dir(dir.path().join("cache/kitchn"))
+        .unwrap()
+        .next()
+        .unwrap()
+        .unwrap()
+        .path()
+        .join("pastry.bin");
+    assert!(fs::read(&bin).unwrap().starts_with(b"KITCHN\0C"));
+
+    // A pastry.bin from before the header: not decoded, the TOMLs are used
+    fs::write(&bin, b"\x04test\x00\x00").unwrap();
+    kitchn(dir.path(), &config_home)
+        .args(["theme", "current"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("test"));
+    kitchn(dir.path(), &config_home)
+        .arg("doctor")
+        .assert()
+        .stdout(predicate::str::contains("pastry.bin has no header"));
+}
+
+#[test]
+fn test_cli_system_cookbook_is_layered_under_the_user() {
+    let dir = tempdir().unwrap();
+    let config_home = setup_config(dir.path());
+    let kitchn_config = config_home.join("kitchn");
+    let base = dir.path().join("xdg/base/kitchn");
+    let corp = dir.path().join("xdg/corp/kitchn");
+    fs::create_dir_all(&base).unwrap();
+    fs::create_dir_all(&corp).unwrap();
+    fs::write(
+        base.join("theme.toml"),
+        "[meta]\nname = \"base\"\n[settings]\nactive_icons = \"none\"\n[colors]\nprimary = \"#000000\"\nbg = \"#111111\"\n[fonts]\n",
+    )
+    .unwrap();
+    fs::write(corp.join("theme.toml"), "[colors]\nprimary = \"#222222\"\n").unwrap();
+    // The user overrides one color and ships no icons of their own
+    fs::write(
+        kitchn_config.join("theme.toml"),
+        "[colors]\nbg = \"#333333\"\n",
+    )
+    .unwrap();
+    fs::rename(kitchn_config.join("icons.toml"), base.join("icons.toml")).unwrap();
+
+    let system_dirs =
+        std::env::join_paths([dir.path().join("xdg/corp"), dir.path().join("xdg/base")]).unwrap();
+    let run = |args: &[&str]| {
+        kitchn(dir.path(), &config_home)
+            .env("XDG_CONFIG_DIRS", &system_dirs)
+            .args(args)
+            .assert()
+    };
+    let export = || run(&["theme", "export", "--format", "json"]).success();
+
+    export()
+        .stdout(predicate::str::contains("\"primary\": \"#222222\""))
+        .stdout(predicate::str::contains("\"bg\": \"#333333\""));
+
+    // A baked cookbook follows the system files too
+    run(&["bake"])
+        .success()
+        .stdout(predicate::str::contains("xdg/base/kitchn/icons.toml"));
+    fs::write(corp.join("theme.toml"), "[colors]\nprimary = \"#444444\"\n").unwrap();
+    export().stdout(predicate::str::contains("\"primary\": \"#444444\""));
+
+    // Unknown keys are reported against the system file that sets them
+    fs::write(corp.join("theme.toml"), "colour = \"#444444\"\n").unwrap();
+    run(&["doctor"]).stdout(
+        predicate::str::contains("unknown key 'colour'")
+            .and(predicate::str::contains("xdg/corp/kitchn/theme.toml")),
+    );
+
+    // An isolated instance leaves the system cookbook out
+    let home_config = dir.path().join("home/config");
+    fs::create_dir_all(&home_config).unwrap();
+    fs::copy(
+        kitchn_config.join("layout.toml"),
+        home_config.join("layout.toml"),
+    )
+    .unwrap();
+    fs::write(home_config.join("icons.toml"), "[ascii]\n").unwrap();
+    fs::write(
+        home_config.join("theme.toml"),
+        "[meta]\nname = \"home\"\n[settings]\nactive_icons = \"none\"\n[colors]\nbg = \"#555555\"\n[fonts]\n",
+    )
+    .unwrap();
+    kitchn(dir.path(), &config_home)
+        .env("XDG_CONFIG_DIRS", &system_dirs)
+        .env("KITCHN_HOME", dir.path().join("home"))
+        .args(["theme", "export", "--format", "json"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("\"bg\": \"#555555\""))
+        .stdout(predicate::str::contains("primary").not());
+}
+
+#[test]
+fn test_cli_config_get_and_set() {
+    let dir = tempdir().unwrap();
+    let config_home = setup_config(dir.path());
+    let layout = config_home.join("kitchn/layout.toml");
+    let original = fs::read_to_string(&layout).unwrap();
+    fs::write(
+        &layout,
+        original.replace("min_width = 0", "# Pad tags\nmin_width = 0 # columns"),
+    )
+    .unwrap();
+
+    kitchn(dir.path(), &config_home)
+        .args(["config", "set", "layout.tag.min_width", "9"])
+        .assert()
+        .success();
+    assert!(
+        fs::read_to_string(&layout)
+            .unwrap()
+            .contains("# Pad tags\nmin_width = 9 # columns")
+    );
+    kitchn(dir.path(), &config_home)
+        .args(["config", "get", "layout.tag.min_width"])
+        .assert()
+        .success()
+        .stdout("9\n");
+    // Re-baked with the new value
+    kitchn(dir.path(), &config_home)
+        .arg("doctor")
+        .assert()
+        .stdout(predicate::str::contains("fresh"));
+
+    kitchn(dir.path(), &config_home)
+        .args(["config", "set", "layout.tag.min_width", "wide"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("expected an integer, got 'wide'"));
+    kitchn(dir.path(), &config_home)
+        .args(["config", "get", "layout.tag.nope"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("'layout.tag.nope' is not set"));
+
+    // The file changes, but the overrides on top of it still win
+    let hosts = config_home.join("kitchn/hosts");
+    fs::create_dir_all(&hosts).unwrap();
+    fs::write(hosts.join("box.toml"), "[layout.tag]\nmin_width = 4\n").unwrap();
+    kitchn(dir.path(), &config_home)
+        .env("KITCHN_HOST", "box")
+        .args(["config", "set", "layout.tag.min_width", "5"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("still comes from hosts/box.toml"));
+    assert!(
+        fs::read_to_string(&layout)
+            .unwrap()
+            .contains("min_width = 5")
+    );
+    kitchn(dir.path(), &config_home)
+        .env("KITCHN_HOST", "box")
+        .env("KITCHN__LAYOUT__TAG__MIN_WIDTH", "3")
+        .args(["config", "set", "layout.tag.min_width", "6"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "still comes from KITCHN__LAYOUT__TAG__MIN_WIDTH",
+        ));
+    kitchn(dir.path(), &config_home)
+        .args(["config", "set", "layout.tag.min_width", "7"])
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("still comes from").not());
+}
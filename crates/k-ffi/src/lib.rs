@@ -185,6 +185,53 @@ pub unsafe extern "C" fn kitchn_log_preset(
     0
 }
 
+// --- Theme API ---
+
+/// A theme color; `a` is 255 for opaque colors
+#[repr(C)]
+pub struct KitchnColor {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+    pub a: u8,
+}
+
+#[no_mangle]
+/// Writes the active theme's color `key` (e.g. "primary") to `out`.
+/// Returns 1 if the theme has no such color.
+/// # Safety
+pub unsafe extern "C" fn kitchn_get_color(
+    ctx: *mut KitchnContext,
+    key: *const c_char,
+    out: *mut KitchnColor,
+) -> c_int {
+    if ctx.is_null() || key.is_null() || out.is_null() {
+        return 1;
+    }
+
+    let context = unsafe { &*ctx };
+    context.clear_error();
+
+    let key = unsafe { CStr::from_ptr(key).to_string_lossy() };
+    match context.config.theme.colors.get(key.as_ref()) {
+        Some(color) => {
+            unsafe {
+                *out = KitchnColor {
+                    r: color.r,
+                    g: color.g,
+                    b: color.b,
+                    a: color.a,
+                };
+            }
+            0
+        }
+        None => {
+            context.set_error(format!("Color '{}' not found in theme", key));
+            1
+        }
+    }
+}
+
 #[no_mangle]
 /// # Safety
 pub unsafe extern "C" fn kitchn_pack(
@@ -1,5 +1,6 @@
 use criterion::{Criterion, black_box, criterion_group, criterion_main};
 use k_lib::config::Cookbook;
+use k_lib::dirs::Dirs;
 use std::fs;
 use tempfile::tempdir;
 
@@ -70,6 +71,10 @@ fn benchmark_config_serialization(c: &mut Criterion) {
     // Let's create a dummy one by loading from an empty dir (or our dummy dir)
     let dir = tempdir().unwrap();
     let config_dir = dir.path();
+    let dirs = Dirs {
+        config: config_dir.to_path_buf(),
+        ..Dirs::under(config_dir)
+    };
     fs::create_dir_all(config_dir).unwrap();
 
     // Mock files to make it valid
@@ -138,7 +143,7 @@ fn benchmark_config_serialization(c: &mut Criterion) {
 
     c.bench_function("config_save_binary", |b| {
         b.iter(|| {
-            let _ = config.save_binary(black_box(&bin_path));
+            let _ = config.save_binary(black_box(&bin_path), &dirs);
         })
     });
 }
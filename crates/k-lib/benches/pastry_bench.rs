@@ -0,0 +1,250 @@
+use criterion::{Criterion, black_box, criterion_group, criterion_main};
+use k_lib::config::{ArchivedCookbook, Cookbook, Provenance};
+use k_lib::dirs::Dirs;
+use k_lib::logger::LogStyle;
+use k_lib::pastry::Pastry;
+use rkyv::rancor;
+use std::fs;
+use tempfile::tempdir;
+
+/// `pastry.bin` as the baseline wrote it (the whole cookbook as a bare
+/// bincode payload), generated by that release from the TOMLs in [`setup`].
+const BASELINE_CACHE: &[u8] = include_bytes!("fixtures/pastry-baseline.bin");
+
+/// The baseline cookbook types the fixture decodes into. bincode is not
+/// self-describing, so every field is spelled out even where only the
+/// lookups below read it; structs nobody reads are decoded as tuples.
+mod baseline {
+    #![allow(dead_code)]
+
+    use serde::Deserialize;
+    use std::collections::HashMap;
+
+    #[derive(Deserialize)]
+    pub struct Cookbook {
+        pub theme: Theme,
+        pub icons: Icons,
+        layout: Layout,
+        pub dictionary: Dictionary,
+    }
+
+    #[derive(Deserialize)]
+    pub struct Theme {
+        meta: (String,),
+        settings: (String,),
+        pub colors: HashMap<String, String>,
+        fonts: HashMap<String, String>,
+        include: Option<Vec<String>>,
+    }
+
+    #[derive(Deserialize)]
+    pub struct Icons {
+        nerdfont: HashMap<String, String>,
+        ascii: HashMap<String, String>,
+        include: Option<Vec<String>>,
+    }
+
+    #[derive(Deserialize)]
+    struct Layout {
+        tag: (String, String, String, usize, String),
+        labels: HashMap<String, String>,
+        structure: (String, String),
+        logging: (String, String, String, String, bool, String),
+        include: Option<Vec<String>>,
+    }
+
+    #[derive(Deserialize)]
+    pub struct Dictionary {
+        pub presets: HashMap<String, Preset>,
+        include: Option<Vec<String>>,
+    }
+
+    #[derive(Deserialize)]
+    pub struct Preset {
+        pub level: String,
+        scope: Option<String>,
+        msg: String,
+    }
+
+    impl Cookbook {
+        pub fn icon(&self, level: &str) -> Option<&str> {
+            let set = match self.theme.settings.0.as_str() {
+                "nerdfont" => &self.icons.nerdfont,
+                _ => &self.icons.ascii,
+            };
+            set.get(level).map(String::as_str)
+        }
+    }
+}
+
+fn setup(config_dir: &std::path::Path) {
+    fs::write(
+        config_dir.join("theme.toml"),
+        r##"
+    [meta]
+    name = "bench_theme"
+    [settings]
+    active_icons = "nerdfont"
+    [colors]
+    bg = "#282a36"
+    fg = "#f8f8f2"
+    primary = "#ff79c6"
+    success = "#50fa7b"
+    [fonts]
+    ui = "Sans"
+    "##,
+    )
+    .unwrap();
+
+    fs::write(
+        config_dir.join("icons.toml"),
+        r##"
+    [nerdfont]
+    success = ""
+    [ascii]
+    success = "*"
+    "##,
+    )
+    .unwrap();
+
+    fs::write(
+        config_dir.join("layout.toml"),
+        r##"
+    [tag]
+    prefix = "["
+    suffix = "]"
+    transform = "uppercase"
+    min_width = 10
+    alignment = "left"
+    [labels]
+    [structure]
+    terminal = "{tag} {msg}"
+    file = "{tag} {msg}"
+    [logging]
+    base_dir = "logs"
+    path_structure = "{app}.log"
+    filename_structure = "log"
+    timestamp_format = "%Y"
+    write_by_default = false
+    app_name = "benchmark"
+    "##,
+    )
+    .unwrap();
+
+    fs::write(
+        config_dir.join("cookbook.toml"),
+        r##"
+    [presets.bench_ok]
+    level = "success"
+    msg = "benchmark passed"
+    "##,
+    )
+    .unwrap();
+}
+
+/// What one `kitchn-log` call does with the cache bytes once they are in
+/// memory: decode (or validate) them, look up a preset and style its level.
+fn benchmark_cache_decode(c: &mut Criterion) {
+    let dir = tempdir().unwrap();
+    let config_dir = dir.path();
+    setup(config_dir);
+
+    c.bench_function("decode_bincode_baseline", |b| {
+        b.iter(|| {
+            let (config, _): (baseline::Cookbook, usize) = bincode::serde::decode_from_slice(
+                black_box(BASELINE_CACHE),
+                bincode::config::standard(),
+            )
+            .unwrap();
+            let preset = &config.dictionary.presets["bench_ok"];
+            black_box((
+                config.icon(&preset.level).is_some(),
+                config.theme.colors.get(&preset.level).cloned(),
+            ));
+        })
+    });
+
+    let config = Cookbook::load_from_dir(config_dir).unwrap();
+    let archive = rkyv::to_bytes::<rancor::Error>(&config).unwrap();
+    c.bench_function("decode_rkyv_validated", |b| {
+        b.iter(|| {
+            let cookbook =
+                rkyv::access::<ArchivedCookbook, rancor::Error>(black_box(&archive)).unwrap();
+            let preset = &cookbook.dictionary.presets.get("bench_ok").unwrap();
+            black_box((
+                cookbook.icon(&preset.level).is_some(),
+                cookbook.color(&preset.level),
+            ));
+        })
+    });
+}
+
+/// Opening `pastry.bin` from disk. The freshness check that comes with it,
+/// hashing every source file, is measured on its own as well.
+fn benchmark_cache_open(c: &mut Criterion) {
+    let dir = tempdir().unwrap();
+    let config_dir = dir.path();
+    setup(config_dir);
+    let dirs = Dirs {
+        config: config_dir.to_path_buf(),
+        ..Dirs::under(config_dir)
+    };
+
+    let bin_path = config_dir.join("pastry.bin");
+    let config = Cookbook::load_from_dir(config_dir).unwrap();
+    config.save_binary(&bin_path, &dirs).unwrap();
+
+    c.bench_function("open_pastry", |b| {
+        b.iter(|| {
+            let pastry = Pastry::open(black_box(&bin_path), &dirs).unwrap().unwrap();
+            let preset = pastry.preset("bench_ok").unwrap();
+            let cookbook = pastry.cookbook();
+            black_box((
+                cookbook.icon(&preset.level).is_some(),
+                cookbook.color(&preset.level),
+            ));
+        })
+    });
+
+    let provenance = Provenance::record(&dirs).unwrap();
+    c.bench_function("open_provenance_check", |b| {
+        b.iter(|| assert!(black_box(&provenance).is_current(&dirs)))
+    });
+}
+
+/// Lookups alone, once the cache is open: the archive is read in place.
+fn benchmark_style_lookup(c: &mut Criterion) {
+    let dir = tempdir().unwrap();
+    let config_dir = dir.path();
+    setup(config_dir);
+    let dirs = Dirs {
+        config: config_dir.to_path_buf(),
+        ..Dirs::under(config_dir)
+    };
+
+    let bin_path = config_dir.join("pastry.bin");
+    let config = Cookbook::load_from_dir(config_dir).unwrap();
+    config.save_binary(&bin_path, &dirs).unwrap();
+    let pastry = Pastry::open(&bin_path, &dirs).unwrap().unwrap();
+    let archived = pastry.cookbook();
+
+    c.bench_function("lookup_owned", |b| {
+        b.iter(|| black_box((config.icon(black_box("success")), config.color("success"))))
+    });
+    c.bench_function("lookup_archived", |b| {
+        b.iter(|| {
+            black_box((
+                archived.icon(black_box("success")),
+                archived.color("success"),
+            ))
+        })
+    });
+}
+
+criterion_group!(
+    benches,
+    benchmark_cache_decode,
+    benchmark_cache_open,
+    benchmark_style_lookup
+);
+criterion_main!(benches);
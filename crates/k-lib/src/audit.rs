@@ -0,0 +1,237 @@
+use crate::color::{ANSI_KEYS, Palette, Rgb};
+
+/// WCAG 2.x AA minimum for body text.
+pub const TEXT: f64 = 4.5;
+/// WCAG 2.x AA minimum for large text and UI elements; used for muted text
+/// such as comments and borders.
+pub const MUTED: f64 = 3.0;
+
+const SEMANTIC_KEYS: [&str; 7] = [
+    "primary",
+    "secondary",
+    "success",
+    "error",
+    "warn",
+    "info",
+    "orange",
+];
+
+/// A color pair kitchn assumes is readable: `fg` drawn on `bg`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Pair {
+    pub fg: &'static str,
+    pub bg: &'static str,
+    pub min: f64,
+}
+
+/// The outcome of checking one [`Pair`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct Check {
+    pub pair: Pair,
+    pub ratio: f64,
+    /// For a failing pair, the nearest `fg` (same hue and saturation) that
+    /// reaches the minimum, if there is one.
+    pub fix: Option<Rgb>,
+}
+
+impl Check {
+    pub fn passed(&self) -> bool {
+        self.ratio >= self.pair.min
+    }
+}
+
+/// The pairs to check for `palette`: `fg`/`bg`, `selection_fg`/`selection_bg`,
+/// the semantic colors on `bg` and the ANSI colors on `bg`.
+///
+/// The ANSI slot that is meant to sit close to the background (`black` on a
+/// dark theme, `white`/`bright_white` on a light one) is not a text color and
+/// is skipped; `bright_black` only has to meet [`MUTED`].
+pub fn pairs(palette: &Palette) -> Vec<Pair> {
+    let pair = |fg, bg, min| Pair { fg, bg, min };
+    let mut pairs = vec![
+        pair("fg", "bg", TEXT),
+        pair("selection_fg", "selection_bg", TEXT),
+    ];
+    pairs.extend(SEMANTIC_KEYS.iter().map(|key| pair(key, "bg", TEXT)));
+
+    let dark = palette
+        .get("bg")
+        .is_some_and(|bg| bg.contrast(Rgb::new(255, 255, 255)) > bg.contrast(Rgb::new(0, 0, 0)));
+    for key in ANSI_KEYS {
+        let skip = if dark {
+            key == "black"
+        } else {
+            key == "white" || key == "bright_white"
+        };
+        if skip {
+            continue;
+        }
+        let min = if key == "bright_black" { MUTED } else { TEXT };
+        pairs.push(pair(key, "bg", min));
+    }
+    pairs
+}
+
+/// Checks every pair from [`pairs`] whose colors are both in `palette`.
+pub fn check(palette: &Palette) -> Vec<Check> {
+    pairs(palette)
+        .into_iter()
+        .filter_map(|pair| {
+            let fg = palette.get(pair.fg)?;
+            let bg = palette.get(pair.bg)?;
+            let ratio = fg.contrast(bg);
+            let fix = (ratio < pair.min).then(|| adjust(fg, bg, pair.min));
+            Some(Check {
+                pair,
+                ratio,
+                fix: fix.filter(|c| c.contrast(bg) >= pair.min),
+            })
+        })
+        .collect()
+}
+
+/// Replaces every failing `fg` in `palette` with its suggested fix and returns
+/// the checks that failed. Backgrounds are never changed.
+pub fn enforce(palette: &mut Palette) -> Vec<Check> {
+    let failed: Vec<Check> = check(palette).into_iter().filter(|c| !c.passed()).collect();
+    for check in &failed {
+        if let Some(fix) = check.fix {
+            palette.colors.insert(check.pair.fg, fix);
+        }
+    }
+    failed
+}
+
+/// The color closest in lightness to `color` (same hue and saturation) with
+/// at least `min` contrast against `bg`. When no lightness gets there, the
+/// best reachable contrast wins.
+pub fn adjust(color: Rgb, bg: Rgb, min: f64) -> Rgb {
+    if color.contrast(bg) >= min {
+        return color;
+    }
+    let (h, s, l) = color.to_hsl();
+    let mut best = color;
+    for step in 1..=100 {
+        let delta = step as f64 / 100.0;
+        for l in [l + delta, l - delta] {
+            if !(0.0..=1.0).contains(&l) {
+                continue;
+            }
+            let candidate = Rgb::from_hsl(h, s, l);
+            if candidate.contrast(bg) >= min {
+                return candidate;
+            }
+            if candidate.contrast(bg) > best.contrast(bg) {
+                best = candidate;
+            }
+        }
+    }
+    best
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color::THEME_KEYS;
+
+    fn palette(colors: &[(&'static str, &str)]) -> Palette {
+        Palette {
+            colors: colors
+                .iter()
+                .map(|(k, v)| (*k, Rgb::from_hex(v).unwrap()))
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn test_check_reports_failures_with_fix() {
+        let palette = palette(&[
+            ("bg", "#161925"),
+            ("fg", "#F8F8F2"),
+            ("blue", "#3A3F8F"),
+            ("bright_black", "#656B84"),
+            ("black", "#1C1F2B"),
+        ]);
+        let checks = check(&palette);
+
+        // Pairs with a missing color are not checked; black is the bg shade
+        let names: Vec<_> = checks.iter().map(|c| c.pair.fg).collect();
+        assert_eq!(names, ["fg", "blue", "bright_black"]);
+
+        assert!(checks[0].passed());
+        assert!(checks[0].fix.is_none());
+
+        let blue = &checks[1];
+        assert!(!blue.passed());
+        let fix = blue.fix.unwrap();
+        assert!(fix.contrast(palette.get("bg").unwrap()) >= TEXT);
+        // Lightened, keeping the hue
+        assert!(fix.to_hsl().2 > Rgb::from_hex("#3A3F8F").unwrap().to_hsl().2);
+        assert!((fix.to_hsl().0 - Rgb::from_hex("#3A3F8F").unwrap().to_hsl().0).abs() < 2.0);
+
+        // Muted text only needs 3:1
+        assert!(checks[2].passed());
+    }
+
+    #[test]
+    fn test_light_theme_darkens_and_skips_white() {
+        let palette = palette(&[
+            ("bg", "#FAFAFA"),
+            ("yellow", "#E5C07B"),
+            ("white", "#F0F0F0"),
+            ("black", "#101010"),
+        ]);
+        let checks = check(&palette);
+        let names: Vec<_> = checks.iter().map(|c| c.pair.fg).collect();
+        assert_eq!(names, ["black", "yellow"]);
+
+        let fix = checks[1].fix.unwrap();
+        assert!(fix.to_hsl().2 < Rgb::from_hex("#E5C07B").unwrap().to_hsl().2);
+        assert!(fix.contrast(palette.get("bg").unwrap()) >= TEXT);
+    }
+
+    #[test]
+    fn test_enforce() {
+        let mut palette = palette(&[
+            ("bg", "#282A36"),
+            ("fg", "#44475A"),
+            ("selection_bg", "#44475A"),
+            ("selection_fg", "#50557A"),
+            ("primary", "#BD93F9"),
+        ]);
+        let failed = enforce(&mut palette);
+        let names: Vec<_> = failed.iter().map(|c| c.pair.fg).collect();
+        assert_eq!(names, ["fg", "selection_fg"]);
+        assert_eq!(palette.get("primary"), Rgb::from_hex("#BD93F9"));
+        assert_eq!(palette.get("bg"), Rgb::from_hex("#282A36"));
+        assert!(check(&palette).iter().all(Check::passed));
+    }
+
+    #[test]
+    fn test_adjust_unreachable_minimum() {
+        // Nothing reaches 7:1 on mid gray; the best is one of the extremes
+        let bg = Rgb::from_hex("#777777").unwrap();
+        let best = adjust(Rgb::from_hex("#808080").unwrap(), bg, 7.0);
+        assert!(best.contrast(bg) < 7.0);
+        assert!(best == Rgb::new(0, 0, 0) || best == Rgb::new(255, 255, 255));
+    }
+
+    #[test]
+    fn test_pairs_cover_theme_keys() {
+        let palette = palette(&[("bg", "#000000")]);
+        let covered: Vec<_> = pairs(&palette).iter().map(|p| p.fg).collect();
+        for (_, keys) in THEME_KEYS {
+            for key in *keys {
+                let exempt = [
+                    "bg",
+                    "cursor",
+                    "selection_bg",
+                    "tabs",
+                    "tabs_active",
+                    "black",
+                ];
+                assert!(covered.contains(key) || exempt.contains(key), "{}", key);
+            }
+        }
+    }
+}
@@ -0,0 +1,971 @@
+use crate::config::ThemeConfig;
+use schemars::{JsonSchema, Schema, SchemaGenerator, json_schema};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::str::FromStr;
+use thiserror::Error;
+
+/// An sRGB color with 8 bits per channel.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Rgb {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+}
+
+impl Rgb {
+    pub const fn new(r: u8, g: u8, b: u8) -> Self {
+        Rgb { r, g, b }
+    }
+
+    /// Parses `#RRGGBB` (the leading `#` is optional).
+    pub fn from_hex(hex: &str) -> Option<Self> {
+        let hex = hex.trim_start_matches('#');
+        if hex.len() != 6 || !hex.is_ascii() {
+            return None;
+        }
+        let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).ok();
+        Some(Rgb::new(channel(0)?, channel(2)?, channel(4)?))
+    }
+
+    /// `#RRGGBB`, upper case like the shipped themes.
+    pub fn to_hex(self) -> String {
+        format!("#{:02X}{:02X}{:02X}", self.r, self.g, self.b)
+    }
+
+    /// WCAG 2.x relative luminance, 0.0 (black) to 1.0 (white).
+    pub fn luminance(self) -> f64 {
+        let linear = |c: u8| {
+            let c = c as f64 / 255.0;
+            if c <= 0.04045 {
+                c / 12.92
+            } else {
+                ((c + 0.055) / 1.055).powf(2.4)
+            }
+        };
+        0.2126 * linear(self.r) + 0.7152 * linear(self.g) + 0.0722 * linear(self.b)
+    }
+
+    /// WCAG 2.x contrast ratio between two colors, 1.0 to 21.0.
+    pub fn contrast(self, other: Rgb) -> f64 {
+        let (a, b) = (self.luminance(), other.luminance());
+        let (hi, lo) = if a > b { (a, b) } else { (b, a) };
+        (hi + 0.05) / (lo + 0.05)
+    }
+
+    /// Hue in degrees (0..360), saturation and lightness (0..1).
+    pub fn to_hsl(self) -> (f64, f64, f64) {
+        let r = self.r as f64 / 255.0;
+        let g = self.g as f64 / 255.0;
+        let b = self.b as f64 / 255.0;
+        let max = r.max(g).max(b);
+        let min = r.min(g).min(b);
+        let l = (max + min) / 2.0;
+        let d = max - min;
+        if d == 0.0 {
+            return (0.0, 0.0, l);
+        }
+        let s = d / (1.0 - (2.0 * l - 1.0).abs());
+        let h = if max == r {
+            60.0 * ((g - b) / d).rem_euclid(6.0)
+        } else if max == g {
+            60.0 * ((b - r) / d + 2.0)
+        } else {
+            60.0 * ((r - g) / d + 4.0)
+        };
+        (h, s, l)
+    }
+
+    pub fn from_hsl(h: f64, s: f64, l: f64) -> Self {
+        let s = s.clamp(0.0, 1.0);
+        let l = l.clamp(0.0, 1.0);
+        let c = (1.0 - (2.0 * l - 1.0).abs()) * s;
+        let h = h.rem_euclid(360.0) / 60.0;
+        let x = c * (1.0 - (h % 2.0 - 1.0).abs());
+        let (r, g, b) = match h as u32 {
+            0 => (c, x, 0.0),
+            1 => (x, c, 0.0),
+            2 => (0.0, c, x),
+            3 => (0.0, x, c),
+            4 => (x, 0.0, c),
+            _ => (c, 0.0, x),
+        };
+        let m = l - c / 2.0;
+        let to_u8 = |v: f64| ((v + m) * 255.0).round().clamp(0.0, 255.0) as u8;
+        Rgb::new(to_u8(r), to_u8(g), to_u8(b))
+    }
+
+    /// Same hue and saturation at lightness `l`.
+    pub fn with_lightness(self, l: f64) -> Self {
+        let (h, s, _) = self.to_hsl();
+        Rgb::from_hsl(h, s, l)
+    }
+}
+
+impl fmt::Display for Rgb {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.to_hex())
+    }
+}
+
+/// A theme color: sRGB plus alpha, as held in `[colors]`.
+///
+/// Parses `#RGB`, `#RGBA`, `#RRGGBB`, `#RRGGBBAA`, `rgb()`/`rgba()`,
+/// `hsl()`/`hsla()` and CSS named colors. Displays (and serializes) as
+/// `#RRGGBB`, or `#RRGGBBAA` when not fully opaque.
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, Hash, rkyv::Archive, rkyv::Serialize, rkyv::Deserialize,
+)]
+pub struct Color {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+    pub a: u8,
+}
+
+impl From<&ArchivedColor> for Color {
+    fn from(c: &ArchivedColor) -> Self {
+        Color::new(c.r, c.g, c.b, c.a)
+    }
+}
+
+impl Color {
+    pub const fn new(r: u8, g: u8, b: u8, a: u8) -> Self {
+        Color { r, g, b, a }
+    }
+
+    /// The color without its alpha.
+    pub fn rgb(self) -> Rgb {
+        Rgb::new(self.r, self.g, self.b)
+    }
+
+    pub fn is_opaque(self) -> bool {
+        self.a == 255
+    }
+
+    /// `rgb` with this color's alpha.
+    pub fn with_rgb(self, rgb: Rgb) -> Self {
+        Color::new(rgb.r, rgb.g, rgb.b, self.a)
+    }
+
+    pub fn with_alpha(self, a: u8) -> Self {
+        Color { a, ..self }
+    }
+
+    fn from_hex_digits(hex: &str) -> Option<Self> {
+        if !hex.is_ascii() {
+            return None;
+        }
+        let channel = |s: &str| u8::from_str_radix(s, 16).ok();
+        let short = |i: usize| channel(&hex[i..i + 1].repeat(2));
+        let long = |i: usize| channel(&hex[i..i + 2]);
+        match hex.len() {
+            3 => Some(Color::new(short(0)?, short(1)?, short(2)?, 255)),
+            4 => Some(Color::new(short(0)?, short(1)?, short(2)?, short(3)?)),
+            6 => Some(Color::new(long(0)?, long(2)?, long(4)?, 255)),
+            8 => Some(Color::new(long(0)?, long(2)?, long(4)?, long(6)?)),
+            _ => None,
+        }
+    }
+}
+
+impl From<Rgb> for Color {
+    fn from(rgb: Rgb) -> Self {
+        Color::new(rgb.r, rgb.g, rgb.b, 255)
+    }
+}
+
+impl From<Color> for colored::CustomColor {
+    fn from(color: Color) -> Self {
+        colored::CustomColor::new(color.r, color.g, color.b)
+    }
+}
+
+impl fmt::Display for Color {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.rgb())?;
+        if !self.is_opaque() {
+            write!(f, "{:02X}", self.a)?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Color {
+    /// Why the value is not a color.
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        let value = s.trim().to_ascii_lowercase();
+
+        if let Some(hex) = value.strip_prefix('#') {
+            return Color::from_hex_digits(hex).ok_or_else(|| {
+                format!(
+                    "'{}' is not a hex color (#RGB, #RGBA, #RRGGBB or #RRGGBBAA)",
+                    s.trim()
+                )
+            });
+        }
+
+        if let Some((function, args)) = value.strip_suffix(')').and_then(|v| v.split_once('(')) {
+            // Both `rgb(1, 2, 3, 0.5)` and `rgb(1 2 3 / 50%)`
+            let args: Vec<&str> = args
+                .split([',', '/', ' '])
+                .filter(|a| !a.is_empty())
+                .collect();
+            let parsed = match function.trim() {
+                "rgb" | "rgba" => css_rgb(&args),
+                "hsl" | "hsla" => css_hsl(&args),
+                other => Err(format!("unknown color function '{}()'", other)),
+            };
+            return parsed.map_err(|e| format!("'{}': {}", s.trim(), e));
+        }
+
+        if let Some((_, color)) = NAMED_COLORS.iter().find(|(name, _)| *name == value) {
+            return Ok(*color);
+        }
+        // Bare hex digits, which kitchn has always accepted
+        if matches!(value.len(), 6 | 8)
+            && let Some(color) = Color::from_hex_digits(&value)
+        {
+            return Ok(color);
+        }
+        Err(format!("'{}' is not a color", s.trim()))
+    }
+}
+
+impl Serialize for Color {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for Color {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+// In theme files a color may also be a reference or a computed color, so
+// the schema only asks for a string
+impl JsonSchema for Color {
+    fn schema_name() -> Cow<'static, str> {
+        "Color".into()
+    }
+
+    fn json_schema(_: &mut SchemaGenerator) -> Schema {
+        json_schema!({
+            "type": "string",
+            "description": "A color: #RGB, #RGBA, #RRGGBB, #RRGGBBAA, rgb(), rgba(), hsl(), hsla() or a CSS color name. In [colors], also @key or darken/lighten/saturate/desaturate(@key, 10%) and mix(@a, @b, 50%)."
+        })
+    }
+}
+
+fn css_rgb(args: &[&str]) -> Result<Color, String> {
+    if !(3..=4).contains(&args.len()) {
+        return Err(format!("rgb() takes 3 or 4 values, got {}", args.len()));
+    }
+    let channel = |arg: &str| -> Result<u8, String> {
+        let value = match arg.strip_suffix('%') {
+            Some(pct) => number(pct)? * 2.55,
+            None => number(arg)?,
+        };
+        Ok(value.round().clamp(0.0, 255.0) as u8)
+    };
+    Ok(Color::new(
+        channel(args[0])?,
+        channel(args[1])?,
+        channel(args[2])?,
+        args.get(3).map(|a| alpha(a)).transpose()?.unwrap_or(255),
+    ))
+}
+
+fn css_hsl(args: &[&str]) -> Result<Color, String> {
+    if !(3..=4).contains(&args.len()) {
+        return Err(format!("hsl() takes 3 or 4 values, got {}", args.len()));
+    }
+    let hue = number(args[0].trim_end_matches("deg"))?;
+    let percent = |arg: &str| Ok::<f64, String>(number(arg.trim_end_matches('%'))? / 100.0);
+    let rgb = Rgb::from_hsl(hue, percent(args[1])?, percent(args[2])?);
+    let a = args.get(3).map(|a| alpha(a)).transpose()?.unwrap_or(255);
+    Ok(Color::from(rgb).with_alpha(a))
+}
+
+/// `0.5` or `50%`.
+fn alpha(arg: &str) -> Result<u8, String> {
+    let value = match arg.strip_suffix('%') {
+        Some(pct) => number(pct)? / 100.0,
+        None => number(arg)?,
+    };
+    Ok((value.clamp(0.0, 1.0) * 255.0).round() as u8)
+}
+
+fn number(arg: &str) -> Result<f64, String> {
+    arg.trim()
+        .parse::<f64>()
+        .ok()
+        .filter(|n| n.is_finite())
+        .ok_or_else(|| format!("'{}' is not a number", arg.trim()))
+}
+
+/// CSS named colors (CSS Color Module Level 4).
+const NAMED_COLORS: &[(&str, Color)] = &[
+    ("transparent", Color::new(0, 0, 0, 0)),
+    ("aliceblue", Color::new(240, 248, 255, 255)),
+    ("antiquewhite", Color::new(250, 235, 215, 255)),
+    ("aqua", Color::new(0, 255, 255, 255)),
+    ("aquamarine", Color::new(127, 255, 212, 255)),
+    ("azure", Color::new(240, 255, 255, 255)),
+    ("beige", Color::new(245, 245, 220, 255)),
+    ("bisque", Color::new(255, 228, 196, 255)),
+    ("black", Color::new(0, 0, 0, 255)),
+    ("blanchedalmond", Color::new(255, 235, 205, 255)),
+    ("blue", Color::new(0, 0, 255, 255)),
+    ("blueviolet", Color::new(138, 43, 226, 255)),
+    ("brown", Color::new(165, 42, 42, 255)),
+    ("burlywood", Color::new(222, 184, 135, 255)),
+    ("cadetblue", Color::new(95, 158, 160, 255)),
+    ("chartreuse", Color::new(127, 255, 0, 255)),
+    ("chocolate", Color::new(210, 105, 30, 255)),
+    ("coral", Color::new(255, 127, 80, 255)),
+    ("cornflowerblue", Color::new(100, 149, 237, 255)),
+    ("cornsilk", Color::new(255, 248, 220, 255)),
+    ("crimson", Color::new(220, 20, 60, 255)),
+    ("cyan", Color::new(0, 255, 255, 255)),
+    ("darkblue", Color::new(0, 0, 139, 255)),
+    ("darkcyan", Color::new(0, 139, 139, 255)),
+    ("darkgoldenrod", Color::new(184, 134, 11, 255)),
+    ("darkgray", Color::new(169, 169, 169, 255)),
+    ("darkgreen", Color::new(0, 100, 0, 255)),
+    ("darkgrey", Color::new(169, 169, 169, 255)),
+    ("darkkhaki", Color::new(189, 183, 107, 255)),
+    ("darkmagenta", Color::new(139, 0, 139, 255)),
+    ("darkolivegreen", Color::new(85, 107, 47, 255)),
+    ("darkorange", Color::new(255, 140, 0, 255)),
+    ("darkorchid", Color::new(153, 50, 204, 255)),
+    ("darkred", Color::new(139, 0, 0, 255)),
+    ("darksalmon", Color::new(233, 150, 122, 255)),
+    ("darkseagreen", Color::new(143, 188, 143, 255)),
+    ("darkslateblue", Color::new(72, 61, 139, 255)),
+    ("darkslategray", Color::new(47, 79, 79, 255)),
+    ("darkslategrey", Color::new(47, 79, 79, 255)),
+    ("darkturquoise", Color::new(0, 206, 209, 255)),
+    ("darkviolet", Color::new(148, 0, 211, 255)),
+    ("deeppink", Color::new(255, 20, 147, 255)),
+    ("deepskyblue", Color::new(0, 191, 255, 255)),
+    ("dimgray", Color::new(105, 105, 105, 255)),
+    ("dimgrey", Color::new(105, 105, 105, 255)),
+    ("dodgerblue", Color::new(30, 144, 255, 255)),
+    ("firebrick", Color::new(178, 34, 34, 255)),
+    ("floralwhite", Color::new(255, 250, 240, 255)),
+    ("forestgreen", Color::new(34, 139, 34, 255)),
+    ("fuchsia", Color::new(255, 0, 255, 255)),
+    ("gainsboro", Color::new(220, 220, 220, 255)),
+    ("ghostwhite", Color::new(248, 248, 255, 255)),
+    ("gold", Color::new(255, 215, 0, 255)),
+    ("goldenrod", Color::new(218, 165, 32, 255)),
+    ("gray", Color::new(128, 128, 128, 255)),
+    ("green", Color::new(0, 128, 0, 255)),
+    ("greenyellow", Color::new(173, 255, 47, 255)),
+    ("grey", Color::new(128, 128, 128, 255)),
+    ("honeydew", Color::new(240, 255, 240, 255)),
+    ("hotpink", Color::new(255, 105, 180, 255)),
+    ("indianred", Color::new(205, 92, 92, 255)),
+    ("indigo", Color::new(75, 0, 130, 255)),
+    ("ivory", Color::new(255, 255, 240, 255)),
+    ("khaki", Color::new(240, 230, 140, 255)),
+    ("lavender", Color::new(230, 230, 250, 255)),
+    ("lavenderblush", Color::new(255, 240, 245, 255)),
+    ("lawngreen", Color::new(124, 252, 0, 255)),
+    ("lemonchiffon", Color::new(255, 250, 205, 255)),
+    ("lightblue", Color::new(173, 216, 230, 255)),
+    ("lightcoral", Color::new(240, 128, 128, 255)),
+    ("lightcyan", Color::new(224, 255, 255, 255)),
+    ("lightgoldenrodyellow", Color::new(250, 250, 210, 255)),
+    ("lightgray", Color::new(211, 211, 211, 255)),
+    ("lightgreen", Color::new(144, 238, 144, 255)),
+    ("lightgrey", Color::new(211, 211, 211, 255)),
+    ("lightpink", Color::new(255, 182, 193, 255)),
+    ("lightsalmon", Color::new(255, 160, 122, 255)),
+    ("lightseagreen", Color::new(32, 178, 170, 255)),
+    ("lightskyblue", Color::new(135, 206, 250, 255)),
+    ("lightslategray", Color::new(119, 136, 153, 255)),
+    ("lightslategrey", Color::new(119, 136, 153, 255)),
+    ("lightsteelblue", Color::new(176, 196, 222, 255)),
+    ("lightyellow", Color::new(255, 255, 224, 255)),
+    ("lime", Color::new(0, 255, 0, 255)),
+    ("limegreen", Color::new(50, 205, 50, 255)),
+    ("linen", Color::new(250, 240, 230, 255)),
+    ("magenta", Color::new(255, 0, 255, 255)),
+    ("maroon", Color::new(128, 0, 0, 255)),
+    ("mediumaquamarine", Color::new(102, 205, 170, 255)),
+    ("mediumblue", Color::new(0, 0, 205, 255)),
+    ("mediumorchid", Color::new(186, 85, 211, 255)),
+    ("mediumpurple", Color::new(147, 112, 219, 255)),
+    ("mediumseagreen", Color::new(60, 179, 113, 255)),
+    ("mediumslateblue", Color::new(123, 104, 238, 255)),
+    ("mediumspringgreen", Color::new(0, 250, 154, 255)),
+    ("mediumturquoise", Color::new(72, 209, 204, 255)),
+    ("mediumvioletred", Color::new(199, 21, 133, 255)),
+    ("midnightblue", Color::new(25, 25, 112, 255)),
+    ("mintcream", Color::new(245, 255, 250, 255)),
+    ("mistyrose", Color::new(255, 228, 225, 255)),
+    ("moccasin", Color::new(255, 228, 181, 255)),
+    ("navajowhite", Color::new(255, 222, 173, 255)),
+    ("navy", Color::new(0, 0, 128, 255)),
+    ("oldlace", Color::new(253, 245, 230, 255)),
+    ("olive", Color::new(128, 128, 0, 255)),
+    ("olivedrab", Color::new(107, 142, 35, 255)),
+    ("orange", Color::new(255, 165, 0, 255)),
+    ("orangered", Color::new(255, 69, 0, 255)),
+    ("orchid", Color::new(218, 112, 214, 255)),
+    ("palegoldenrod", Color::new(238, 232, 170, 255)),
+    ("palegreen", Color::new(152, 251, 152, 255)),
+    ("paleturquoise", Color::new(175, 238, 238, 255)),
+    ("palevioletred", Color::new(219, 112, 147, 255)),
+    ("papayawhip", Color::new(255, 239, 213, 255)),
+    ("peachpuff", Color::new(255, 218, 185, 255)),
+    ("peru", Color::new(205, 133, 63, 255)),
+    ("pink", Color::new(255, 192, 203, 255)),
+    ("plum", Color::new(221, 160, 221, 255)),
+    ("powderblue", Color::new(176, 224, 230, 255)),
+    ("purple", Color::new(128, 0, 128, 255)),
+    ("rebeccapurple", Color::new(102, 51, 153, 255)),
+    ("red", Color::new(255, 0, 0, 255)),
+    ("rosybrown", Color::new(188, 143, 143, 255)),
+    ("royalblue", Color::new(65, 105, 225, 255)),
+    ("saddlebrown", Color::new(139, 69, 19, 255)),
+    ("salmon", Color::new(250, 128, 114, 255)),
+    ("sandybrown", Color::new(244, 164, 96, 255)),
+    ("seagreen", Color::new(46, 139, 87, 255)),
+    ("seashell", Color::new(255, 245, 238, 255)),
+    ("sienna", Color::new(160, 82, 45, 255)),
+    ("silver", Color::new(192, 192, 192, 255)),
+    ("skyblue", Color::new(135, 206, 235, 255)),
+    ("slateblue", Color::new(106, 90, 205, 255)),
+    ("slategray", Color::new(112, 128, 144, 255)),
+    ("slategrey", Color::new(112, 128, 144, 255)),
+    ("snow", Color::new(255, 250, 250, 255)),
+    ("springgreen", Color::new(0, 255, 127, 255)),
+    ("steelblue", Color::new(70, 130, 180, 255)),
+    ("tan", Color::new(210, 180, 140, 255)),
+    ("teal", Color::new(0, 128, 128, 255)),
+    ("thistle", Color::new(216, 191, 216, 255)),
+    ("tomato", Color::new(255, 99, 71, 255)),
+    ("turquoise", Color::new(64, 224, 208, 255)),
+    ("violet", Color::new(238, 130, 238, 255)),
+    ("wheat", Color::new(245, 222, 179, 255)),
+    ("white", Color::new(255, 255, 255, 255)),
+    ("whitesmoke", Color::new(245, 245, 245, 255)),
+    ("yellow", Color::new(255, 255, 0, 255)),
+    ("yellowgreen", Color::new(154, 205, 50, 255)),
+];
+
+/// `[colors]` keys of a complete theme, grouped the way the shipped themes are laid out.
+pub const THEME_KEYS: &[(&str, &[&str])] = &[
+    (
+        "Special",
+        &["bg", "fg", "cursor", "selection_bg", "selection_fg"],
+    ),
+    ("Tabs", &["tabs", "tabs_active"]),
+    (
+        "Semantic",
+        &[
+            "primary",
+            "secondary",
+            "success",
+            "error",
+            "warn",
+            "info",
+            "orange",
+        ],
+    ),
+    (
+        "Standard ANSI (Normal)",
+        &[
+            "black", "red", "green", "yellow", "blue", "magenta", "cyan", "white",
+        ],
+    ),
+    (
+        "Standard ANSI (Bright)",
+        &[
+            "bright_black",
+            "bright_red",
+            "bright_green",
+            "bright_yellow",
+            "bright_blue",
+            "bright_magenta",
+            "bright_cyan",
+            "bright_white",
+        ],
+    ),
+];
+
+/// ANSI slot names in terminal order (`color0` .. `color15`).
+pub const ANSI_KEYS: [&str; 16] = [
+    "black",
+    "red",
+    "green",
+    "yellow",
+    "blue",
+    "magenta",
+    "cyan",
+    "white",
+    "bright_black",
+    "bright_red",
+    "bright_green",
+    "bright_yellow",
+    "bright_blue",
+    "bright_magenta",
+    "bright_cyan",
+    "bright_white",
+];
+
+/// A set of colors keyed like `[colors]` in a theme (see [`THEME_KEYS`]).
+#[derive(Debug, Clone, Default)]
+pub struct Palette {
+    pub colors: HashMap<&'static str, Rgb>,
+}
+
+impl Palette {
+    /// The known keys of a theme's `[colors]` that are opaque; translucent
+    /// colors are left out.
+    pub fn from_colors(colors: &HashMap<String, Color>) -> Self {
+        let colors = THEME_KEYS
+            .iter()
+            .flat_map(|(_, keys)| keys.iter())
+            .filter_map(|key| {
+                let color = colors.get(*key)?;
+                color.is_opaque().then(|| (*key, color.rgb()))
+            })
+            .collect();
+        Palette { colors }
+    }
+
+    pub fn get(&self, key: &str) -> Option<Rgb> {
+        self.colors.get(key).copied()
+    }
+
+    /// A complete theme file for the palette, starting with `header` as a
+    /// comment. Fonts and icon set are carried over from `base`, since color
+    /// sources say nothing about them.
+    pub fn to_theme_toml(&self, name: &str, header: &str, base: &ThemeConfig) -> String {
+        let quote = |s: &str| toml::Value::String(s.to_string()).to_string();
+        let icons = toml::Value::try_from(&base.settings.active_icons)
+            .map(|v| v.to_string())
+            .unwrap_or_default();
+        let mut out = String::new();
+
+        out.push_str(&format!("# {}\n\n", header));
+        out.push_str(&format!("[meta]\nname = {}\n\n", quote(name)));
+        out.push_str(&format!("[settings]\nactive_icons = {}\n\n", icons));
+
+        out.push_str("[colors]\n");
+        for (i, (section, keys)) in THEME_KEYS.iter().enumerate() {
+            if i > 0 {
+                out.push('\n');
+            }
+            out.push_str(&format!("# {}\n", section));
+            for key in *keys {
+                if let Some(color) = self.get(key) {
+                    out.push_str(&format!("{} = \"{}\"\n", key, color));
+                }
+            }
+        }
+
+        out.push_str("\n[fonts]\n");
+        let fonts: BTreeMap<_, _> = base.fonts.iter().collect();
+        for (key, value) in fonts {
+            out.push_str(&format!("{} = {}\n", key, quote(value)));
+        }
+        out
+    }
+}
+
+/// Functions available in `[colors]` values, e.g. `darken(@bg, 10%)`.
+const FUNCTIONS: &[&str] = &["darken", "lighten", "saturate", "desaturate", "mix"];
+
+#[derive(Error, Debug)]
+pub enum ColorError {
+    #[error("refers to '@{target}', which is not in [colors]")]
+    UnknownReference { key: String, target: String },
+    #[error("reference cycle {}", .0.join(" -> "))]
+    Cycle(Vec<String>),
+    #[error("{reason}")]
+    Invalid { key: String, reason: String },
+}
+
+impl ColorError {
+    /// The `[colors]` key whose value could not be resolved.
+    pub fn key(&self) -> &str {
+        match self {
+            ColorError::UnknownReference { key, .. } | ColorError::Invalid { key, .. } => key,
+            ColorError::Cycle(keys) => &keys[0],
+        }
+    }
+}
+
+/// Resolves theme `[colors]` values into [`Color`]s. Besides plain colors,
+/// values may be references (`@bright_blue`) and functions
+/// (`darken(@bg, 10%)`, `lighten`, `saturate`, `desaturate`,
+/// `mix(@a, @b, 25%)`). Amounts are percentage points of HSL lightness or
+/// saturation; `mix` weighs its first color. Alpha is carried through.
+pub fn resolve_colors(
+    colors: &HashMap<String, String>,
+) -> Result<HashMap<String, Color>, ColorError> {
+    let mut resolver = Resolver {
+        raw: colors,
+        done: HashMap::new(),
+        stack: Vec::new(),
+    };
+    // Sorted, so the same broken theme always reports the same key
+    let mut keys: Vec<&String> = colors.keys().collect();
+    keys.sort();
+    for key in keys {
+        resolver.key(key)?;
+    }
+    Ok(resolver.done)
+}
+
+/// How each resolved color reads in templates: a plain color as the theme
+/// wrote it (through any references), a computed one as [`Color`] displays.
+/// Templates rendered before colors were typed keep their exact output.
+pub fn spell_colors(
+    raw: &HashMap<String, String>,
+    resolved: &HashMap<String, Color>,
+) -> HashMap<String, String> {
+    resolved
+        .iter()
+        .map(|(name, color)| {
+            // Cycles were ruled out while resolving
+            let mut key = name.as_str();
+            while let Some(target) = raw[key].trim().strip_prefix('@') {
+                key = target;
+            }
+            let expr = raw[key].trim();
+            let spelling = match function_call(expr) {
+                Some(_) => color.to_string(),
+                None => expr.to_string(),
+            };
+            (name.clone(), spelling)
+        })
+        .collect()
+}
+
+/// `(name, args)` if `expr` calls one of [`FUNCTIONS`].
+fn function_call(expr: &str) -> Option<(&str, &str)> {
+    expr.strip_suffix(')')
+        .and_then(|e| e.split_once('('))
+        .filter(|(name, _)| FUNCTIONS.contains(&name.trim()))
+}
+
+struct Resolver<'a> {
+    raw: &'a HashMap<String, String>,
+    done: HashMap<String, Color>,
+    /// Keys being resolved, outermost first
+    stack: Vec<String>,
+}
+
+impl Resolver<'_> {
+    fn key(&mut self, key: &str) -> Result<Color, ColorError> {
+        if let Some(color) = self.done.get(key) {
+            return Ok(*color);
+        }
+        if let Some(start) = self.stack.iter().position(|k| k == key) {
+            let mut cycle = self.stack[start..].to_vec();
+            cycle.push(key.to_string());
+            return Err(ColorError::Cycle(cycle));
+        }
+
+        let raw = &self.raw[key];
+        self.stack.push(key.to_string());
+        let color = self.expr(key, raw)?;
+        self.stack.pop();
+        self.done.insert(key.to_string(), color);
+        Ok(color)
+    }
+
+    fn expr(&mut self, key: &str, expr: &str) -> Result<Color, ColorError> {
+        let expr = expr.trim();
+        let invalid = |reason: String| ColorError::Invalid {
+            key: key.to_string(),
+            reason,
+        };
+        if let Some(target) = expr.strip_prefix('@') {
+            if !self.raw.contains_key(target) {
+                return Err(ColorError::UnknownReference {
+                    key: key.to_string(),
+                    target: target.to_string(),
+                });
+            }
+            return self.key(target);
+        }
+
+        // Anything that is not one of ours (rgb(), hsl(), ...) is a plain color
+        let Some((name, args)) = function_call(expr) else {
+            return expr.parse().map_err(invalid);
+        };
+        let name = name.trim();
+        let args = split_args(args);
+
+        let expected = if name == "mix" { 2..=3 } else { 2..=2 };
+        if !expected.contains(&args.len()) {
+            return Err(invalid(format!(
+                "{}() takes {} arguments, got {}",
+                name,
+                if name == "mix" { "2 or 3" } else { "2" },
+                args.len()
+            )));
+        }
+
+        let base = self.expr(key, args[0])?;
+        let other = if name == "mix" {
+            Some(self.expr(key, args[1])?)
+        } else {
+            None
+        };
+        let amount_arg = match name {
+            "mix" => args.get(2).copied().unwrap_or("50%"),
+            _ => args[1],
+        };
+        let amount = amount_arg
+            .trim()
+            .trim_end_matches('%')
+            .trim()
+            .parse::<f64>()
+            .map_err(|_| invalid(format!("'{}' is not a percentage", amount_arg.trim())))?
+            / 100.0;
+
+        let (h, s, l) = base.rgb().to_hsl();
+        let result = match name {
+            "darken" => base.with_rgb(Rgb::from_hsl(h, s, l - amount)),
+            "lighten" => base.with_rgb(Rgb::from_hsl(h, s, l + amount)),
+            "saturate" => base.with_rgb(Rgb::from_hsl(h, s + amount, l)),
+            "desaturate" => base.with_rgb(Rgb::from_hsl(h, s - amount, l)),
+            _ => {
+                let other = other.expect("mix has two colors");
+                let w = amount.clamp(0.0, 1.0);
+                let blend = |a: u8, b: u8| (a as f64 * w + b as f64 * (1.0 - w)).round() as u8;
+                Color::new(
+                    blend(base.r, other.r),
+                    blend(base.g, other.g),
+                    blend(base.b, other.b),
+                    blend(base.a, other.a),
+                )
+            }
+        };
+        Ok(result)
+    }
+}
+
+/// Splits function arguments on commas outside nested parentheses.
+fn split_args(args: &str) -> Vec<&str> {
+    let mut out = Vec::new();
+    let (mut depth, mut start) = (0usize, 0);
+    for (i, c) in args.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' => depth = depth.saturating_sub(1),
+            ',' if depth == 0 => {
+                out.push(&args[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    out.push(&args[start..]);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hex_roundtrip() {
+        let c = Rgb::from_hex("#bd93f9").unwrap();
+        assert_eq!(c, Rgb::new(0xbd, 0x93, 0xf9));
+        assert_eq!(c.to_hex(), "#BD93F9");
+        assert_eq!(Rgb::from_hex("161925"), Some(Rgb::new(0x16, 0x19, 0x25)));
+        assert_eq!(Rgb::from_hex("#fff"), None);
+        assert_eq!(Rgb::from_hex("#gg0000"), None);
+    }
+
+    #[test]
+    fn test_contrast() {
+        let black = Rgb::new(0, 0, 0);
+        let white = Rgb::new(255, 255, 255);
+        assert!((black.contrast(white) - 21.0).abs() < 1e-9);
+        assert!((white.contrast(black) - 21.0).abs() < 1e-9);
+        assert!((white.contrast(white) - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_hsl_roundtrip() {
+        for hex in ["#FF5555", "#50FA7B", "#161925", "#808080", "#8BE9FD"] {
+            let c = Rgb::from_hex(hex).unwrap();
+            let (h, s, l) = c.to_hsl();
+            let back = Rgb::from_hsl(h, s, l);
+            assert!(
+                (c.r as i16 - back.r as i16).abs() <= 1
+                    && (c.g as i16 - back.g as i16).abs() <= 1
+                    && (c.b as i16 - back.b as i16).abs() <= 1,
+                "{} -> {}",
+                hex,
+                back
+            );
+        }
+    }
+
+    #[test]
+    fn test_color_parsing() {
+        let parse = |s: &str| s.parse::<Color>().unwrap();
+        assert_eq!(parse("#BD93F9"), Color::new(0xbd, 0x93, 0xf9, 255));
+        assert_eq!(parse("bd93f9"), Color::new(0xbd, 0x93, 0xf9, 255));
+        assert_eq!(parse("#f0a"), Color::new(0xff, 0x00, 0xaa, 255));
+        assert_eq!(parse("#f0a8"), Color::new(0xff, 0x00, 0xaa, 0x88));
+        assert_eq!(parse("#16192580"), Color::new(0x16, 0x19, 0x25, 0x80));
+        assert_eq!(parse("rgb(255, 0, 128)"), Color::new(255, 0, 128, 255));
+        assert_eq!(parse("rgba(0, 0, 0, 0.5)"), Color::new(0, 0, 0, 128));
+        assert_eq!(parse("rgb(100% 0% 0% / 25%)"), Color::new(255, 0, 0, 64));
+        assert_eq!(parse("hsl(0, 100%, 50%)"), Color::new(255, 0, 0, 255));
+        assert_eq!(
+            parse("hsla(120deg 100% 25% / 1)"),
+            Color::new(0, 128, 0, 255)
+        );
+        assert_eq!(parse("RebeccaPurple"), Color::new(102, 51, 153, 255));
+        assert_eq!(parse("transparent"), Color::new(0, 0, 0, 0));
+
+        for bad in [
+            "",
+            "#12345",
+            "#ggg",
+            "notacolor",
+            "rgb(1, 2)",
+            "hsl(a, b, c)",
+            "cmyk(0,0,0,0)",
+        ] {
+            assert!(bad.parse::<Color>().is_err(), "{}", bad);
+        }
+        assert_eq!(
+            "#12345".parse::<Color>().unwrap_err(),
+            "'#12345' is not a hex color (#RGB, #RGBA, #RRGGBB or #RRGGBBAA)"
+        );
+    }
+
+    #[test]
+    fn test_color_display_and_serde() {
+        assert_eq!(Color::new(0xbd, 0x93, 0xf9, 255).to_string(), "#BD93F9");
+        assert_eq!(Color::new(0, 0, 0, 0x80).to_string(), "#00000080");
+
+        #[derive(Debug, Serialize, Deserialize)]
+        struct Theme {
+            colors: HashMap<String, Color>,
+        }
+        let theme: Theme = toml::from_str("[colors]\nbg = \"rgb(22, 25, 37)\"").unwrap();
+        assert_eq!(theme.colors["bg"].to_string(), "#161925");
+        assert!(
+            toml::to_string(&theme)
+                .unwrap()
+                .contains("bg = \"#161925\"")
+        );
+
+        let err = toml::from_str::<Theme>("[colors]\nbg = \"#zzz\"").unwrap_err();
+        assert!(err.to_string().contains("'#zzz' is not a hex color"));
+    }
+
+    fn colors(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_resolve_references_and_functions() {
+        let resolved = resolve_colors(&colors(&[
+            ("bg", "#161925"),
+            ("tabs", "@bg"),
+            ("bright_blue", "#BD93F9"),
+            ("primary", "@bright_blue"),
+            ("tabs_active", "@primary"),
+            ("border", "lighten(@bg, 10%)"),
+            ("shadow", "darken( @border , 10 )"),
+            ("muted", "mix(@bg, #FFFFFF)"),
+            ("dim", "desaturate(lighten(@bright_blue, 5%), 20%)"),
+            ("overlay", "rgba(0, 0, 0, 0.5)"),
+            ("scrim", "darken(@overlay, 0%)"),
+        ]))
+        .unwrap();
+        let hex = |key: &str| resolved[key].to_string();
+
+        assert_eq!(hex("tabs"), "#161925");
+        assert_eq!(hex("tabs_active"), "#BD93F9");
+        assert_eq!(hex("shadow"), "#161925");
+        assert_eq!(hex("muted"), "#8B8C92");
+        // Alpha survives functions
+        assert_eq!(hex("overlay"), "#00000080");
+        assert_eq!(hex("scrim"), "#00000080");
+
+        let bg = Rgb::from_hex("#161925").unwrap().to_hsl();
+        let border = resolved["border"].rgb().to_hsl();
+        assert!((border.2 - bg.2 - 0.1).abs() < 0.01);
+        let dim = resolved["dim"].rgb().to_hsl();
+        assert!(dim.1 < Rgb::from_hex("#BD93F9").unwrap().to_hsl().1);
+    }
+
+    #[test]
+    fn test_spell_colors() {
+        let raw = colors(&[
+            ("bg", "#161925"),
+            ("fg", " #f8f8f2 "),
+            ("tabs", "@bg"),
+            ("overlay", "rgba(0, 0, 0, 0.5)"),
+            ("scrim", "@overlay"),
+            ("border", "lighten(@bg, 0%)"),
+            ("edge", "@border"),
+        ]);
+        let spellings = spell_colors(&raw, &resolve_colors(&raw).unwrap());
+
+        // Plain colors keep their spelling, through references
+        assert_eq!(spellings["fg"], "#f8f8f2");
+        assert_eq!(spellings["tabs"], "#161925");
+        assert_eq!(spellings["scrim"], "rgba(0, 0, 0, 0.5)");
+        // Computed colors have no spelling of their own
+        assert_eq!(spellings["border"], "#161925");
+        assert_eq!(spellings["edge"], "#161925");
+    }
+
+    #[test]
+    fn test_resolve_errors() {
+        let err = resolve_colors(&colors(&[
+            ("a", "@b"),
+            ("b", "darken(@c, 5%)"),
+            ("c", "@a"),
+        ]))
+        .unwrap_err();
+        assert_eq!(err.to_string(), "reference cycle a -> b -> c -> a");
+        assert_eq!(err.key(), "a");
+
+        let err = resolve_colors(&colors(&[("tabs", "@background")])).unwrap_err();
+        assert_eq!(err.key(), "tabs");
+        assert_eq!(
+            err.to_string(),
+            "refers to '@background', which is not in [colors]"
+        );
+
+        let err =
+            resolve_colors(&colors(&[("x", "darken(@y, lots)"), ("y", "#000000")])).unwrap_err();
+        assert_eq!(err.to_string(), "'lots' is not a percentage");
+
+        let err = resolve_colors(&colors(&[("x", "#00000"), ("y", "@x")])).unwrap_err();
+        assert_eq!(err.key(), "x");
+        assert!(err.to_string().contains("is not a hex color"));
+
+        let err = resolve_colors(&colors(&[("x", "darken(#000000)")])).unwrap_err();
+        assert!(err.to_string().contains("takes 2 arguments, got 1"));
+    }
+}
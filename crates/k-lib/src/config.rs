@@ -1,34 +1,139 @@
+use crate::color::{self, Color};
+use crate::db::content_hash;
+use crate::dirs::Dirs;
+use crate::pastry::Pastry;
+use crate::scheduler;
 use log::debug;
+use rkyv::Archive;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(
+    Debug, Deserialize, Serialize, JsonSchema, Archive, rkyv::Serialize, rkyv::Deserialize,
+)]
 pub struct ThemeConfig {
     pub meta: ThemeMeta,
     pub settings: ThemeSettings,
-    pub colors: HashMap<String, String>,
+    pub colors: HashMap<String, Color>,
+    /// Each color as templates see it (see [`color::spell_colors`]).
+    #[serde(skip)]
+    pub spellings: HashMap<String, String>,
     pub fonts: HashMap<String, String>, // Using HashMap to allow dynamic font keys like "mono", "ui", "size_mono"
     pub include: Option<Vec<String>>,
+    /// Color overrides per variant (`[variants.light]`, `[variants.dark]`),
+    /// merged over `colors` when that variant is active.
+    #[serde(default)]
+    pub variants: HashMap<String, HashMap<String, String>>,
+    /// Active variant. The theme file may set a default; a variant chosen with
+    /// [`Cookbook::select_variant`] takes precedence.
+    #[serde(default)]
+    pub variant: Option<String>,
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(
+    Debug, Deserialize, Serialize, JsonSchema, Archive, rkyv::Serialize, rkyv::Deserialize,
+)]
 pub struct ThemeMeta {
     pub name: String,
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(
+    Debug, Deserialize, Serialize, JsonSchema, Archive, rkyv::Serialize, rkyv::Deserialize,
+)]
 pub struct ThemeSettings {
-    pub active_icons: String,
+    pub active_icons: IconChain,
+    /// When to switch between the light and dark variants (see `kitchn watch`).
+    #[serde(default)]
+    pub schedule: Option<Schedule>,
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+/// `[settings.schedule]`: either fixed `light`/`dark` times (`"HH:MM"`, local
+/// time) or `latitude`/`longitude` to follow sunrise and sunset.
+#[derive(
+    Debug,
+    Clone,
+    Default,
+    Deserialize,
+    Serialize,
+    JsonSchema,
+    Archive,
+    rkyv::Serialize,
+    rkyv::Deserialize,
+)]
+pub struct Schedule {
+    pub light: Option<String>,
+    pub dark: Option<String>,
+    pub latitude: Option<f64>,
+    pub longitude: Option<f64>,
+}
+
+/// `active_icons`: one icon set, or a fallback chain of them tried in order
+/// (`["nerdfont", "emoji", "ascii"]`).
+#[derive(
+    Debug,
+    Clone,
+    PartialEq,
+    Eq,
+    Deserialize,
+    Serialize,
+    JsonSchema,
+    Archive,
+    rkyv::Serialize,
+    rkyv::Deserialize,
+)]
+#[serde(untagged)]
+pub enum IconChain {
+    One(String),
+    Chain(Vec<String>),
+}
+
+impl IconChain {
+    /// The set names, most wanted first.
+    pub fn sets(&self) -> &[String] {
+        match self {
+            IconChain::One(set) => std::slice::from_ref(set),
+            IconChain::Chain(sets) => sets,
+        }
+    }
+}
+
+/// `icons.toml`: any number of named icon sets (`[nerdfont]`, `[emoji]`,
+/// ...), each mapping icon names to glyphs.
+#[derive(
+    Debug, Deserialize, Serialize, JsonSchema, Archive, rkyv::Serialize, rkyv::Deserialize,
+)]
 pub struct IconsConfig {
-    pub nerdfont: HashMap<String, String>,
-    pub ascii: HashMap<String, String>,
     pub include: Option<Vec<String>>,
+    #[serde(flatten)]
+    pub sets: HashMap<String, HashMap<String, String>>,
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+impl IconsConfig {
+    /// The icon `name` from the first set in `chain` that has it.
+    pub fn resolve(&self, chain: &IconChain, name: &str) -> Option<&str> {
+        chain
+            .sets()
+            .iter()
+            .find_map(|set| self.sets.get(set)?.get(name))
+            .map(String::as_str)
+    }
+
+    /// Every icon `chain` provides, each from the first set that has it.
+    pub fn resolve_all(&self, chain: &IconChain) -> HashMap<&str, &str> {
+        let mut icons = HashMap::new();
+        for set in chain.sets().iter().filter_map(|set| self.sets.get(set)) {
+            for (name, icon) in set {
+                icons.entry(name.as_str()).or_insert(icon.as_str());
+            }
+        }
+        icons
+    }
+}
+
+#[derive(
+    Debug, Deserialize, Serialize, JsonSchema, Archive, rkyv::Serialize, rkyv::Deserialize,
+)]
 pub struct LayoutConfig {
     pub tag: TagConfig,
     pub labels: HashMap<String, String>,
@@ -37,22 +142,52 @@ pub struct LayoutConfig {
     pub include: Option<Vec<String>>,
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(
+    Debug, Deserialize, Serialize, JsonSchema, Archive, rkyv::Serialize, rkyv::Deserialize,
+)]
 pub struct TagConfig {
     pub prefix: String,
     pub suffix: String,
     pub transform: String,
     pub min_width: usize,
+    /// `left`, `right` or `center` (anything else centers).
     pub alignment: String,
+    /// Labels wider than this many columns are cut.
+    #[serde(default)]
+    pub max_width: Option<usize>,
+    /// `[tag.levels.<level>]`: overrides for one level's tag.
+    #[serde(default)]
+    pub levels: HashMap<String, LevelTagConfig>,
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+/// How one level's tag differs from `[tag]`. `fg` and `bg` are theme color
+/// keys or colors; `fg` defaults to the level's color.
+#[derive(
+    Debug, Default, Deserialize, Serialize, JsonSchema, Archive, rkyv::Serialize, rkyv::Deserialize,
+)]
+pub struct LevelTagConfig {
+    pub prefix: Option<String>,
+    pub suffix: Option<String>,
+    pub transform: Option<String>,
+    pub fg: Option<String>,
+    pub bg: Option<String>,
+    #[serde(default)]
+    pub bold: bool,
+    #[serde(default)]
+    pub reverse: bool,
+}
+
+#[derive(
+    Debug, Deserialize, Serialize, JsonSchema, Archive, rkyv::Serialize, rkyv::Deserialize,
+)]
 pub struct StructureConfig {
     pub terminal: String,
     pub file: String,
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(
+    Debug, Deserialize, Serialize, JsonSchema, Archive, rkyv::Serialize, rkyv::Deserialize,
+)]
 pub struct LoggingConfig {
     pub base_dir: String,
     pub path_structure: String,
@@ -67,20 +202,181 @@ fn default_app_name() -> String {
     "kitchn".to_string()
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(
+    Debug, Deserialize, Serialize, JsonSchema, Archive, rkyv::Serialize, rkyv::Deserialize,
+)]
 pub struct DictionaryConfig {
     pub presets: HashMap<String, Preset>,
     pub include: Option<Vec<String>>,
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(
+    Debug, Deserialize, Serialize, JsonSchema, Archive, rkyv::Serialize, rkyv::Deserialize,
+)]
 pub struct Preset {
     pub level: String,
     pub scope: Option<String>,
     pub msg: String,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+/// Contents of `active_theme.toml`: which theme from `themes/` is in use.
+///
+/// Kept in its own file so switching themes never rewrites a theme file.
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct ThemeSelection {
+    pub name: Option<String>,
+    pub variant: Option<String>,
+}
+
+impl ThemeSelection {
+    pub fn load(config_dir: &Path) -> Result<Self, ConfigError> {
+        let path = config_dir.join(THEME_SELECTION_FILE);
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        Ok(toml::from_str(&fs::read_to_string(path)?)?)
+    }
+
+    pub fn save(&self, config_dir: &Path) -> Result<(), ConfigError> {
+        fs::create_dir_all(config_dir)?;
+        fs::write(
+            config_dir.join(THEME_SELECTION_FILE),
+            toml::to_string(self)?,
+        )?;
+        Ok(())
+    }
+}
+
+/// Directory (inside the config dir) holding named themes as `<name>.toml`.
+pub const THEMES_DIR: &str = "themes";
+/// File (inside the config dir) recording the [`ThemeSelection`].
+pub const THEME_SELECTION_FILE: &str = "active_theme.toml";
+
+/// Directory (inside the config dir) with per-machine overlays,
+/// `hosts/<hostname>.toml`.
+pub const HOSTS_DIR: &str = "hosts";
+/// Directory (inside the config dir) with overlays chosen by [`PROFILE_ENV`],
+/// `profiles/<name>.toml`.
+pub const PROFILES_DIR: &str = "profiles";
+/// Names the profile overlay to apply, if any.
+pub const PROFILE_ENV: &str = "KITCHN_PROFILE";
+/// Overrides the hostname used to pick the host overlay.
+pub const HOST_ENV: &str = "KITCHN_HOST";
+/// Prefix of environment variables that override one cookbook value, e.g.
+/// `KITCHN__THEME__COLORS__PRIMARY=#ff0000`.
+pub const ENV_PREFIX: &str = "KITCHN__";
+/// Sections of an overlay file, one per cookbook file.
+const OVERLAY_SECTIONS: [&str; 4] = ["theme", "icons", "layout", "cookbook"];
+
+/// Host and profile overlays, merged in that order: a profile wins over the
+/// host. Each section is deep-merged over the matching cookbook file.
+struct Overlay {
+    files: Vec<PathBuf>,
+    value: toml::Value,
+}
+
+impl Overlay {
+    fn section(&self, name: &str) -> Option<&toml::Value> {
+        self.value.get(name)
+    }
+}
+
+/// A file a cookbook was built from, with the hash of its content. `None`
+/// if it was looked for but missing (`cookbook.toml`, a `?` include).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SourceFile {
+    pub path: PathBuf,
+    pub hash: Option<String>,
+}
+
+/// An `include` entry and the files it resolved to, which can change without
+/// the including file changing (globs, `~`, `$VAR`).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ResolvedInclude {
+    pub from: PathBuf,
+    pub entry: String,
+    pub paths: Vec<PathBuf>,
+}
+
+/// What a baked cookbook was built from. Stored ahead of the cookbook in
+/// `pastry.bin`, which is fresh only while all of it still holds.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Provenance {
+    pub files: Vec<SourceFile>,
+    pub includes: Vec<ResolvedInclude>,
+    /// [`PROFILE_ENV`] when baked
+    pub profile: Option<String>,
+    /// The host overlay looked for, whether or not it existed
+    pub host_overlay: Option<PathBuf>,
+    /// [`Dirs::system`] when baked
+    pub system_dirs: Vec<PathBuf>,
+}
+
+impl Provenance {
+    /// Everything the cookbook in `dirs` is currently built from.
+    pub fn record(dirs: &Dirs) -> Result<Self, ConfigError> {
+        let sources = Cookbook::walk_sources(dirs)?;
+        let host_overlay = Cookbook::host_overlay_path(&dirs.config);
+        let mut paths: Vec<PathBuf> = Vec::new();
+        for path in sources.files.into_iter().chain(host_overlay.clone()) {
+            if !paths.contains(&path) {
+                paths.push(path);
+            }
+        }
+        Ok(Provenance {
+            files: paths
+                .into_iter()
+                .map(|path| SourceFile {
+                    hash: Self::hash(&path),
+                    path,
+                })
+                .collect(),
+            includes: sources.includes,
+            profile: std::env::var(PROFILE_ENV).ok(),
+            host_overlay,
+            system_dirs: dirs.system.clone(),
+        })
+    }
+
+    /// Whether every file still has the recorded content, every include
+    /// still resolves to the same files and the same overlays and system dirs
+    /// are selected.
+    pub fn is_current(&self, dirs: &Dirs) -> bool {
+        self.profile == std::env::var(PROFILE_ENV).ok()
+            && self.host_overlay == Cookbook::host_overlay_path(&dirs.config)
+            && self.system_dirs == dirs.system
+            && self
+                .files
+                .iter()
+                .all(|file| Self::hash(&file.path) == file.hash)
+            && self.includes.iter().all(|inc| {
+                let entry = inc.entry.strip_prefix('?').unwrap_or(&inc.entry);
+                Cookbook::include_paths(&inc.from, entry).is_ok_and(|paths| paths == inc.paths)
+            })
+    }
+
+    fn hash(path: &Path) -> Option<String> {
+        fs::read(path).ok().map(|content| content_hash(&content))
+    }
+}
+
+/// Where [`Cookbook::set_value`] wrote a setting, and what overrides it there.
+#[derive(Debug)]
+pub struct SetOutcome {
+    pub file: PathBuf,
+    /// The file (relative to the config dir) or variable whose value is in
+    /// effect instead, if any.
+    pub shadowed_by: Option<String>,
+}
+
+/// Files and includes met while loading, see [`Cookbook::walk_sources`].
+#[derive(Default)]
+struct Sources {
+    files: Vec<PathBuf>,
+    includes: Vec<ResolvedInclude>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Archive, rkyv::Serialize, rkyv::Deserialize)]
 pub struct Cookbook {
     pub theme: ThemeConfig,
     pub icons: IconsConfig,
@@ -88,7 +384,6 @@ pub struct Cookbook {
     pub dictionary: DictionaryConfig,
 }
 
-use directories::ProjectDirs;
 use std::fs;
 use std::path::{Path, PathBuf};
 use thiserror::Error;
@@ -101,64 +396,78 @@ pub enum ConfigError {
     Io(#[from] std::io::Error),
     #[error("TOML parsing error: {0}")]
     Toml(#[from] toml::de::Error),
+    #[error("TOML serialization error: {0}")]
+    TomlSer(#[from] toml::ser::Error),
+    #[error("Theme '{0}' not found in the themes directory")]
+    ThemeNotFound(String),
+    #[error("Profile '{0}' not found in the profiles directory")]
+    ProfileNotFound(String),
+    #[error("Invalid override {var}: {reason}")]
+    EnvOverride { var: String, reason: String },
+    #[error("TOML parsing error: {0}")]
+    TomlEdit(#[from] toml_edit::TomlError),
+    #[error("'{0}' is not set")]
+    NotSet(String),
+    #[error("Invalid setting {key}: {reason}")]
+    Setting { key: String, reason: String },
+    #[error("Include cycle: {0}")]
+    IncludeCycle(String),
+    #[error("Invalid include '{include}' in {}: {reason}", path.display())]
+    Include {
+        include: String,
+        path: PathBuf,
+        reason: String,
+    },
+    #[error("Invalid color '{key}' in {}: {reason}", path.display())]
+    InvalidColor {
+        key: String,
+        path: PathBuf,
+        reason: String,
+    },
 }
 
 impl Cookbook {
+    /// Loads the cookbook of the [`Dirs`] in effect (see [`Dirs::resolve`]).
     pub fn load() -> Result<Self, ConfigError> {
-        let config_dir = Self::get_config_dir()?;
-        // Use cache dir for binary config
-        let bin_path = if let Ok(cache_dir) = Self::get_cache_dir() {
-            cache_dir.join("pastry.bin")
-        } else {
-            // Fallback to data dir or config dir if cache not available (unlikely)
-            config_dir.join("pastry.bin")
-        };
-
-        Self::load_with_cache(&config_dir, &bin_path, false)
+        Self::load_in(&Dirs::resolve()?)
     }
 
     pub fn load_no_cache() -> Result<Self, ConfigError> {
-        let config_dir = Self::get_config_dir()?;
-        let bin_path = if let Ok(cache_dir) = Self::get_cache_dir() {
-            cache_dir.join("pastry.bin")
-        } else {
-            config_dir.join("pastry.bin")
-        };
+        Self::load_no_cache_in(&Dirs::resolve()?)
+    }
 
-        Self::load_with_cache(&config_dir, &bin_path, true)
+    /// Loads the cookbook in `dirs.config` through its own cache, with
+    /// environment overrides applied.
+    pub fn load_in(dirs: &Dirs) -> Result<Self, ConfigError> {
+        let mut cookbook = Self::load_with_cache(dirs, &dirs.pastry_path(), false)?;
+        cookbook.apply_env_overrides()?;
+        Ok(cookbook)
     }
 
-    pub fn load_from_dir(config_dir: &Path) -> Result<Self, ConfigError> {
-        // For manual loading, we still check the standard cache location relative to project dirs if possible
-        // But if we only have a random dir, we might have to assume cache is there or skip it.
-        // For simplicity in CLI/testing, we'll ask for cache dir again.
+    pub fn load_no_cache_in(dirs: &Dirs) -> Result<Self, ConfigError> {
+        let mut cookbook = Self::load_with_cache(dirs, &dirs.pastry_path(), true)?;
+        cookbook.apply_env_overrides()?;
+        Ok(cookbook)
+    }
 
-        let bin_path = if let Ok(cache_dir) = Self::get_cache_dir() {
-            cache_dir.join("pastry.bin")
-        } else {
-            config_dir.join("pastry.bin")
+    /// Loads the cookbook in `config_dir` alone through the cache kept for
+    /// it, without system layers or environment overrides.
+    pub fn load_from_dir(config_dir: &Path) -> Result<Self, ConfigError> {
+        let dirs = Dirs {
+            config: config_dir.to_path_buf(),
+            system: Vec::new(),
+            ..Dirs::resolve()?
         };
-
-        Self::load_with_cache(config_dir, &bin_path, false)
+        Self::load_with_cache(&dirs, &dirs.pastry_path(), false)
     }
 
-    pub fn load_with_cache(
-        config_dir: &Path,
-        bin_path: &Path,
-        force: bool,
-    ) -> Result<Self, ConfigError> {
+    pub fn load_with_cache(dirs: &Dirs, bin_path: &Path, force: bool) -> Result<Self, ConfigError> {
         // Try loading from binary cache if it exists and is fresh
         if !force
             && bin_path.exists()
-            && Self::is_cache_fresh(bin_path, config_dir)?
-            && let Ok(file) = fs::File::open(bin_path)
+            && let Some(pastry) = Pastry::open(bin_path, dirs)?
         {
-            let mut reader = std::io::BufReader::new(file);
-            // Decode using bincode
-            match bincode::serde::decode_from_std_read::<Cookbook, _, _>(
-                &mut reader,
-                bincode::config::standard(),
-            ) {
+            match pastry.to_cookbook() {
                 Ok(cfg) => {
                     debug!("Loaded configuration from binary cache: {:?}", bin_path);
                     return Ok(cfg);
@@ -174,11 +483,24 @@ impl Cookbook {
             debug!("Binary cache miss or stale (loading from TOMLs)");
         }
 
-        // Fallback: Load from TOML files
-        // Fallback: Load from TOML files
-        let theme: ThemeConfig = Self::load_with_includes(&config_dir.join("theme.toml"))?;
-        let icons: IconsConfig = Self::load_with_includes(&config_dir.join("icons.toml"))?;
-        let layout: LayoutConfig = Self::load_with_includes(&config_dir.join("layout.toml"))?;
+        Self::load_tomls(dirs)
+    }
+
+    /// Loads the cookbook in `dirs` from its TOML files.
+    fn load_tomls(dirs: &Dirs) -> Result<Self, ConfigError> {
+        let config_dir = &dirs.config;
+        let theme = Self::load_theme(dirs)?;
+        let overlay = Self::load_overlay(config_dir)?;
+        let icons: IconsConfig = Self::load_with_includes(
+            dirs,
+            &config_dir.join("icons.toml"),
+            overlay.section("icons"),
+        )?;
+        let layout: LayoutConfig = Self::load_with_includes(
+            dirs,
+            &config_dir.join("layout.toml"),
+            overlay.section("layout"),
+        )?;
 
         // Load System Dictionary (Embedded)
         // This ensures defaults are always available without external files
@@ -189,8 +511,19 @@ impl Cookbook {
             toml::from_str(SYSTEM_DICTIONARY).map_err(ConfigError::Toml)?;
 
         let user_dict_path = config_dir.join("cookbook.toml");
-        if user_dict_path.exists() {
-            let user_dict: DictionaryConfig = Self::load_with_includes(&user_dict_path)?;
+        let user_dict: Option<DictionaryConfig> = if Self::any_layer_exists(dirs, &user_dict_path) {
+            Some(Self::load_with_includes(
+                dirs,
+                &user_dict_path,
+                overlay.section("cookbook"),
+            )?)
+        } else {
+            overlay
+                .section("cookbook")
+                .map(|section| section.clone().try_into())
+                .transpose()?
+        };
+        if let Some(user_dict) = user_dict {
             // Merge user dict into system dict (user overrides system)
             for (curr_k, curr_v) in user_dict.presets {
                 dictionary.presets.insert(curr_k, curr_v);
@@ -215,71 +548,859 @@ impl Cookbook {
         })
     }
 
-    pub fn save_binary(&self, path: &Path) -> Result<(), ConfigError> {
-        if let Some(parent) = path.parent() {
-            fs::create_dir_all(parent).map_err(ConfigError::Io)?;
+    /// Applies every `KITCHN__<SECTION>__<KEY>...` variable in the
+    /// environment (see [`ENV_PREFIX`]). [`Cookbook::load`] does this after
+    /// reading the TOMLs or the cache, so overrides never end up in
+    /// `pastry.bin`.
+    pub fn apply_env_overrides(&mut self) -> Result<(), ConfigError> {
+        self.apply_overrides(std::env::vars_os().filter_map(|(name, value)| {
+            Some((name.into_string().ok()?, value.into_string().ok()?))
+        }))
+    }
+
+    /// Sets one leaf per `KITCHN__A__B__C=value` pair. Segments are
+    /// lowercased; the top level is `theme`, `icons`, `layout` or `cookbook`.
+    /// The value takes the type of the setting it replaces.
+    fn apply_overrides(
+        &mut self,
+        vars: impl IntoIterator<Item = (String, String)>,
+    ) -> Result<(), ConfigError> {
+        let mut overrides: Vec<(String, String)> = vars
+            .into_iter()
+            .filter(|(name, _)| name.starts_with(ENV_PREFIX))
+            .collect();
+        if overrides.is_empty() {
+            return Ok(());
+        }
+        // Deterministic when two variables name the same setting
+        overrides.sort();
+
+        let mut settings = Vec::new();
+        for (var, raw) in &overrides {
+            let path: Vec<String> = var[ENV_PREFIX.len()..]
+                .split("__")
+                .map(str::to_lowercase)
+                .collect();
+            let Some(path) = Self::setting_path(&path) else {
+                return Err(ConfigError::EnvOverride {
+                    var: var.clone(),
+                    reason: "expected KITCHN__<THEME|ICONS|LAYOUT|COOKBOOK>__<KEY>".to_string(),
+                });
+            };
+            settings.push((path, raw.as_str()));
         }
-        let file = fs::File::create(path).map_err(ConfigError::Io)?;
-        let mut writer = std::io::BufWriter::new(file);
 
-        bincode::serde::encode_into_std_write(self, &mut writer, bincode::config::standard())
-            .map_err(|e| ConfigError::Io(std::io::Error::other(e)))?;
+        self.set_values(&settings)
+            .map_err(|(culprit, reason)| ConfigError::EnvOverride {
+                var: match culprit {
+                    Some(i) => overrides[i].0.clone(),
+                    None => overrides
+                        .iter()
+                        .map(|(var, _)| var.as_str())
+                        .collect::<Vec<_>>()
+                        .join(", "),
+                },
+                reason,
+            })
+    }
+
+    /// `path` (`theme`, `icons`, `layout` or `cookbook` first, then at least
+    /// one key) as a path into the cookbook, or `None` if it cannot name a
+    /// setting.
+    fn setting_path(path: &[String]) -> Option<Vec<String>> {
+        let mut path = path.to_vec();
+        if path.first()? == "cookbook" {
+            path[0] = "dictionary".to_string();
+        }
+        let top_level = ["theme", "icons", "layout", "dictionary"];
+        (path.len() >= 2 && top_level.contains(&path[0].as_str())).then_some(path)
+    }
 
+    /// `raw` as a value of the type of `current`, the setting `leaf` holds
+    /// now. New settings are strings.
+    fn typed_value(
+        leaf: &str,
+        current: Option<&toml::Value>,
+        raw: &str,
+    ) -> Result<toml::Value, String> {
+        match current {
+            Some(toml::Value::Boolean(_)) => raw
+                .parse()
+                .map(toml::Value::Boolean)
+                .map_err(|_| format!("expected true or false, got '{}'", raw)),
+            Some(toml::Value::Integer(_)) => raw
+                .parse()
+                .map(toml::Value::Integer)
+                .map_err(|_| format!("expected an integer, got '{}'", raw)),
+            Some(toml::Value::Float(_)) => raw
+                .parse()
+                .map(toml::Value::Float)
+                .map_err(|_| format!("expected a number, got '{}'", raw)),
+            Some(toml::Value::Table(_) | toml::Value::Array(_)) => {
+                Err(format!("'{}' is not a single value", leaf))
+            }
+            _ => Ok(toml::Value::String(raw.to_string())),
+        }
+    }
+
+    /// The value of `key` (`layout.tag.transform`, `cookbook.presets.boot_ok`,
+    /// ...) in this cookbook.
+    pub fn get_value(&self, key: &str) -> Result<toml::Value, ConfigError> {
+        let path = Self::key_path(key)?;
+        let root = toml::Value::try_from(self)?;
+        path.iter()
+            .try_fold(&root, |value, name| value.get(name))
+            .cloned()
+            .ok_or_else(|| ConfigError::NotSet(key.to_string()))
+    }
+
+    /// Sets `key` to `raw` in the user's file for its section (the active
+    /// theme, `icons.toml`, `layout.toml` or `cookbook.toml`), keeping the
+    /// comments and formatting of the file. The value takes the type of the
+    /// setting it replaces and is checked against the whole cookbook; the
+    /// file is put back if the cookbook no longer loads. The file is kept
+    /// when an overlay, the active variant or a [`ENV_PREFIX`] variable
+    /// overrides the new value, and the [`SetOutcome`] names the winner.
+    pub fn set_value(dirs: &Dirs, key: &str, raw: &str) -> Result<SetOutcome, ConfigError> {
+        let config_dir = &dirs.config;
+        let path = Self::key_path(key)?;
+        let invalid = |reason: String| ConfigError::Setting {
+            key: key.to_string(),
+            reason,
+        };
+
+        let mut cookbook = Self::load_tomls(dirs)?;
+        let (leaf, parents) = path.split_last().unwrap();
+        let root = toml::Value::try_from(&cookbook)?;
+        let current = parents
+            .iter()
+            .try_fold(&root, |value, name| value.get(name))
+            .and_then(|table| table.get(leaf));
+        let value = Self::typed_value(leaf, current, raw).map_err(invalid)?;
+        // Colors may be references, which only resolve on load below
+        if path[..2] != ["theme", "colors"] {
+            cookbook
+                .set_values(&[(path.clone(), raw)])
+                .map_err(|(_, reason)| invalid(reason))?;
+        }
+
+        let file = match path[0].as_str() {
+            "theme" => Self::theme_path(dirs)?,
+            "icons" => config_dir.join("icons.toml"),
+            "layout" => config_dir.join("layout.toml"),
+            _ => config_dir.join("cookbook.toml"),
+        };
+        let original = match fs::read_to_string(&file) {
+            Ok(content) => Some(content),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+            Err(e) => return Err(e.into()),
+        };
+        let mut doc: toml_edit::DocumentMut = original.as_deref().unwrap_or_default().parse()?;
+        // A user preset replaces the one it names whole, so editing a preset
+        // from elsewhere (the defaults, a system layer) starts from a copy
+        if let [section, presets, name, _] = &path[..]
+            && section == "dictionary"
+            && presets == "presets"
+            && doc.get(presets).and_then(|p| p.get(name)).is_none()
+            && let Some(preset) = root
+                .get(section)
+                .and_then(|d| d.get(presets))
+                .and_then(|p| p.get(name))
+                .and_then(|p| p.as_table())
+        {
+            for (field, field_value) in preset {
+                let field_path = [presets.clone(), name.clone(), field.clone()];
+                edit_value(doc.as_table_mut(), &field_path, field_value.clone())
+                    .map_err(invalid)?;
+            }
+        }
+        edit_value(doc.as_table_mut(), &path[1..], value.clone()).map_err(invalid)?;
+        fs::write(&file, doc.to_string())?;
+
+        // Colors and references are only resolved on load
+        let mut effective = match Self::load_tomls(dirs) {
+            Ok(cookbook) => cookbook,
+            Err(e) => {
+                match original {
+                    Some(original) => fs::write(&file, original)?,
+                    None => fs::remove_file(&file)?,
+                }
+                return Err(invalid(e.to_string()));
+            }
+        };
+        effective.apply_env_overrides()?;
+        let shadowed_by = (!effective.holds(&path, raw, &value)).then(|| {
+            let variant = effective.theme.variant.as_deref();
+            Self::shadowing_source(dirs, &path, variant)
+        });
+        Ok(SetOutcome { file, shadowed_by })
+    }
+
+    /// Whether the setting at `path` reads as `raw`, typed as `value`.
+    /// Colors are compared once resolved, so `@primary` holds wherever the
+    /// key resolves to the color `primary` has here.
+    fn holds(&self, path: &[String], raw: &str, value: &toml::Value) -> bool {
+        if let [theme, colors, key] = path
+            && theme == "theme"
+            && colors == "colors"
+        {
+            // Spellings are plain colors, references already followed
+            let mut raw_colors = self.theme.spellings.clone();
+            raw_colors.insert(key.clone(), raw.to_string());
+            let wanted = color::resolve_colors(&raw_colors)
+                .ok()
+                .and_then(|resolved| resolved.get(key).copied());
+            return wanted.is_some() && wanted == self.theme.colors.get(key).copied();
+        }
+        toml::Value::try_from(self).is_ok_and(|root| {
+            path.iter().try_fold(&root, |value, name| value.get(name)) == Some(value)
+        })
+    }
+
+    /// What overrides the user's file at setting `path`: a [`ENV_PREFIX`]
+    /// variable, the `variant` table of the theme or an overlay, or an
+    /// overlay. Files are shown relative to the config dir.
+    fn shadowing_source(dirs: &Dirs, path: &[String], variant: Option<&str>) -> String {
+        let shown = |file: &Path| {
+            file.strip_prefix(&dirs.config)
+                .unwrap_or(file)
+                .display()
+                .to_string()
+        };
+
+        // Variables are applied last; sorted, so the last one wins
+        let mut vars: Vec<String> = std::env::vars_os()
+            .filter_map(|(name, _)| name.into_string().ok())
+            .filter(|name| {
+                name.strip_prefix(ENV_PREFIX).is_some_and(|rest| {
+                    let segments: Vec<String> = rest.split("__").map(str::to_lowercase).collect();
+                    Self::setting_path(&segments).as_deref() == Some(path)
+                })
+            })
+            .collect();
+        vars.sort();
+        if let Some(var) = vars.pop() {
+            return var;
+        }
+
+        let overlays = Self::overlay_files(&dirs.config).unwrap_or_default();
+        let (leaf, parents) = path.split_last().unwrap();
+        // Variant tables are merged over `[colors]` from every file
+        if let (Some(variant), [theme, colors]) = (variant, parents)
+            && theme == "theme"
+            && colors == "colors"
+        {
+            let in_overlay = overlays.iter().rev().find_map(|overlay| {
+                Self::defining_file(overlay, &["theme", "variants", variant], leaf)
+            });
+            let in_theme = || {
+                let theme = Self::theme_path(dirs).ok()?;
+                Self::layers(dirs, &theme)
+                    .iter()
+                    .rev()
+                    .find_map(|layer| Self::defining_file(layer, &["variants", variant], leaf))
+            };
+            if let Some(file) = in_overlay.or_else(in_theme) {
+                return format!("[variants.{}] in {}", variant, shown(&file));
+            }
+        }
+
+        let section = match path[0].as_str() {
+            "dictionary" => "cookbook",
+            section => section,
+        };
+        let table: Vec<&str> = std::iter::once(section)
+            .chain(parents[1..].iter().map(String::as_str))
+            .collect();
+        overlays
+            .iter()
+            .rev()
+            .find_map(|overlay| Self::defining_file(overlay, &table, leaf))
+            .map_or_else(|| "another file".to_string(), |file| shown(&file))
+    }
+
+    /// `key` split at dots, as a [setting path](Self::setting_path).
+    fn key_path(key: &str) -> Result<Vec<String>, ConfigError> {
+        let path: Vec<String> = key.split('.').map(str::to_string).collect();
+        Self::setting_path(&path).ok_or_else(|| ConfigError::Setting {
+            key: key.to_string(),
+            reason: "expected theme, icons, layout or cookbook, then a key".to_string(),
+        })
+    }
+
+    /// Sets the leaf at each path (see [`Self::setting_path`]) to its raw
+    /// value, typed like the setting it replaces. On error, the index of the
+    /// setting to blame (`None` if it cannot be told) and the reason.
+    fn set_values(
+        &mut self,
+        settings: &[(Vec<String>, &str)],
+    ) -> Result<(), (Option<usize>, String)> {
+        let mut root = toml::Value::try_from(&*self).map_err(|e| (None, e.to_string()))?;
+        for (i, (path, raw)) in settings.iter().enumerate() {
+            let invalid = |reason: String| (Some(i), reason);
+            let (leaf, parents) = path.split_last().unwrap();
+            let mut table = root.as_table_mut().unwrap();
+            for name in parents {
+                table = table
+                    .entry(name.as_str())
+                    .or_insert_with(|| toml::Value::Table(toml::map::Map::new()))
+                    .as_table_mut()
+                    .ok_or_else(|| invalid(format!("'{}' is not a table", name)))?;
+            }
+            let value = Self::typed_value(leaf, table.get(leaf), raw).map_err(invalid)?;
+            table.insert(leaf.clone(), value);
+        }
+
+        // Several settings may build one new entry (a preset needs `level`
+        // and `msg`), so the result is only checked as a whole. Errors blame
+        // the setting under the failing key.
+        let paths: Vec<String> = settings.iter().map(|(path, _)| path.join(".")).collect();
+        let culprit = |at: &str| {
+            paths
+                .iter()
+                .position(|path| path.starts_with(at) || at.starts_with(path.as_str()))
+        };
+        let mut unknown = None;
+        let mut track = |key: serde_ignored::Path| {
+            unknown.get_or_insert_with(|| key.to_string());
+        };
+        let ignored = serde_ignored::Deserializer::new(root, &mut track);
+        let cookbook: Cookbook = serde_path_to_error::deserialize(ignored).map_err(|e| {
+            let at = e.path().to_string();
+            (culprit(&at), e.inner().message().to_string())
+        })?;
+        if let Some(key) = unknown {
+            return Err((culprit(&key), format!("'{}' is not a setting", key)));
+        }
+
+        // Spellings are not part of the serialized cookbook
+        let mut cookbook = cookbook;
+        cookbook.theme.spellings = std::mem::take(&mut self.theme.spellings);
+        for (path, raw) in settings {
+            if let [theme, colors, key] = path.as_slice()
+                && theme == "theme"
+                && colors == "colors"
+            {
+                cookbook
+                    .theme
+                    .spellings
+                    .insert(key.clone(), raw.trim().to_string());
+            }
+        }
+        *self = cookbook;
         Ok(())
     }
 
-    fn is_cache_fresh(bin_path: &Path, config_dir: &Path) -> Result<bool, ConfigError> {
-        let bin_meta = fs::metadata(bin_path)?;
-        let bin_mtime = bin_meta.modified()?;
+    /// Bakes the cookbook to `path` (see [`Pastry`]), recording the
+    /// [`Provenance`] of the TOMLs in `dirs` it was loaded from.
+    pub fn save_binary(&self, path: &Path, dirs: &Dirs) -> Result<(), ConfigError> {
+        Pastry::write(self, path, dirs)
+    }
+
+    /// Whether the binary cache at `bin_path` can be used for `dirs`
+    /// (see [`Pastry::open`]).
+    pub fn is_cache_fresh(bin_path: &Path, dirs: &Dirs) -> Result<bool, ConfigError> {
+        Ok(Pastry::open(bin_path, dirs)?.is_some())
+    }
+
+    /// The theme file in effect: `themes/<name>.toml` if a theme was selected
+    /// with [`Cookbook::select_theme`], `theme.toml` otherwise.
+    pub fn theme_path(dirs: &Dirs) -> Result<PathBuf, ConfigError> {
+        match Self::selected_theme(&dirs.config)? {
+            Some(name) => Self::library_theme_path(dirs, &name),
+            None => Ok(dirs.config.join("theme.toml")),
+        }
+    }
+
+    /// Name of the selected library theme, if any.
+    pub fn selected_theme(config_dir: &Path) -> Result<Option<String>, ConfigError> {
+        Ok(ThemeSelection::load(config_dir)?.name)
+    }
+
+    /// Makes `themes/<name>.toml` the active theme, or goes back to
+    /// `theme.toml` with `None`. The theme must parse before it is selected.
+    pub fn select_theme(dirs: &Dirs, name: Option<&str>) -> Result<(), ConfigError> {
+        let config_dir = &dirs.config;
+        if let Some(name) = name {
+            let path = Self::library_theme_path(dirs, name)?;
+            let overlay = Self::load_overlay(config_dir)?;
+            let variant = ThemeSelection::load(config_dir)?.variant;
+            Self::load_theme_file(dirs, &path, variant, &overlay)?;
+        }
+        // The variant carries over: a dark desktop stays dark across themes
+        let mut selection = ThemeSelection::load(config_dir)?;
+        selection.name = name.map(str::to_string);
+        selection.save(config_dir)
+    }
+
+    /// Overrides the variant of whichever theme is active, or falls back to
+    /// the theme's own default with `None`.
+    pub fn select_variant(config_dir: &Path, variant: Option<&str>) -> Result<(), ConfigError> {
+        let mut selection = ThemeSelection::load(config_dir)?;
+        selection.variant = variant.map(str::to_string);
+        selection.save(config_dir)
+    }
+
+    /// Names of the themes in the `themes/` directory, the system ones
+    /// included, sorted.
+    pub fn themes(dirs: &Dirs) -> Result<Vec<String>, ConfigError> {
+        let mut names = Vec::new();
+        for dir in Self::layers(dirs, &dirs.config.join(THEMES_DIR)) {
+            if !dir.is_dir() {
+                continue;
+            }
+            for entry in fs::read_dir(dir)? {
+                let path = entry?.path();
+                if path.extension().is_some_and(|ext| ext == "toml")
+                    && let Some(stem) = path.file_stem()
+                {
+                    names.push(stem.to_string_lossy().into_owned());
+                }
+            }
+        }
+        names.sort();
+        names.dedup();
+        Ok(names)
+    }
+
+    /// Loads the active theme with the overlays and its variant's colors
+    /// merged in.
+    fn load_theme(dirs: &Dirs) -> Result<ThemeConfig, ConfigError> {
+        let path = Self::theme_path(dirs)?;
+        let overlay = Self::load_overlay(&dirs.config)?;
+        let variant = ThemeSelection::load(&dirs.config)?.variant;
+        Self::load_theme_file(dirs, &path, variant, &overlay)
+    }
+
+    /// Loads the theme at `path` over its system layers, with the overlay
+    /// and the `selected` variant (or the theme's own default) merged in and
+    /// every color resolved and validated.
+    fn load_theme_file(
+        dirs: &Dirs,
+        path: &Path,
+        selected: Option<String>,
+        overlay: &Overlay,
+    ) -> Result<ThemeConfig, ConfigError> {
+        debug!("Loading config file: {:?}", path);
+        let mut value = Self::load_layered(dirs, path, &mut Sources::default())?;
+        if let Some(section) = overlay.section("theme") {
+            Self::deep_merge(&mut value, section.clone());
+        }
+
+        let variant = selected.or_else(|| {
+            value
+                .get("variant")
+                .and_then(|v| v.as_str())
+                .map(str::to_string)
+        });
+
+        if let Some(variant) = &variant
+            && let toml::Value::Table(table) = &mut value
+        {
+            let overrides = table.get("variants").and_then(|v| v.get(variant)).cloned();
+            match overrides {
+                Some(overrides) => {
+                    let colors = table
+                        .entry("colors")
+                        .or_insert_with(|| toml::Value::Table(toml::map::Map::new()));
+                    Self::deep_merge(colors, overrides);
+                }
+                // Themes without this variant still load, with their base colors
+                None => debug!("Theme has no '{}' variant, using base colors", variant),
+            }
+            table.insert("variant".to_string(), toml::Value::String(variant.clone()));
+        }
+
+        // After the variant merge, so `@bg` follows the variant's bg
+        let invalid = |key: &str, reason: String| ConfigError::InvalidColor {
+            key: key.to_string(),
+            path: Self::color_source(
+                &Self::layers(dirs, path),
+                &overlay.files,
+                key,
+                variant.as_deref(),
+            ),
+            reason,
+        };
+        let mut raw = HashMap::new();
+        if let Some(colors) = value.get("colors").and_then(|v| v.as_table()) {
+            for (key, v) in colors {
+                let v = v
+                    .as_str()
+                    .ok_or_else(|| invalid(key, format!("expected a string, found {}", v)))?;
+                raw.insert(key.clone(), v.to_string());
+            }
+        }
+        let resolved = color::resolve_colors(&raw).map_err(|e| invalid(e.key(), e.to_string()))?;
+        let spellings = color::spell_colors(&raw, &resolved);
+        if let toml::Value::Table(table) = &mut value {
+            let colors = resolved
+                .into_iter()
+                .map(|(key, color)| (key, toml::Value::String(color.to_string())))
+                .collect();
+            table.insert("colors".to_string(), toml::Value::Table(colors));
+        }
+
+        let mut theme: ThemeConfig = value.try_into()?;
+        theme.spellings = spellings;
+        if let Some(schedule) = &theme.settings.schedule {
+            scheduler::validate(schedule).map_err(|e| ConfigError::Setting {
+                key: "settings.schedule".to_string(),
+                reason: e.to_string(),
+            })?;
+        }
+        Ok(theme)
+    }
+
+    /// The file among the theme `layers`, their includes and the `overlays`
+    /// whose value for color `key` won, for error messages. Falls back to the
+    /// user's theme file.
+    fn color_source(
+        layers: &[PathBuf],
+        overlays: &[PathBuf],
+        key: &str,
+        variant: Option<&str>,
+    ) -> PathBuf {
+        // Variant overrides beat `[colors]` from any file
+        let mut tables = Vec::new();
+        if let Some(variant) = variant {
+            tables.push(vec!["variants", variant]);
+        }
+        tables.push(vec!["colors"]);
+
+        tables
+            .iter()
+            .find_map(|table| {
+                // Later overlays are merged over earlier ones, and all over the theme
+                let in_overlay = overlays.iter().rev().find_map(|overlay| {
+                    let table: Vec<&str> = std::iter::once("theme").chain(table.clone()).collect();
+                    Self::defining_file(overlay, &table, key)
+                });
+                in_overlay.or_else(|| {
+                    layers
+                        .iter()
+                        .rev()
+                        .find_map(|layer| Self::defining_file(layer, table, key))
+                })
+            })
+            .unwrap_or_else(|| layers[layers.len() - 1].clone())
+    }
+
+    fn defining_file(path: &Path, table: &[&str], key: &str) -> Option<PathBuf> {
+        let value: toml::Value = toml::from_str(&fs::read_to_string(path).ok()?).ok()?;
+        let mut current = Some(&value);
+        for name in table {
+            current = current.and_then(|v| v.get(name));
+        }
+        if current.and_then(|t| t.get(key)).is_some() {
+            return Some(path.to_path_buf());
+        }
+
+        // Later includes are merged over earlier ones
+        let includes = value.get("include").and_then(|v| v.as_array())?;
+        includes.iter().rev().find_map(|inc| {
+            let entry = inc.as_str()?;
+            let paths = Self::include_paths(path, entry.trim_start_matches('?')).ok()?;
+            paths
+                .iter()
+                .rev()
+                .find_map(|inc_path| Self::defining_file(inc_path, table, key))
+        })
+    }
+
+    /// `hosts/<hostname>.toml`, whether or not it exists. The hostname comes
+    /// from [`HOST_ENV`] if set. `None` if the hostname cannot be determined.
+    pub fn host_overlay_path(config_dir: &Path) -> Option<PathBuf> {
+        let host = match std::env::var(HOST_ENV) {
+            Ok(host) => host,
+            Err(_) => hostname::get().ok()?.into_string().ok()?,
+        };
+        if host.is_empty() || host.contains(['/', '\\']) {
+            return None;
+        }
+        Some(config_dir.join(HOSTS_DIR).join(format!("{}.toml", host)))
+    }
+
+    /// `profiles/<name>.toml` for the profile named by [`PROFILE_ENV`], which
+    /// must exist. `None` if no profile is set.
+    pub fn profile_overlay_path(config_dir: &Path) -> Result<Option<PathBuf>, ConfigError> {
+        let Ok(name) = std::env::var(PROFILE_ENV) else {
+            return Ok(None);
+        };
+        let path = config_dir.join(PROFILES_DIR).join(format!("{}.toml", name));
+        if name.is_empty() || name.contains(['/', '\\']) || !path.is_file() {
+            return Err(ConfigError::ProfileNotFound(name));
+        }
+        Ok(Some(path))
+    }
+
+    /// The overlays in effect: the host file if there is one, then the
+    /// profile.
+    pub fn overlay_files(config_dir: &Path) -> Result<Vec<PathBuf>, ConfigError> {
+        let mut files: Vec<PathBuf> = Self::host_overlay_path(config_dir)
+            .filter(|path| path.is_file())
+            .into_iter()
+            .collect();
+        files.extend(Self::profile_overlay_path(config_dir)?);
+        Ok(files)
+    }
+
+    fn load_overlay(config_dir: &Path) -> Result<Overlay, ConfigError> {
+        let files = Self::overlay_files(config_dir)?;
+        let mut value = toml::Value::Table(toml::map::Map::new());
+        for file in &files {
+            debug!("Applying overlay: {:?}", file);
+            Self::deep_merge(
+                &mut value,
+                Self::load_value_recursive(file, &mut Sources::default())?,
+            );
+        }
+        Ok(Overlay { files, value })
+    }
 
-        // Check if the running executable is newer than the cache
-        // This ensures that if we update the embedded defaults, the cache is invalidated
-        if let Ok(exe_path) = std::env::current_exe()
-            && let Ok(exe_meta) = fs::metadata(&exe_path)
-            && let Ok(exe_mtime) = exe_meta.modified()
-            && exe_mtime > bin_mtime
+    fn library_theme_path(dirs: &Dirs, name: &str) -> Result<PathBuf, ConfigError> {
+        let path = dirs.config.join(THEMES_DIR).join(format!("{}.toml", name));
+        if name.is_empty()
+            || name.contains(['/', '\\'])
+            || !Self::layers(dirs, &path).iter().any(|p| p.is_file())
         {
-            return Ok(false); // Executable is newer
+            return Err(ConfigError::ThemeNotFound(name.to_string()));
         }
+        Ok(path)
+    }
 
-        let toml_files = ["theme.toml", "icons.toml", "layout.toml", "cookbook.toml"];
-        for file in toml_files {
-            let path = config_dir.join(file);
-            if path.exists() {
-                let meta = fs::metadata(&path)?;
-                let mtime = meta.modified()?;
-                if mtime > bin_mtime {
-                    return Ok(false); // Source is newer
+    /// Every file the cookbook in `dirs` is built from: the top-level TOMLs
+    /// (including a not-yet-created `cookbook.toml`) and their system layers,
+    /// the theme selection and everything they pull in through `include`.
+    pub fn source_files(dirs: &Dirs) -> Result<Vec<PathBuf>, ConfigError> {
+        Ok(Self::walk_sources(dirs)?.files)
+    }
+
+    fn walk_sources(dirs: &Dirs) -> Result<Sources, ConfigError> {
+        let config_dir = &dirs.config;
+        let mut sources = Sources {
+            files: vec![config_dir.join(THEME_SELECTION_FILE)],
+            includes: Vec::new(),
+        };
+        Self::load_layered(dirs, &Self::theme_path(dirs)?, &mut sources)?;
+        for file in ["icons.toml", "layout.toml"] {
+            Self::load_layered(dirs, &config_dir.join(file), &mut sources)?;
+        }
+        let user_dict_path = config_dir.join("cookbook.toml");
+        if Self::any_layer_exists(dirs, &user_dict_path) {
+            Self::load_layered(dirs, &user_dict_path, &mut sources)?;
+        } else {
+            sources.files.extend(Self::layers(dirs, &user_dict_path));
+        }
+        for overlay in Self::overlay_files(config_dir)? {
+            Self::load_value_recursive(&overlay, &mut sources)?;
+        }
+        Ok(sources)
+    }
+
+    /// Keys in the cookbook that kitchn does not read (typos, settings from
+    /// older versions) as `(file, dotted.path)`. Includes are merged first, so
+    /// a key from an included file is reported against the top-level file.
+    pub fn unknown_keys(dirs: &Dirs) -> Result<Vec<(PathBuf, String)>, ConfigError> {
+        let config_dir = &dirs.config;
+        let mut unknown = Vec::new();
+
+        Self::collect_layer_unknown::<ThemeConfig>(dirs, &Self::theme_path(dirs)?, &mut unknown)?;
+        Self::collect_layer_unknown::<IconsConfig>(
+            dirs,
+            &config_dir.join("icons.toml"),
+            &mut unknown,
+        )?;
+        Self::collect_layer_unknown::<LayoutConfig>(
+            dirs,
+            &config_dir.join("layout.toml"),
+            &mut unknown,
+        )?;
+        Self::collect_layer_unknown::<DictionaryConfig>(
+            dirs,
+            &config_dir.join("cookbook.toml"),
+            &mut unknown,
+        )?;
+
+        // Overlay sections are partial, so only their names are checked
+        for path in Self::overlay_files(config_dir)? {
+            let overlay = Self::load_value_recursive(&path, &mut Sources::default())?;
+            if let Some(table) = overlay.as_table() {
+                for key in table.keys() {
+                    if !OVERLAY_SECTIONS.contains(&key.as_str()) && key != "include" {
+                        unknown.push((path.clone(), key.clone()));
+                    }
                 }
             }
         }
-        Ok(true)
+
+        let selection_path = config_dir.join(THEME_SELECTION_FILE);
+        if selection_path.exists() {
+            let selection = toml::from_str(&fs::read_to_string(&selection_path)?)?;
+            Self::collect_unknown::<ThemeSelection>(&selection_path, selection, &mut unknown)?;
+        }
+
+        Ok(unknown)
+    }
+
+    /// [`Self::collect_unknown`] for `path` merged over its layers. Layers
+    /// may rely on each other for required keys, so the merged value is
+    /// checked, and each key reported against the most important layer that
+    /// sets it.
+    fn collect_layer_unknown<T: for<'a> Deserialize<'a>>(
+        dirs: &Dirs,
+        path: &Path,
+        unknown: &mut Vec<(PathBuf, String)>,
+    ) -> Result<(), ConfigError> {
+        let layers = Self::existing_layers(dirs, path);
+        if layers.is_empty() {
+            return Ok(());
+        }
+        let mut merged = Self::load_layered(dirs, path, &mut Sources::default())?;
+        // Color names are free-form, and the values are checked on load
+        if let Some(colors) = merged.get_mut("colors") {
+            *colors = toml::Value::Table(toml::map::Map::new());
+        }
+        let mut keys = Vec::new();
+        Self::collect_unknown::<T>(path, merged, &mut keys)?;
+        if keys.is_empty() {
+            return Ok(());
+        }
+
+        let values = layers
+            .iter()
+            .map(|layer| Self::load_value_recursive(layer, &mut Sources::default()))
+            .collect::<Result<Vec<_>, _>>()?;
+        for (_, key) in keys {
+            let layer = layers
+                .iter()
+                .zip(&values)
+                .rev()
+                .find(|(_, value)| {
+                    key.split('.')
+                        .try_fold(*value, |value, name| value.get(name))
+                        .is_some()
+                })
+                .map_or(path, |(layer, _)| layer.as_path());
+            unknown.push((layer.to_path_buf(), key));
+        }
+        Ok(())
+    }
+
+    fn collect_unknown<T: for<'a> Deserialize<'a>>(
+        path: &Path,
+        value: toml::Value,
+        unknown: &mut Vec<(PathBuf, String)>,
+    ) -> Result<(), ConfigError> {
+        let _: T = serde_ignored::deserialize(value, |key| {
+            unknown.push((path.to_path_buf(), key.to_string()));
+        })?;
+        Ok(())
     }
 
-    fn get_config_dir() -> Result<PathBuf, ConfigError> {
-        // Use XDG_CONFIG_HOME/kitchn or ~/.config/kitchn
-        if let Some(proj_dirs) = ProjectDirs::from("", "", "kitchn") {
-            return Ok(proj_dirs.config_dir().to_path_buf());
+    /// The layers of `path`, a file in `dirs.config`, in merge order: the
+    /// same file in every [system dir](Dirs::system), least important first,
+    /// then `path` itself. Missing ones included.
+    pub fn layers(dirs: &Dirs, path: &Path) -> Vec<PathBuf> {
+        let mut layers = Vec::new();
+        if let Ok(relative) = path.strip_prefix(&dirs.config) {
+            for dir in dirs.system.iter().rev() {
+                let layer = dir.join(relative);
+                if layer != path && !layers.contains(&layer) {
+                    layers.push(layer);
+                }
+            }
         }
-        Err(ConfigError::ConfigDirNotFound)
+        layers.push(path.to_path_buf());
+        layers
     }
 
-    fn get_cache_dir() -> Result<PathBuf, ConfigError> {
-        // Use XDG_CACHE_HOME/kitchn or ~/.cache/kitchn
-        if let Some(proj_dirs) = ProjectDirs::from("", "", "kitchn") {
-            return Ok(proj_dirs.cache_dir().to_path_buf());
+    fn existing_layers(dirs: &Dirs, path: &Path) -> Vec<PathBuf> {
+        Self::layers(dirs, path)
+            .into_iter()
+            .filter(|layer| layer.exists())
+            .collect()
+    }
+
+    fn any_layer_exists(dirs: &Dirs, path: &Path) -> bool {
+        !Self::existing_layers(dirs, path).is_empty()
+    }
+
+    /// Loads `path` deep-merged over its system [layers](Self::layers). The
+    /// user's file may be missing if a system one exists; missing layers are
+    /// still recorded in `sources`, since creating one changes the cookbook.
+    fn load_layered(
+        dirs: &Dirs,
+        path: &Path,
+        sources: &mut Sources,
+    ) -> Result<toml::Value, ConfigError> {
+        let layers = Self::layers(dirs, path);
+        let mut merged: Option<toml::Value> = None;
+        for layer in &layers[..layers.len() - 1] {
+            if !layer.exists() {
+                sources.files.push(layer.clone());
+                continue;
+            }
+            debug!("Loading system layer: {:?}", layer);
+            let value = Self::load_value_recursive(layer, sources)?;
+            match &mut merged {
+                Some(merged) => Self::deep_merge(merged, value),
+                None => merged = Some(value),
+            }
         }
-        Err(ConfigError::ConfigDirNotFound)
+
+        // Without a system layer, the user's file is required
+        let Some(mut merged) = merged else {
+            return Self::load_value_recursive(path, sources);
+        };
+        if path.exists() {
+            Self::deep_merge(&mut merged, Self::load_value_recursive(path, sources)?);
+        } else {
+            sources.files.push(path.to_path_buf());
+        }
+        Ok(merged)
     }
 
-    fn load_with_includes<T: for<'a> Deserialize<'a>>(path: &Path) -> Result<T, ConfigError> {
+    fn load_with_includes<T: for<'a> Deserialize<'a>>(
+        dirs: &Dirs,
+        path: &Path,
+        overlay: Option<&toml::Value>,
+    ) -> Result<T, ConfigError> {
         debug!("Loading config file: {:?}", path);
-        let value = Self::load_value_recursive(path)?;
+        let mut value = Self::load_layered(dirs, path, &mut Sources::default())?;
+        if let Some(overlay) = overlay {
+            Self::deep_merge(&mut value, overlay.clone());
+        }
         let config: T = value.try_into()?;
         Ok(config)
     }
 
-    fn load_value_recursive(path: &Path) -> Result<toml::Value, ConfigError> {
+    fn load_value_recursive(
+        path: &Path,
+        sources: &mut Sources,
+    ) -> Result<toml::Value, ConfigError> {
+        Self::load_value_chain(path, sources, &mut Vec::new())
+    }
+
+    /// [`Self::load_value_recursive`], with `chain` holding the files that
+    /// include `path`, outermost first.
+    fn load_value_chain(
+        path: &Path,
+        sources: &mut Sources,
+        chain: &mut Vec<PathBuf>,
+    ) -> Result<toml::Value, ConfigError> {
+        let real = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+        if chain.contains(&real) {
+            let base = chain[0].parent().unwrap_or(Path::new(""));
+            let shown: Vec<String> = chain
+                .iter()
+                .chain(std::iter::once(&real))
+                .map(|p| p.strip_prefix(base).unwrap_or(p).display().to_string())
+                .collect();
+            return Err(ConfigError::IncludeCycle(shown.join(" -> ")));
+        }
+
+        sources.files.push(path.to_path_buf());
         let content = fs::read_to_string(path)?;
         let current_value: toml::Value = toml::from_str(&content)?;
 
@@ -287,20 +1408,33 @@ impl Cookbook {
         let mut bases = Vec::new();
 
         if let Some(includes) = current_value.get("include").and_then(|v| v.as_array()) {
+            chain.push(real);
             for inc in includes {
                 if let Some(inc_str) = inc.as_str() {
-                    // Resolve path relative to current config file
-                    let inc_path = if let Some(parent) = path.parent() {
-                        parent.join(inc_str)
-                    } else {
-                        PathBuf::from(inc_str)
+                    // `?local.toml` may be missing
+                    let (optional, entry) = match inc_str.strip_prefix('?') {
+                        Some(entry) => (true, entry),
+                        None => (false, inc_str),
                     };
-
-                    // Recursive load
-                    let base_value = Self::load_value_recursive(&inc_path)?;
-                    bases.push(base_value);
+                    let paths = Self::include_paths(path, entry)?;
+                    sources.includes.push(ResolvedInclude {
+                        from: path.to_path_buf(),
+                        entry: inc_str.to_string(),
+                        paths: paths.clone(),
+                    });
+                    for inc_path in paths {
+                        if optional && !inc_path.exists() {
+                            debug!("Optional include not found: {:?}", inc_path);
+                            // Still a source: creating it changes the cookbook
+                            sources.files.push(inc_path);
+                            continue;
+                        }
+                        let base_value = Self::load_value_chain(&inc_path, sources, chain)?;
+                        bases.push(base_value);
+                    }
                 }
             }
+            chain.pop();
         }
 
         // Merge bases first (in order), then current on top
@@ -318,6 +1452,29 @@ impl Cookbook {
         Ok(final_value)
     }
 
+    /// The files `entry` (an `include` of `path`, without its `?` marker)
+    /// stands for, in merge order. `~` and `$VAR`/`${VAR}` are expanded and
+    /// relative paths resolved against `path`'s directory. A glob yields its
+    /// matches in alphabetical order, possibly none.
+    fn include_paths(path: &Path, entry: &str) -> Result<Vec<PathBuf>, ConfigError> {
+        let invalid = |reason: String| ConfigError::Include {
+            include: entry.to_string(),
+            path: path.to_path_buf(),
+            reason,
+        };
+        let expanded = expand_include(entry).map_err(invalid)?;
+        let inc_path = path.parent().unwrap_or(Path::new("")).join(expanded);
+        if !entry.contains(['*', '?', '[']) {
+            return Ok(vec![inc_path]);
+        }
+
+        let pattern = inc_path.to_string_lossy();
+        let matches = glob::glob(&pattern).map_err(|e| invalid(e.to_string()))?;
+        matches
+            .map(|m| m.map_err(|e| invalid(e.to_string())))
+            .collect()
+    }
+
     fn deep_merge(target: &mut toml::Value, source: toml::Value) {
         match (target, source) {
             (toml::Value::Table(t), toml::Value::Table(s)) => {
@@ -334,32 +1491,117 @@ impl Cookbook {
     }
 }
 
+/// Sets `path` in `table` to `value`, creating the tables on the way. An
+/// existing value keeps its comments and spacing.
+fn edit_value(
+    table: &mut dyn toml_edit::TableLike,
+    path: &[String],
+    value: toml::Value,
+) -> Result<(), String> {
+    let (name, rest) = path.split_first().unwrap();
+    if !rest.is_empty() {
+        if table.get(name).is_none() {
+            // `[presets.boot_ok]` rather than an empty `[presets]` above it
+            let mut new = toml_edit::Table::new();
+            new.set_implicit(true);
+            table.insert(name, toml_edit::Item::Table(new));
+        }
+        let inner = table
+            .get_mut(name)
+            .and_then(|item| item.as_table_like_mut())
+            .ok_or_else(|| format!("'{}' is not a table", name))?;
+        return edit_value(inner, rest, value);
+    }
+
+    let mut value = match value {
+        toml::Value::String(s) => toml_edit::Value::from(s),
+        toml::Value::Integer(i) => toml_edit::Value::from(i),
+        toml::Value::Float(f) => toml_edit::Value::from(f),
+        toml::Value::Boolean(b) => toml_edit::Value::from(b),
+        _ => return Err(format!("'{}' is not a single value", name)),
+    };
+    match table.get_mut(name) {
+        Some(toml_edit::Item::Value(current)) => {
+            *value.decor_mut() = current.decor().clone();
+            *current = value;
+        }
+        Some(toml_edit::Item::None) | None => {
+            table.insert(name, toml_edit::Item::Value(value));
+        }
+        Some(_) => return Err(format!("'{}' is not a single value", name)),
+    }
+    Ok(())
+}
+
+/// Expands a leading `~` and `$VAR`/`${VAR}` references in an include path.
+fn expand_include(entry: &str) -> Result<String, String> {
+    let mut expanded = String::new();
+    let mut rest = entry;
+    if rest == "~" || rest.starts_with("~/") {
+        let home = directories::BaseDirs::new().ok_or("could not determine home directory")?;
+        expanded.push_str(&home.home_dir().to_string_lossy());
+        rest = &rest[1..];
+    }
+
+    while let Some(start) = rest.find('$') {
+        expanded.push_str(&rest[..start]);
+        let after = &rest[start + 1..];
+        let (name, len) = match after.strip_prefix('{') {
+            Some(braced) => {
+                let end = braced.find('}').ok_or("unclosed '${'")?;
+                (&braced[..end], end + 2)
+            }
+            None => {
+                let end = after
+                    .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                    .unwrap_or(after.len());
+                (&after[..end], end)
+            }
+        };
+        if name.is_empty() {
+            return Err("'$' without a variable name".to_string());
+        }
+        let value =
+            std::env::var(name).map_err(|_| format!("environment variable {} is not set", name))?;
+        expanded.push_str(&value);
+        rest = &after[len..];
+    }
+    expanded.push_str(rest);
+    Ok(expanded)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use tempfile::tempdir;
 
-    #[test]
-    fn test_binary_serialization() {
-        let dir = tempdir().unwrap();
-        let config_path = dir.path().join("pastry.bin");
+    /// The cookbook in `config_dir` alone, without system layers.
+    fn dirs(config_dir: &Path) -> Dirs {
+        Dirs {
+            config: config_dir.to_path_buf(),
+            ..Dirs::under(config_dir)
+        }
+    }
 
-        // Create a minimal config for testing
-        let config = Cookbook {
+    fn minimal_cookbook() -> Cookbook {
+        Cookbook {
             theme: ThemeConfig {
                 meta: ThemeMeta {
                     name: "test_theme".to_string(),
                 },
                 settings: ThemeSettings {
-                    active_icons: "nerdfont".to_string(),
+                    active_icons: IconChain::One("nerdfont".into()),
+                    schedule: None,
                 },
                 colors: HashMap::new(),
+                spellings: HashMap::new(),
                 fonts: HashMap::new(),
                 include: None,
+                variants: HashMap::new(),
+                variant: None,
             },
             icons: IconsConfig {
-                nerdfont: HashMap::new(),
-                ascii: HashMap::new(),
+                sets: HashMap::new(),
                 include: None,
             },
             layout: LayoutConfig {
@@ -369,6 +1611,8 @@ mod tests {
                     transform: "none".to_string(),
                     min_width: 0,
                     alignment: "left".to_string(),
+                    max_width: None,
+                    levels: HashMap::new(),
                 },
                 labels: HashMap::new(),
                 structure: StructureConfig {
@@ -389,21 +1633,589 @@ mod tests {
                 presets: HashMap::new(),
                 include: None,
             },
-        };
+        }
+    }
+
+    #[test]
+    fn test_binary_serialization() {
+        let dir = tempdir().unwrap();
+        let config_path = dir.path().join("pastry.bin");
+        fs::write(
+            dir.path().join("theme.toml"),
+            "include = [\"palette.toml\"]\n[meta]\nname = \"from_toml\"\n[settings]\nactive_icons = \"ascii\"\n[fonts]\n",
+        )
+        .unwrap();
+        fs::write(dir.path().join("palette.toml"), "[colors]\n# a\n").unwrap();
+        fs::write(dir.path().join("icons.toml"), "[nerdfont]\n[ascii]\n").unwrap();
+        fs::write(
+            dir.path().join("layout.toml"),
+            toml::to_string(&minimal_cookbook().layout).unwrap(),
+        )
+        .unwrap();
+
+        // Create a minimal config for testing
+        let config = minimal_cookbook();
 
         // Save
         config
-            .save_binary(&config_path)
+            .save_binary(&config_path, &dirs(dir.path()))
             .expect("Failed to save binary");
         assert!(config_path.exists());
 
-        // Load (simulating cache hit)
-        // Since no TOMLs exist in tempdir, load_with_cache should use bin if fresh check passes (it checks for tomls existence too)
-        // In is_cache_fresh, valid if bin exists. If tomls don't exist, mtime check loop skips?
-        // "if path.exists() ...". Yes.
-
-        let loaded = Cookbook::load_with_cache(dir.path(), &config_path, false)
+        // Load (simulating cache hit): the TOMLs are unchanged
+        let loaded = Cookbook::load_with_cache(&dirs(dir.path()), &config_path, false)
             .expect("Failed to load from cache");
         assert_eq!(loaded.theme.meta.name, "test_theme");
+
+        // A cache from before headers existed is never decoded
+        let old_path = dir.path().join("old.bin");
+        fs::write(&old_path, b"a bare bincode payload").unwrap();
+        assert!(!Cookbook::is_cache_fresh(&old_path, &dirs(dir.path())).unwrap());
+
+        // Same size, same mtime second: only the content hash tells
+        fs::write(dir.path().join("palette.toml"), "[colors]\n# b\n").unwrap();
+        assert!(!Cookbook::is_cache_fresh(&config_path, &dirs(dir.path())).unwrap());
+        let loaded = Cookbook::load_with_cache(&dirs(dir.path()), &config_path, false).unwrap();
+        assert_eq!(loaded.theme.meta.name, "from_toml");
+    }
+
+    #[test]
+    fn test_provenance_tracks_includes() {
+        let dir = tempdir().unwrap();
+        let config_dir = dir.path();
+        fs::write(
+            config_dir.join("theme.toml"),
+            "include = [\"colors.d/*.toml\", \"?local.toml\"]\n",
+        )
+        .unwrap();
+        fs::create_dir(config_dir.join("colors.d")).unwrap();
+        fs::write(config_dir.join("colors.d/a.toml"), "").unwrap();
+        fs::write(config_dir.join("icons.toml"), "").unwrap();
+        fs::write(config_dir.join("layout.toml"), "").unwrap();
+
+        let provenance = Provenance::record(&dirs(config_dir)).unwrap();
+        let recorded = |path: &Path| provenance.files.iter().find(|f| f.path == path).cloned();
+        assert!(
+            recorded(&config_dir.join("colors.d/a.toml"))
+                .unwrap()
+                .hash
+                .is_some()
+        );
+        // Looked for but missing
+        assert_eq!(recorded(&config_dir.join("local.toml")).unwrap().hash, None);
+        assert_eq!(
+            recorded(&config_dir.join("cookbook.toml")).unwrap().hash,
+            None
+        );
+        assert!(provenance.is_current(&dirs(config_dir)));
+
+        // A new match for the glob
+        fs::write(config_dir.join("colors.d/b.toml"), "").unwrap();
+        assert!(!provenance.is_current(&dirs(config_dir)));
+        let provenance = Provenance::record(&dirs(config_dir)).unwrap();
+        assert!(provenance.is_current(&dirs(config_dir)));
+
+        // The optional include appears
+        fs::write(config_dir.join("local.toml"), "").unwrap();
+        assert!(!provenance.is_current(&dirs(config_dir)));
+    }
+
+    #[test]
+    fn test_theme_selection() {
+        let dir = tempdir().unwrap();
+        let config_dir = dir.path();
+        fs::write(config_dir.join("theme.toml"), "").unwrap();
+        fs::create_dir_all(config_dir.join(THEMES_DIR)).unwrap();
+        fs::write(
+            config_dir.join(THEMES_DIR).join("night.toml"),
+            "[meta]\nname = \"Night\"\n[settings]\nactive_icons = \"ascii\"\n[colors]\n[fonts]\n",
+        )
+        .unwrap();
+
+        assert_eq!(Cookbook::themes(&dirs(config_dir)).unwrap(), vec!["night"]);
+        assert_eq!(
+            Cookbook::theme_path(&dirs(config_dir)).unwrap(),
+            config_dir.join("theme.toml")
+        );
+
+        Cookbook::select_theme(&dirs(config_dir), Some("night")).unwrap();
+        assert_eq!(
+            Cookbook::selected_theme(config_dir).unwrap().as_deref(),
+            Some("night")
+        );
+        assert_eq!(
+            Cookbook::theme_path(&dirs(config_dir)).unwrap(),
+            config_dir.join(THEMES_DIR).join("night.toml")
+        );
+
+        assert!(matches!(
+            Cookbook::select_theme(&dirs(config_dir), Some("../theme")),
+            Err(ConfigError::ThemeNotFound(_))
+        ));
+        assert_eq!(
+            Cookbook::selected_theme(config_dir).unwrap().as_deref(),
+            Some("night")
+        );
+
+        Cookbook::select_theme(&dirs(config_dir), None).unwrap();
+        assert_eq!(Cookbook::selected_theme(config_dir).unwrap(), None);
+    }
+
+    #[test]
+    fn test_theme_variants() {
+        let dir = tempdir().unwrap();
+        let config_dir = dir.path();
+        fs::write(
+            config_dir.join("theme.toml"),
+            r##"
+variant = "light"
+[meta]
+name = "Duo"
+[settings]
+active_icons = "ascii"
+[colors]
+bg = "#000000"
+fg = "#ffffff"
+tabs = "@bg"
+[fonts]
+[variants.light]
+bg = "#fafafa"
+[variants.dark]
+bg = "#101010"
+"##,
+        )
+        .unwrap();
+
+        // The theme's own default applies until a variant is selected
+        let theme = Cookbook::load_theme(&dirs(config_dir)).unwrap();
+        assert_eq!(theme.variant.as_deref(), Some("light"));
+        assert_eq!(theme.colors["bg"].to_string(), "#FAFAFA");
+        assert_eq!(theme.colors["fg"].to_string(), "#FFFFFF");
+        // References resolve against the merged colors
+        assert_eq!(theme.colors["tabs"].to_string(), "#FAFAFA");
+
+        Cookbook::select_variant(config_dir, Some("dark")).unwrap();
+        let theme = Cookbook::load_theme(&dirs(config_dir)).unwrap();
+        assert_eq!(theme.variant.as_deref(), Some("dark"));
+        assert_eq!(theme.colors["bg"].to_string(), "#101010");
+        assert_eq!(theme.variants.len(), 2);
+
+        // Unknown variants keep the base colors
+        Cookbook::select_variant(config_dir, Some("dim")).unwrap();
+        let theme = Cookbook::load_theme(&dirs(config_dir)).unwrap();
+        assert_eq!(theme.colors["bg"].to_string(), "#000000");
+    }
+
+    #[test]
+    fn test_schedule_with_equal_times_is_rejected() {
+        let dir = tempdir().unwrap();
+        let config_dir = dir.path();
+        let write = |light: &str| {
+            fs::write(
+                config_dir.join("theme.toml"),
+                format!(
+                    "[meta]\nname = \"Duo\"\n[settings]\nactive_icons = \"ascii\"\n\
+                     [settings.schedule]\nlight = \"{}\"\ndark = \"19:00\"\n[colors]\n[fonts]\n",
+                    light
+                ),
+            )
+            .unwrap();
+        };
+
+        write("07:00");
+        assert!(Cookbook::load_theme(&dirs(config_dir)).is_ok());
+
+        write("19:00");
+        let err = Cookbook::load_theme(&dirs(config_dir)).unwrap_err();
+        assert!(matches!(&err, ConfigError::Setting { key, .. } if key == "settings.schedule"));
+        assert!(err.to_string().contains("both 19:00"), "{}", err);
+    }
+
+    #[test]
+    fn test_invalid_color_names_key_and_file() {
+        let dir = tempdir().unwrap();
+        let config_dir = dir.path();
+        fs::write(
+            config_dir.join("theme.toml"),
+            r##"
+include = ["palette.toml"]
+[meta]
+name = "Broken"
+[settings]
+active_icons = "ascii"
+[colors]
+fg = "#ffffff"
+[fonts]
+"##,
+        )
+        .unwrap();
+        fs::write(
+            config_dir.join("palette.toml"),
+            "[colors]\nbg = \"#12345\"\naccent = \"hsl(200, 50%, 40%, 0.5)\"\n",
+        )
+        .unwrap();
+
+        let err = Cookbook::load_theme(&dirs(config_dir)).unwrap_err();
+        let ConfigError::InvalidColor { key, path, reason } = &err else {
+            panic!("expected an invalid color, got {}", err);
+        };
+        assert_eq!(key, "bg");
+        assert_eq!(path, &config_dir.join("palette.toml"));
+        assert!(reason.contains("'#12345' is not a hex color"), "{}", reason);
+
+        fs::write(
+            config_dir.join("palette.toml"),
+            "[colors]\nbg = \"#123456\"\naccent = \"hsl(200, 50%, 40%, 0.5)\"\n",
+        )
+        .unwrap();
+        let theme = Cookbook::load_theme(&dirs(config_dir)).unwrap();
+        assert_eq!(theme.colors["accent"].a, 128);
+        assert_eq!(theme.colors["fg"], Color::new(255, 255, 255, 255));
+    }
+
+    #[test]
+    fn test_include_globs_optional_and_cycles() {
+        let dir = tempdir().unwrap();
+        let config_dir = dir.path();
+        let theme = |include: &str| {
+            format!(
+                "include = {}\n[meta]\nname = \"t\"\n[settings]\nactive_icons = \"ascii\"\n[colors]\n[fonts]\n",
+                include
+            )
+        };
+        fs::create_dir(config_dir.join("colors.d")).unwrap();
+        fs::write(
+            config_dir.join("colors.d/10-base.toml"),
+            "[colors]\nbg = \"#000000\"\nfg = \"#111111\"\n",
+        )
+        .unwrap();
+        fs::write(
+            config_dir.join("colors.d/20-accent.toml"),
+            "[colors]\nfg = \"#222222\"\n",
+        )
+        .unwrap();
+        fs::write(
+            config_dir.join("theme.toml"),
+            theme(r#"["colors.d/*.toml", "?local.toml"]"#),
+        )
+        .unwrap();
+
+        // Matches merge in alphabetical order; the missing optional file is skipped
+        let loaded = Cookbook::load_theme(&dirs(config_dir)).unwrap();
+        assert_eq!(loaded.colors["bg"].to_string(), "#000000");
+        assert_eq!(loaded.colors["fg"].to_string(), "#222222");
+        let mut sources = Sources::default();
+        Cookbook::load_value_recursive(&config_dir.join("theme.toml"), &mut sources).unwrap();
+        assert!(sources.files.contains(&config_dir.join("local.toml")));
+
+        fs::write(
+            config_dir.join("local.toml"),
+            "[colors]\nbg = \"#333333\"\n",
+        )
+        .unwrap();
+        let loaded = Cookbook::load_theme(&dirs(config_dir)).unwrap();
+        assert_eq!(loaded.colors["bg"].to_string(), "#333333");
+
+        // A required include still has to exist
+        fs::write(config_dir.join("theme.toml"), theme(r#"["missing.toml"]"#)).unwrap();
+        assert!(matches!(
+            Cookbook::load_theme(&dirs(config_dir)),
+            Err(ConfigError::Io(_))
+        ));
+
+        fs::write(config_dir.join("theme.toml"), theme(r#"["a.toml"]"#)).unwrap();
+        fs::write(config_dir.join("a.toml"), "include = [\"b.toml\"]\n").unwrap();
+        fs::write(config_dir.join("b.toml"), "include = [\"a.toml\"]\n").unwrap();
+        let err = Cookbook::load_theme(&dirs(config_dir)).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Include cycle: theme.toml -> a.toml -> b.toml -> a.toml",
+            "{}",
+            err
+        );
+
+        fs::write(
+            config_dir.join("theme.toml"),
+            theme(r#"["${KITCHN_TEST_UNSET}/x.toml"]"#),
+        )
+        .unwrap();
+        let err = Cookbook::load_theme(&dirs(config_dir)).unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("environment variable KITCHN_TEST_UNSET is not set"),
+            "{}",
+            err
+        );
+    }
+
+    #[test]
+    fn test_expand_include() {
+        let home = directories::BaseDirs::new().unwrap();
+        let home = home.home_dir().to_string_lossy();
+        assert_eq!(
+            expand_include("~/kitchn/extra.toml").unwrap(),
+            format!("{}/kitchn/extra.toml", home)
+        );
+        assert_eq!(expand_include("plain.toml").unwrap(), "plain.toml");
+        assert_eq!(expand_include("a~b.toml").unwrap(), "a~b.toml");
+        assert!(expand_include("${UNCLOSED").is_err());
+        assert!(expand_include("$/x").is_err());
+    }
+
+    #[test]
+    fn test_unknown_keys() {
+        let dir = tempdir().unwrap();
+        let config_dir = dir.path();
+        fs::write(
+            config_dir.join("theme.toml"),
+            r##"
+[meta]
+name = "Typos"
+[settings]
+active_icons = "ascii"
+active_icon = "nerdfont"
+[colors]
+anything_goes = "#ffffff"
+[fonts]
+"##,
+        )
+        .unwrap();
+        fs::write(
+            config_dir.join("icons.toml"),
+            "include = [\"extra.toml\"]\n[nerdfont]\n[ascii]\n",
+        )
+        .unwrap();
+        // Icon sets are open, so an included `[emoji]` is no typo
+        fs::write(config_dir.join("extra.toml"), "[emoji]\ninfo = \"i\"\n").unwrap();
+        fs::write(
+            config_dir.join("layout.toml"),
+            r#"
+[tag]
+prefix = ""
+suffix = ""
+transform = "none"
+min_width = 0
+alignment = "left"
+[labels]
+[structure]
+terminal = "{msg}"
+file = "{msg}"
+[logging]
+base_dir = "logs"
+path_structure = ""
+filename_structure = ""
+timestamp_format = ""
+write_by_default = false
+"#,
+        )
+        .unwrap();
+        fs::write(
+            config_dir.join("cookbook.toml"),
+            "[presets.hi]\nlevel = \"info\"\nscope = \"X\"\nmsg = \"hi\"\ncolour = \"red\"\n",
+        )
+        .unwrap();
+
+        let unknown = Cookbook::unknown_keys(&dirs(config_dir)).unwrap();
+        assert_eq!(
+            unknown,
+            vec![
+                (
+                    config_dir.join("theme.toml"),
+                    "settings.active_icon".to_string()
+                ),
+                (
+                    config_dir.join("cookbook.toml"),
+                    "presets.hi.colour".to_string()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_overlay_merges_over_theme() {
+        let dir = tempdir().unwrap();
+        let config_dir = dir.path();
+        let theme = config_dir.join("theme.toml");
+        fs::write(
+            &theme,
+            "[meta]\nname = \"Base\"\n[settings]\nactive_icons = \"ascii\"\n[colors]\nbg = \"#000000\"\nfg = \"#ffffff\"\nprimary = \"@fg\"\n[fonts]\n",
+        )
+        .unwrap();
+        let host = config_dir.join("laptop.toml");
+        fs::write(&host, "[theme.colors]\nfg = \"#eeeeee\"\n").unwrap();
+
+        let load = |files: Vec<PathBuf>| {
+            let mut value = toml::Value::Table(toml::map::Map::new());
+            for file in &files {
+                Cookbook::deep_merge(
+                    &mut value,
+                    toml::from_str(&fs::read_to_string(file).unwrap()).unwrap(),
+                );
+            }
+            Cookbook::load_theme_file(&dirs(config_dir), &theme, None, &Overlay { files, value })
+        };
+
+        // References resolve against the merged colors
+        let merged = load(vec![host.clone()]).unwrap();
+        assert_eq!(merged.colors["fg"].to_string(), "#EEEEEE");
+        assert_eq!(merged.colors["primary"].to_string(), "#EEEEEE");
+        assert_eq!(merged.colors["bg"].to_string(), "#000000");
+
+        // Errors name the overlay that set the color
+        let profile = config_dir.join("work.toml");
+        fs::write(&profile, "[theme.colors]\nfg = \"#nothex\"\n").unwrap();
+        let err = load(vec![host, profile.clone()]).unwrap_err();
+        let ConfigError::InvalidColor { key, path, .. } = &err else {
+            panic!("expected an invalid color, got {}", err);
+        };
+        assert_eq!(key, "fg");
+        assert_eq!(path, &profile);
+    }
+
+    #[test]
+    fn test_env_overrides() {
+        let vars = |pairs: &[(&str, &str)]| {
+            pairs
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect::<Vec<_>>()
+        };
+
+        let mut config = minimal_cookbook();
+        config
+            .apply_overrides(vars(&[
+                ("KITCHN__THEME__COLORS__PRIMARY", "#ff0000"),
+                ("KITCHN__LAYOUT__LOGGING__WRITE_BY_DEFAULT", "true"),
+                ("KITCHN__LAYOUT__TAG__MIN_WIDTH", "8"),
+                ("KITCHN__COOKBOOK__PRESETS__HI__LEVEL", "info"),
+                ("KITCHN__COOKBOOK__PRESETS__HI__MSG", "hello"),
+                ("PATH", "/usr/bin"),
+            ]))
+            .unwrap();
+        assert_eq!(config.theme.colors["primary"], Color::new(255, 0, 0, 255));
+        assert_eq!(config.theme.spellings["primary"], "#ff0000");
+        assert!(config.layout.logging.write_by_default);
+        assert_eq!(config.layout.tag.min_width, 8);
+        assert_eq!(config.dictionary.presets["hi"].msg, "hello");
+
+        let err = |pairs: &[(&str, &str)]| {
+            minimal_cookbook()
+                .apply_overrides(vars(pairs))
+                .unwrap_err()
+                .to_string()
+        };
+        assert!(
+            err(&[("KITCHN__LAYOUT__LOGGING__WRITE_BY_DEFAULT", "no")]).contains("true or false")
+        );
+        assert!(err(&[("KITCHN__THEME__COLORS__PRIMARY", "@bg")]).contains("'@bg' is not a color"));
+        assert!(
+            err(&[("KITCHN__LAYOUT__TAG__PREFX", "<")])
+                .contains("'layout.tag.prefx' is not a setting")
+        );
+        assert!(err(&[("KITCHN__COLORS__PRIMARY", "#000")]).contains("KITCHN__COLORS__PRIMARY"));
+        assert!(err(&[("KITCHN__LAYOUT__TAG", "x")]).contains("not a single value"));
+        // A new preset needs every field
+        let missing = err(&[("KITCHN__COOKBOOK__PRESETS__HI__LEVEL", "info")]);
+        assert!(
+            missing.contains("KITCHN__COOKBOOK__PRESETS__HI__LEVEL"),
+            "{}",
+            missing
+        );
+        assert!(missing.contains("missing field `msg`"), "{}", missing);
+    }
+
+    #[test]
+    fn test_set_value_keeps_formatting() {
+        let dir = tempdir().unwrap();
+        let config_dir = dir.path();
+        let theme = "# My theme\n[meta]\nname = \"Base\"\n[settings]\nactive_icons = \"ascii\"\n\n[colors]\nbg = \"#000000\"\nprimary = \"@bg\"   # accent\n[fonts]\n";
+        fs::write(config_dir.join("theme.toml"), theme).unwrap();
+        fs::write(config_dir.join("icons.toml"), "[nerdfont]\n[ascii]\n").unwrap();
+        let layout = "# Tag\n[tag]\nprefix = \"[\"\nsuffix = \"]\"\ntransform = \"none\"\nmin_width = 0 # pad\nalignment = \"left\"\n[labels]\n[structure]\nterminal = \"{msg}\"\nfile = \"{msg}\"\n[logging]\nbase_dir = \"logs\"\npath_structure = \"{app}\"\nfilename_structure = \"log\"\ntimestamp_format = \"%Y\"\nwrite_by_default = false\n";
+        fs::write(config_dir.join("layout.toml"), layout).unwrap();
+
+        let set =
+            Cookbook::set_value(&dirs(config_dir), "theme.colors.primary", "#ff79c6").unwrap();
+        assert_eq!(set.shadowed_by, None);
+        let file = set.file;
+        assert_eq!(file, config_dir.join("theme.toml"));
+        assert_eq!(
+            fs::read_to_string(&file).unwrap(),
+            theme.replace("\"@bg\"", "\"#ff79c6\"")
+        );
+        Cookbook::set_value(&dirs(config_dir), "layout.tag.min_width", "12").unwrap();
+        let written = fs::read_to_string(config_dir.join("layout.toml")).unwrap();
+        assert_eq!(
+            written,
+            layout.replace("min_width = 0 #", "min_width = 12 #")
+        );
+
+        let config = Cookbook::load_tomls(&dirs(config_dir)).unwrap();
+        assert_eq!(
+            config.get_value("layout.tag.min_width").unwrap(),
+            toml::Value::Integer(12)
+        );
+        assert!(matches!(
+            config.get_value("layout.tag.nope"),
+            Err(ConfigError::NotSet(_))
+        ));
+
+        // Rejected values leave the files alone
+        let err = |key: &str, raw: &str| {
+            Cookbook::set_value(&dirs(config_dir), key, raw)
+                .unwrap_err()
+                .to_string()
+        };
+        assert!(err("layout.tag.min_width", "wide").contains("expected an integer"));
+        assert!(err("layout.tag.prefx", "<").contains("'layout.tag.prefx' is not a setting"));
+        assert!(err("tag.prefix", "<").contains("expected theme, icons, layout or cookbook"));
+        assert!(err("theme.colors.fg", "@nope").contains("'@nope'"));
+        assert_eq!(
+            fs::read_to_string(config_dir.join("layout.toml")).unwrap(),
+            written
+        );
+        assert!(!fs::read_to_string(&file).unwrap().contains("@nope"));
+
+        Cookbook::set_value(&dirs(config_dir), "theme.colors.fg", "@primary").unwrap();
+        let config = Cookbook::load_tomls(&dirs(config_dir)).unwrap();
+        assert_eq!(config.theme.colors["fg"].to_string(), "#FF79C6");
+
+        // A default preset is copied into cookbook.toml before it is changed
+        let file = Cookbook::set_value(&dirs(config_dir), "cookbook.presets.cook_ok.msg", "done")
+            .unwrap()
+            .file;
+        let dict: DictionaryConfig = toml::from_str(&fs::read_to_string(file).unwrap()).unwrap();
+        assert_eq!(dict.presets["cook_ok"].msg, "done");
+        assert_eq!(dict.presets["cook_ok"].scope.as_deref(), Some("COOK"));
+    }
+
+    #[test]
+    fn test_set_value_names_what_overrides_it() {
+        let dir = tempdir().unwrap();
+        let config_dir = dir.path();
+        fs::write(
+            config_dir.join("theme.toml"),
+            "variant = \"dark\"\n[meta]\nname = \"Base\"\n[settings]\nactive_icons = \"ascii\"\n[colors]\nbg = \"#000000\"\nfg = \"#ffffff\"\n[fonts]\n[variants.dark]\nfg = \"#eeeeee\"\n",
+        )
+        .unwrap();
+        fs::write(config_dir.join("icons.toml"), "[ascii]\n").unwrap();
+        fs::write(config_dir.join("layout.toml"), "[tag]\nprefix = \"[\"\nsuffix = \"]\"\ntransform = \"none\"\nmin_width = 0\nalignment = \"left\"\n[labels]\n[structure]\nterminal = \"{msg}\"\nfile = \"{msg}\"\n[logging]\nbase_dir = \"logs\"\npath_structure = \"{app}\"\nfilename_structure = \"log\"\ntimestamp_format = \"%Y\"\nwrite_by_default = false\n").unwrap();
+
+        // The dark variant sets its own fg
+        let set = Cookbook::set_value(&dirs(config_dir), "theme.colors.fg", "#ff0000").unwrap();
+        assert_eq!(set.file, config_dir.join("theme.toml"));
+        assert_eq!(
+            set.shadowed_by.as_deref(),
+            Some("[variants.dark] in theme.toml")
+        );
+        assert!(
+            fs::read_to_string(&set.file)
+                .unwrap()
+                .contains("fg = \"#ff0000\"")
+        );
+
+        // A reference that resolves to the color in effect holds
+        let set = Cookbook::set_value(&dirs(config_dir), "theme.colors.bg", "@fg").unwrap();
+        assert_eq!(set.shadowed_by, None);
     }
 }
@@ -1,15 +1,63 @@
+use crate::format::PANTRY;
 use crate::ingredient::Ingredient;
-use anyhow::{Context, Result};
+use anyhow::{Context, Result, bail};
 use log::debug;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use std::collections::HashMap;
 use std::fs::{self, File};
-use std::io::{BufReader, BufWriter};
+use std::io::BufWriter;
 use std::path::{Path, PathBuf};
 
+/// A file written by an ingredient while cooking.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct TargetRecord {
+    pub path: PathBuf,
+    /// Copy of the file that existed before kitchn first wrote this target.
+    pub backup: Option<PathBuf>,
+    /// [`content_hash`] of what kitchn last wrote to `path`.
+    pub hash: String,
+    /// Version of the ingredient that last wrote `path`.
+    pub version: String,
+    /// Unix timestamp (seconds) of the last write.
+    pub cooked_at: i64,
+    /// `meta.name` of the theme the content was rendered with.
+    pub theme: String,
+}
+
+/// Hex-encoded SHA-256 of `content`, used to detect drift in managed files.
+pub fn content_hash(content: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(content))
+}
+
+/// What happened to a managed target when its ingredient was removed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Removal {
+    /// The generated file was deleted.
+    Deleted(PathBuf),
+    /// The pre-kitchn original was moved back into place.
+    Restored(PathBuf),
+    /// The file was already gone.
+    Missing(PathBuf),
+    /// Another ingredient, named here, still owns the file, so it was left alone.
+    Shared(PathBuf, String),
+    /// Restoring or deleting failed; the target stays recorded.
+    Failed(PathBuf, String),
+}
+
+/// On-disk layout of `pantry.db`: ingredients, their targets and their links.
+type Layout = (
+    HashMap<String, Ingredient>,
+    HashMap<String, Vec<TargetRecord>>,
+    HashMap<String, PathBuf>,
+);
+
 #[derive(Default)]
 pub struct Pantry {
     path: PathBuf,
     ingredients: HashMap<String, Ingredient>,
+    targets: HashMap<String, Vec<TargetRecord>>,
+    links: HashMap<String, PathBuf>,
 }
 
 impl Pantry {
@@ -18,21 +66,48 @@ impl Pantry {
         let mut db = Pantry {
             path: path.to_path_buf(),
             ingredients: HashMap::new(),
+            targets: HashMap::new(),
+            links: HashMap::new(),
         };
 
         if path.exists() {
             // Check if file is empty (e.g. newly created by NamedTempFile or touch)
             let len = fs::metadata(path).map(|m| m.len()).unwrap_or(0);
             if len > 0 {
-                let file = File::open(path).context("Failed to open pantry database")?;
-                let mut reader = BufReader::new(file);
+                let bytes = fs::read(path).context("Failed to open pantry database")?;
 
-                // Using bincode 2.0 serde integration
-                let data: HashMap<String, Ingredient> =
-                    bincode::serde::decode_from_std_read(&mut reader, bincode::config::standard())
-                        .context("Failed to decode pantry database")?;
+                let mut rest = &bytes[..];
+                let (ingredients, targets, links) = match PANTRY.read_header(&mut rest)? {
+                    Some(header) if header.version == PANTRY.version => {
+                        // Using bincode 2.0 serde integration
+                        bincode::serde::decode_from_slice::<Layout, _>(
+                            rest,
+                            bincode::config::standard(),
+                        )
+                        .context("Failed to decode pantry database")?
+                        .0
+                    }
+                    // Never downgrade a pantry a newer kitchn wrote
+                    Some(header) => bail!(PANTRY.mismatch(header)),
+                    None => {
+                        debug!("Migrating headerless pantry {:?}", path);
+                        let mut backup = path.as_os_str().to_owned();
+                        backup.push(".v1");
+                        let backup = PathBuf::from(backup);
+                        if !backup.exists() {
+                            fs::copy(path, &backup).context("Failed to back up pantry database")?;
+                        }
+                        (
+                            legacy::decode_headerless(&bytes)?,
+                            HashMap::new(),
+                            HashMap::new(),
+                        )
+                    }
+                };
 
-                db.ingredients = data;
+                db.ingredients = ingredients;
+                db.targets = targets;
+                db.links = links;
                 debug!("Loaded {} ingredients", db.ingredients.len());
             } else {
                 debug!("Pantry file is empty");
@@ -51,8 +126,9 @@ impl Pantry {
         let file = File::create(&self.path).context("Failed to create pantry database file")?;
         let mut writer = BufWriter::new(file);
 
+        PANTRY.write_header(&mut writer)?;
         bincode::serde::encode_into_std_write(
-            &self.ingredients,
+            (&self.ingredients, &self.targets, &self.links),
             &mut writer,
             bincode::config::standard(),
         )
@@ -69,8 +145,14 @@ impl Pantry {
         Ok(())
     }
 
+    pub fn get(&self, name: &str) -> Option<&Ingredient> {
+        self.ingredients.get(name)
+    }
+
     pub fn discard(&mut self, name: &str) -> Option<Ingredient> {
         debug!("Discarding ingredient: {}", name);
+        self.targets.remove(name);
+        self.links.remove(name);
         self.ingredients.remove(name)
     }
 
@@ -80,6 +162,8 @@ impl Pantry {
             debug!("Discarding ingredient: {}", name);
         }
         self.ingredients.clear();
+        self.targets.clear();
+        self.links.clear();
     }
 
     pub fn set_ignored(&mut self, name: &str, state: bool) -> Result<bool> {
@@ -101,4 +185,228 @@ impl Pantry {
     pub fn iter(&self) -> std::collections::hash_map::Values<'_, String, Ingredient> {
         self.ingredients.values()
     }
+
+    /// Remembers that `name` was stocked from `source` and should follow it:
+    /// `kitchn watch` re-stocks the ingredient whenever that file changes.
+    pub fn link(&mut self, name: &str, source: &Path) {
+        debug!("Linking ingredient {} to {:?}", name, source);
+        self.links.insert(name.to_string(), source.to_path_buf());
+    }
+
+    /// Source files of linked ingredients, keyed by ingredient name.
+    pub fn links(&self) -> &HashMap<String, PathBuf> {
+        &self.links
+    }
+
+    /// Files written by `name`, in the order they were first cooked.
+    pub fn targets(&self, name: &str) -> &[TargetRecord] {
+        self.targets.get(name).map(|v| v.as_slice()).unwrap_or(&[])
+    }
+
+    /// Writes `content` to `path` for `pkg` and records the target.
+    ///
+    /// The first time kitchn takes over a file that already exists (and that no
+    /// other ingredient owns), the original is copied to the backup directory so
+    /// `remove` can put it back. The target is only recorded once the write has
+    /// succeeded; rewriting an owned path only refreshes hash and provenance.
+    pub fn write_target(
+        &mut self,
+        pkg: &Ingredient,
+        theme: &str,
+        path: &Path,
+        content: &str,
+    ) -> Result<()> {
+        let name = pkg.meta.name.as_str();
+        let tracked = self.targets.values().flatten().any(|t| t.path == path);
+
+        let backup = if path.exists() && !tracked {
+            let backup = self.backup_path(name, path);
+            if let Some(parent) = backup.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            debug!("Backing up {:?} to {:?}", path, backup);
+            fs::copy(path, &backup)
+                .with_context(|| format!("Failed to back up {}", path.display()))?;
+            Some(backup)
+        } else {
+            None
+        };
+
+        let written = path
+            .parent()
+            .map_or(Ok(()), fs::create_dir_all)
+            .and_then(|_| fs::write(path, content))
+            .with_context(|| format!("Failed to write {}", path.display()));
+        if let Err(e) = written {
+            if let Some(backup) = &backup {
+                let _ = fs::remove_file(backup);
+            }
+            return Err(e);
+        }
+
+        let hash = content_hash(content.as_bytes());
+        let cooked_at = chrono::Utc::now().timestamp();
+        let records = self.targets.entry(name.to_string()).or_default();
+        if let Some(record) = records.iter_mut().find(|t| t.path == path) {
+            record.hash = hash;
+            record.version = pkg.meta.version.clone();
+            record.cooked_at = cooked_at;
+            record.theme = theme.to_string();
+            return Ok(());
+        }
+        records.push(TargetRecord {
+            path: path.to_path_buf(),
+            backup,
+            hash,
+            version: pkg.meta.version.clone(),
+            cooked_at,
+            theme: theme.to_string(),
+        });
+        Ok(())
+    }
+
+    /// Finds the ingredient that owns `path`, if any.
+    pub fn owner_of(&self, path: &Path) -> Option<(&Ingredient, &TargetRecord)> {
+        self.targets.iter().find_map(|(name, records)| {
+            let record = records.iter().find(|t| t.path == path)?;
+            Some((self.ingredients.get(name)?, record))
+        })
+    }
+
+    /// Discards `name` and undoes what cooking it did on disk.
+    ///
+    /// Each target is restored from its backup when one exists, otherwise
+    /// deleted. A target another stocked ingredient also owns is left in place
+    /// and hands it the backup, if it had none. A target that cannot be handled does not stop the others: it is
+    /// reported as [`Removal::Failed`] and stays recorded, together with the
+    /// ingredient, so the removal can be retried. Returns `None` if the
+    /// ingredient is not stocked.
+    pub fn remove(&mut self, name: &str) -> Result<Option<(Ingredient, Vec<Removal>)>> {
+        if !self.ingredients.contains_key(name) {
+            return Ok(None);
+        }
+
+        let mut removals = Vec::new();
+        let mut kept = Vec::new();
+        for target in self.targets.remove(name).unwrap_or_default() {
+            let path = target.path.clone();
+            if let Some((owner, record)) = self.targets.iter_mut().find_map(|(owner, records)| {
+                Some((owner, records.iter_mut().find(|t| t.path == path)?))
+            }) {
+                debug!("Leaving {:?} to {}", path, owner);
+                if record.backup.is_none() {
+                    record.backup = target.backup;
+                }
+                removals.push(Removal::Shared(path, owner.clone()));
+                continue;
+            }
+            let result = match &target.backup {
+                Some(backup) if backup.exists() => {
+                    debug!("Restoring {:?} from {:?}", path, backup);
+                    // Copy rather than rename: the data dir may live on another filesystem
+                    fs::copy(backup, &path)
+                        .with_context(|| format!("Failed to restore {}", path.display()))
+                        .and_then(|_| {
+                            fs::remove_file(backup).with_context(|| {
+                                format!("Failed to remove backup {}", backup.display())
+                            })
+                        })
+                        .map(|_| Removal::Restored(path.clone()))
+                }
+                _ if path.exists() => {
+                    debug!("Deleting {:?}", path);
+                    fs::remove_file(&path)
+                        .with_context(|| format!("Failed to delete {}", path.display()))
+                        .map(|_| Removal::Deleted(path.clone()))
+                }
+                _ => Ok(Removal::Missing(path.clone())),
+            };
+            match result {
+                Ok(removal) => removals.push(removal),
+                Err(e) => {
+                    debug!("Keeping record of {:?}: {:#}", path, e);
+                    removals.push(Removal::Failed(path, format!("{:#}", e)));
+                    kept.push(target);
+                }
+            }
+        }
+
+        if !kept.is_empty() {
+            self.targets.insert(name.to_string(), kept);
+            let ingredient = self.ingredients[name].clone();
+            return Ok(Some((ingredient, removals)));
+        }
+
+        let _ = fs::remove_dir(self.backup_dir().join(name));
+        let ingredient = self.discard(name).expect("checked above");
+        Ok(Some((ingredient, removals)))
+    }
+
+    fn backup_dir(&self) -> PathBuf {
+        self.path.with_file_name("backups")
+    }
+
+    fn backup_path(&self, name: &str, path: &Path) -> PathBuf {
+        let key = content_hash(path.as_os_str().as_encoded_bytes());
+        self.backup_dir().join(name).join(key)
+    }
+}
+
+/// Pantries written before `pantry.db` had a header: a bare map of
+/// ingredients whose hooks only knew `reload`, with no targets or links.
+mod legacy {
+    use crate::ingredient::{self, Ingredient, IngredientManifest};
+    use anyhow::{Context, Result};
+    use serde::Deserialize;
+    use std::collections::HashMap;
+
+    #[derive(Deserialize)]
+    struct LegacyIngredient {
+        meta: IngredientManifest,
+        templates: Vec<LegacyTemplate>,
+        files: Vec<LegacyTemplate>,
+        hooks: LegacyHooks,
+    }
+
+    #[derive(Deserialize)]
+    struct LegacyTemplate {
+        target: String,
+        content: String,
+    }
+
+    #[derive(Deserialize)]
+    struct LegacyHooks {
+        reload: Option<String>,
+    }
+
+    impl From<LegacyTemplate> for ingredient::Template {
+        fn from(t: LegacyTemplate) -> Self {
+            ingredient::Template {
+                target: t.target,
+                content: t.content,
+                header: false,
+            }
+        }
+    }
+
+    impl From<LegacyIngredient> for Ingredient {
+        fn from(i: LegacyIngredient) -> Self {
+            Ingredient {
+                meta: i.meta,
+                templates: i.templates.into_iter().map(Into::into).collect(),
+                files: i.files.into_iter().map(Into::into).collect(),
+                hooks: ingredient::Hooks {
+                    reload: i.hooks.reload,
+                    on_remove: None,
+                },
+            }
+        }
+    }
+
+    pub fn decode_headerless(bytes: &[u8]) -> Result<HashMap<String, Ingredient>> {
+        let (data, _): (HashMap<String, LegacyIngredient>, _) =
+            bincode::serde::decode_from_slice(bytes, bincode::config::standard())
+                .context("Failed to decode pantry database")?;
+        Ok(data.into_iter().map(|(k, v)| (k, v.into())).collect())
+    }
 }
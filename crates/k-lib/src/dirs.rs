@@ -0,0 +1,153 @@
+use crate::config::ConfigError;
+use crate::db::content_hash;
+use directories::{BaseDirs, ProjectDirs};
+use std::path::{Path, PathBuf};
+
+/// Relocates every kitchn directory at once, to `config/`, `cache/`, `data/`
+/// and `run/` inside it.
+pub const HOME_ENV: &str = "KITCHN_HOME";
+/// Base directories of the system-wide cookbooks, colon separated and most
+/// important first; `kitchn/` inside each is layered under the user's files.
+pub const SYSTEM_DIRS_ENV: &str = "XDG_CONFIG_DIRS";
+/// [`SYSTEM_DIRS_ENV`] when it is unset.
+const DEFAULT_SYSTEM_DIRS: &str = "/etc/xdg";
+
+/// The directories one kitchn instance reads and writes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Dirs {
+    /// The cookbook: `theme.toml`, `icons.toml`, `layout.toml`, ...
+    pub config: PathBuf,
+    /// Baked cookbooks, one per config dir (see [`Dirs::pastry_path`]).
+    pub cache: PathBuf,
+    /// The pantry (`pantry.db`) and the backups of replaced files.
+    pub data: PathBuf,
+    /// The global lock and the debug socket.
+    pub runtime: PathBuf,
+    /// System cookbooks layered under [`Dirs::config`], most important first.
+    pub system: Vec<PathBuf>,
+}
+
+impl Dirs {
+    /// Everything under [`HOME_ENV`] if it is set, without system cookbooks;
+    /// the XDG directories (`~/.config/kitchn`, `~/.cache/kitchn`, ...) and
+    /// `kitchn/` in every [`SYSTEM_DIRS_ENV`] dir otherwise.
+    pub fn resolve() -> Result<Self, ConfigError> {
+        if let Some(home) = std::env::var_os(HOME_ENV).filter(|home| !home.is_empty()) {
+            return Ok(Self::under(Path::new(&home)));
+        }
+        let project = ProjectDirs::from("", "", "kitchn").ok_or(ConfigError::ConfigDirNotFound)?;
+        let runtime = BaseDirs::new()
+            .and_then(|dirs| dirs.runtime_dir().map(Path::to_path_buf))
+            .unwrap_or_else(std::env::temp_dir);
+        Ok(Dirs {
+            config: project.config_dir().to_path_buf(),
+            cache: project.cache_dir().to_path_buf(),
+            data: project.data_dir().to_path_buf(),
+            runtime,
+            system: Self::system_from_env(),
+        })
+    }
+
+    /// `<dir>/kitchn` for every dir in [`SYSTEM_DIRS_ENV`], most important
+    /// first. Relative entries are ignored, as the XDG spec asks.
+    fn system_from_env() -> Vec<PathBuf> {
+        let dirs = std::env::var_os(SYSTEM_DIRS_ENV)
+            .filter(|dirs| !dirs.is_empty())
+            .unwrap_or_else(|| DEFAULT_SYSTEM_DIRS.into());
+        std::env::split_paths(&dirs)
+            .filter(|dir| dir.is_absolute())
+            .map(|dir| dir.join("kitchn"))
+            .collect()
+    }
+
+    /// The layout [`HOME_ENV`] gives: every directory inside `home`, and no
+    /// system cookbooks, so the instance is fully isolated.
+    pub fn under(home: &Path) -> Self {
+        Dirs {
+            config: home.join("config"),
+            cache: home.join("cache"),
+            data: home.join("data"),
+            runtime: home.join("run"),
+            system: Vec::new(),
+        }
+    }
+
+    /// Points the instance at another cookbook and/or pantry, as `--config-dir`
+    /// and `--data-dir` do. Like [`Dirs::under`], the result is isolated: it
+    /// has no system cookbooks, and a runtime dir of its own, keyed by both
+    /// dirs, so it shares neither lock nor debug socket with other instances.
+    pub fn with_overrides(mut self, config: Option<PathBuf>, data: Option<PathBuf>) -> Self {
+        if config.is_none() && data.is_none() {
+            return self;
+        }
+        self.config = config.unwrap_or(self.config);
+        self.data = data.unwrap_or(self.data);
+        let mut key = self.config.as_os_str().to_owned();
+        key.push("\0");
+        key.push(&self.data);
+        let key = content_hash(key.as_encoded_bytes());
+        self.runtime = self.runtime.join(format!("kitchn-{}", &key[..16]));
+        self.system.clear();
+        self
+    }
+
+    /// The baked cookbook for [`Dirs::config`]. Keyed by the config dir, so
+    /// instances that share a cache dir never read each other's cookbook.
+    pub fn pastry_path(&self) -> PathBuf {
+        let config = std::path::absolute(&self.config).unwrap_or_else(|_| self.config.clone());
+        let key = content_hash(config.as_os_str().as_encoded_bytes());
+        self.cache.join(&key[..16]).join("pastry.bin")
+    }
+
+    pub fn pantry_path(&self) -> PathBuf {
+        self.data.join("pantry.db")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pastry_is_keyed_by_config_dir() {
+        let dirs = Dirs::under(Path::new("/tmp/kitchn-a"));
+        assert_eq!(dirs.config, Path::new("/tmp/kitchn-a/config"));
+        assert_eq!(dirs.runtime, Path::new("/tmp/kitchn-a/run"));
+        assert!(dirs.pastry_path().starts_with("/tmp/kitchn-a/cache"));
+        assert!(dirs.pastry_path().ends_with("pastry.bin"));
+        assert!(dirs.system.is_empty());
+
+        // Same cache dir, different cookbooks
+        let other = Dirs {
+            config: PathBuf::from("/tmp/kitchn-b/config"),
+            ..dirs.clone()
+        };
+        assert_ne!(dirs.pastry_path(), other.pastry_path());
+        assert_eq!(dirs.pastry_path(), dirs.clone().pastry_path());
+    }
+
+    #[test]
+    fn test_overrides_isolate_the_instance() {
+        let dirs = Dirs {
+            system: vec![PathBuf::from("/etc/xdg/kitchn")],
+            ..Dirs::under(Path::new("/tmp/kitchn-a"))
+        };
+        assert_eq!(dirs.clone().with_overrides(None, None), dirs);
+
+        let sandbox = dirs
+            .clone()
+            .with_overrides(Some(PathBuf::from("/tmp/cookbook")), None);
+        assert_eq!(sandbox.config, Path::new("/tmp/cookbook"));
+        assert_eq!(sandbox.data, dirs.data);
+        assert!(sandbox.system.is_empty());
+        assert!(sandbox.runtime.starts_with(&dirs.runtime));
+        assert_ne!(sandbox.runtime, dirs.runtime);
+
+        // Another pantry for the same cookbook gets its own lock too
+        let other = dirs.clone().with_overrides(
+            Some(PathBuf::from("/tmp/cookbook")),
+            Some(PathBuf::from("/tmp/pantry")),
+        );
+        assert_ne!(other.runtime, sandbox.runtime);
+    }
+}
@@ -0,0 +1,366 @@
+use crate::color::{ANSI_KEYS, Color, Rgb, THEME_KEYS};
+use crate::config::ThemeConfig;
+use anyhow::{Result, anyhow};
+use serde_json::json;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::str::FromStr;
+
+/// Formats `kitchn theme export` can write.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// base16 YAML (`scheme`, `author`, `base00`..`base0F`)
+    Base16,
+    /// X resources (`*.background`, `*.color0`, ...)
+    Xresources,
+    /// Colors and fonts as a JSON object
+    Json,
+    /// CSS custom properties on `:root`
+    Css,
+    /// SCSS variables
+    Scss,
+    /// GIMP/Inkscape palette
+    Gpl,
+    /// POSIX shell script that recolors the running terminal via OSC escapes
+    Osc,
+}
+
+impl Format {
+    pub const ALL: &[Format] = &[
+        Format::Base16,
+        Format::Xresources,
+        Format::Json,
+        Format::Css,
+        Format::Scss,
+        Format::Gpl,
+        Format::Osc,
+    ];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Format::Base16 => "base16",
+            Format::Xresources => "xresources",
+            Format::Json => "json",
+            Format::Css => "css",
+            Format::Scss => "scss",
+            Format::Gpl => "gpl",
+            Format::Osc => "osc",
+        }
+    }
+}
+
+impl fmt::Display for Format {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for Format {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Format::ALL
+            .iter()
+            .copied()
+            .find(|f| f.as_str() == s)
+            .ok_or_else(|| anyhow!("unknown export format '{}'", s))
+    }
+}
+
+/// base16 slots and the kitchn keys that fill them, best match first.
+const BASE16: &[(&str, &[&str])] = &[
+    ("base00", &["bg"]),
+    ("base01", &["black", "tabs", "bg"]),
+    ("base02", &["selection_bg", "bright_black"]),
+    ("base03", &["bright_black"]),
+    ("base04", &["white", "fg"]),
+    ("base05", &["fg"]),
+    ("base06", &["bright_white", "fg"]),
+    ("base07", &["bright_white", "fg"]),
+    ("base08", &["red", "error"]),
+    ("base09", &["orange", "warn"]),
+    ("base0A", &["yellow", "warn"]),
+    ("base0B", &["green", "success"]),
+    ("base0C", &["cyan", "info"]),
+    ("base0D", &["blue", "primary"]),
+    ("base0E", &["magenta", "secondary"]),
+    ("base0F", &["secondary", "bright_red"]),
+];
+
+/// Renders `theme` (with its active variant already applied) as `format`.
+pub fn export(theme: &ThemeConfig, format: Format) -> String {
+    match format {
+        Format::Base16 => base16(theme),
+        Format::Xresources => xresources(theme),
+        Format::Json => json(theme),
+        Format::Css => css(theme),
+        Format::Scss => scss(theme),
+        Format::Gpl => gpl(theme),
+        Format::Osc => osc(theme),
+    }
+}
+
+/// Every color in `theme`: the standard keys in theme-file order, then any
+/// custom keys alphabetically.
+fn ordered_colors(theme: &ThemeConfig) -> Vec<(&str, Color)> {
+    let mut out: Vec<(&str, Color)> = THEME_KEYS
+        .iter()
+        .flat_map(|(_, keys)| keys.iter())
+        .filter_map(|k| theme.colors.get(*k).map(|c| (*k, *c)))
+        .collect();
+    let custom: BTreeMap<&str, Color> = theme
+        .colors
+        .iter()
+        .filter(|(k, _)| !out.iter().any(|(o, _)| o == k))
+        .map(|(k, c)| (k.as_str(), *c))
+        .collect();
+    out.extend(custom);
+    out
+}
+
+/// Formats without alpha get the color as if it were opaque.
+fn rgb(theme: &ThemeConfig, key: &str) -> Option<Rgb> {
+    theme.colors.get(key).map(|c| c.rgb())
+}
+
+fn quote(s: &str) -> String {
+    serde_json::Value::String(s.to_string()).to_string()
+}
+
+/// CSS/SCSS identifier for a key: `selection_bg` -> `selection-bg`.
+fn ident(key: &str) -> String {
+    key.replace('_', "-")
+}
+
+fn base16(theme: &ThemeConfig) -> String {
+    let mut out = format!("scheme: {}\nauthor: \"kitchn\"\n", quote(&theme.meta.name));
+    for (slot, keys) in BASE16 {
+        if let Some(color) = keys.iter().find_map(|k| rgb(theme, k)) {
+            out.push_str(&format!(
+                "{}: \"{}\"\n",
+                slot,
+                color.to_hex().trim_start_matches('#').to_lowercase()
+            ));
+        }
+    }
+    out
+}
+
+fn xresources(theme: &ThemeConfig) -> String {
+    let mut out = format!("! kitchn theme: {}\n", theme.meta.name);
+    for (resource, key) in [
+        ("background", "bg"),
+        ("foreground", "fg"),
+        ("cursorColor", "cursor"),
+    ] {
+        if let Some(color) = rgb(theme, key) {
+            out.push_str(&format!("*.{}: {}\n", resource, color));
+        }
+    }
+    for (i, key) in ANSI_KEYS.iter().enumerate() {
+        if let Some(color) = rgb(theme, key) {
+            out.push_str(&format!("*.color{}: {}\n", i, color));
+        }
+    }
+    out
+}
+
+fn json(theme: &ThemeConfig) -> String {
+    let value = json!({
+        "name": theme.meta.name,
+        "variant": theme.variant,
+        "colors": theme.colors,
+        "fonts": theme.fonts,
+    });
+    let mut out = serde_json::to_string_pretty(&value).expect("JSON values always serialize");
+    out.push('\n');
+    out
+}
+
+fn css(theme: &ThemeConfig) -> String {
+    let mut out = format!("/* kitchn theme: {} */\n:root {{\n", theme.meta.name);
+    for (key, value) in ordered_colors(theme) {
+        out.push_str(&format!("  --color-{}: {};\n", ident(key), value));
+    }
+    let fonts: BTreeMap<_, _> = theme.fonts.iter().collect();
+    for (key, value) in fonts {
+        out.push_str(&format!("  --font-{}: {};\n", ident(key), css_value(value)));
+    }
+    out.push_str("}\n");
+    out
+}
+
+fn scss(theme: &ThemeConfig) -> String {
+    let mut out = format!("// kitchn theme: {}\n", theme.meta.name);
+    for (key, value) in ordered_colors(theme) {
+        out.push_str(&format!("$color-{}: {};\n", ident(key), value));
+    }
+    let fonts: BTreeMap<_, _> = theme.fonts.iter().collect();
+    for (key, value) in fonts {
+        out.push_str(&format!("$font-{}: {};\n", ident(key), css_value(value)));
+    }
+    out
+}
+
+/// Font sizes stay bare numbers; family names are quoted.
+fn css_value(value: &str) -> String {
+    if value.parse::<f64>().is_ok() {
+        value.to_string()
+    } else {
+        quote(value)
+    }
+}
+
+fn gpl(theme: &ThemeConfig) -> String {
+    let mut out = format!("GIMP Palette\nName: {}\nColumns: 8\n#\n", theme.meta.name);
+    for (key, c) in ordered_colors(theme) {
+        if c.is_opaque() {
+            out.push_str(&format!("{:3} {:3} {:3}\t{}\n", c.r, c.g, c.b, key));
+        }
+    }
+    out
+}
+
+fn osc(theme: &ThemeConfig) -> String {
+    let mut out = format!(
+        "#!/bin/sh\n# kitchn theme: {}\n# Recolors the current terminal; run it or source it from your shell rc.\n",
+        theme.meta.name
+    );
+    let mut set = |code: &str, color: Rgb| {
+        out.push_str(&format!("printf '\\033]{};{}\\033\\\\'\n", code, color));
+    };
+    for (i, key) in ANSI_KEYS.iter().enumerate() {
+        if let Some(color) = rgb(theme, key) {
+            set(&format!("4;{}", i), color);
+        }
+    }
+    // Dynamic colors: foreground, background, cursor, selection
+    for (code, key) in [
+        ("10", "fg"),
+        ("11", "bg"),
+        ("12", "cursor"),
+        ("17", "selection_bg"),
+    ] {
+        if let Some(color) = rgb(theme, key) {
+            set(code, color);
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::importer;
+
+    fn theme() -> ThemeConfig {
+        toml::from_str(
+            r##"
+[meta]
+name = "Sweet Dracula"
+[settings]
+active_icons = "nerdfont"
+[colors]
+bg = "#161925"
+fg = "#F8F8F2"
+cursor = "#8BE9FD"
+selection_bg = "#44475A"
+primary = "#BD93F9"
+orange = "#FFB86C"
+black = "#44475A"
+red = "#DE312B"
+green = "#2FD651"
+yellow = "#D0D662"
+blue = "#9C6FCF"
+magenta = "#DE559C"
+cyan = "#6AC5D3"
+white = "#D7D4C8"
+bright_black = "#656B84"
+bright_white = "#F8F8F2"
+accent_2 = "rgba(0, 0, 0, 0.5)"
+[fonts]
+mono = "JetBrainsMono Nerd Font"
+size_mono = "10"
+"##,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_base16_roundtrips_through_import() {
+        let out = export(&theme(), Format::Base16);
+        assert!(out.starts_with("scheme: \"Sweet Dracula\"\n"));
+        assert!(out.contains("base00: \"161925\"\n"));
+
+        let imported = importer::parse(&out, importer::Format::Base16, None).unwrap();
+        assert_eq!(imported.name.as_deref(), Some("Sweet Dracula"));
+        for key in ["bg", "fg", "red", "blue", "orange", "bright_black"] {
+            assert_eq!(
+                imported.palette.get(key).unwrap().to_hex(),
+                theme().colors[key].to_string(),
+                "{}",
+                key
+            );
+        }
+    }
+
+    #[test]
+    fn test_xresources_roundtrips_through_import() {
+        let out = export(&theme(), Format::Xresources);
+        assert!(out.contains("*.background: #161925\n"));
+        assert!(out.contains("*.color1: #DE312B\n"));
+        let imported = importer::parse(&out, importer::Format::Xresources, None).unwrap();
+        assert_eq!(imported.palette.get("cursor").unwrap().to_hex(), "#8BE9FD");
+        assert_eq!(imported.palette.get("white").unwrap().to_hex(), "#D7D4C8");
+    }
+
+    #[test]
+    fn test_json() {
+        let out = export(&theme(), Format::Json);
+        let value: serde_json::Value = serde_json::from_str(&out).unwrap();
+        assert_eq!(value["name"], "Sweet Dracula");
+        assert_eq!(value["colors"]["bg"], "#161925");
+        assert_eq!(value["fonts"]["mono"], "JetBrainsMono Nerd Font");
+    }
+
+    #[test]
+    fn test_css_and_scss() {
+        let css = export(&theme(), Format::Css);
+        assert!(css.contains(":root {\n  --color-bg: #161925;\n  --color-fg: #F8F8F2;\n"));
+        assert!(css.contains("  --color-selection-bg: #44475A;\n"));
+        // Custom keys come after the standard ones, alpha kept
+        assert!(css.contains("  --color-accent-2: #00000080;\n"));
+        assert!(css.contains("  --font-mono: \"JetBrainsMono Nerd Font\";\n"));
+        assert!(css.contains("  --font-size-mono: 10;\n"));
+        assert!(css.ends_with("}\n"));
+
+        let scss = export(&theme(), Format::Scss);
+        assert!(scss.contains("$color-primary: #BD93F9;\n"));
+        assert!(scss.contains("$font-size-mono: 10;\n"));
+    }
+
+    #[test]
+    fn test_gpl() {
+        let out = export(&theme(), Format::Gpl);
+        assert!(out.starts_with("GIMP Palette\nName: Sweet Dracula\n"));
+        assert!(out.contains(" 22  25  37\tbg\n"));
+        // Palettes have no alpha, so translucent colors are left out
+        assert!(!out.contains("accent_2"));
+    }
+
+    #[test]
+    fn test_osc() {
+        let out = export(&theme(), Format::Osc);
+        assert!(out.starts_with("#!/bin/sh\n"));
+        assert!(out.contains("printf '\\033]4;1;#DE312B\\033\\\\'\n"));
+        assert!(out.contains("printf '\\033]11;#161925\\033\\\\'\n"));
+    }
+
+    #[test]
+    fn test_format_names() {
+        for format in Format::ALL {
+            assert_eq!(format.as_str().parse::<Format>().unwrap(), *format);
+        }
+        assert!("yaml".parse::<Format>().is_err());
+    }
+}
@@ -0,0 +1,369 @@
+use crate::audit;
+use crate::color::{Palette, Rgb};
+use anyhow::{Context, Result};
+use log::debug;
+use std::collections::HashMap;
+use std::path::Path;
+
+/// Number of clusters the image is reduced to.
+const CLUSTERS: usize = 8;
+const MAX_ITERATIONS: usize = 20;
+/// Images are downscaled before clustering; detail does not change the palette.
+const SAMPLE_SIZE: u32 = 128;
+
+/// Minimum WCAG contrast of `fg` against `bg`, above the AA minimum the
+/// audit asks of other text (see [`audit::check`]).
+const CONTRAST_FG: f64 = 7.0;
+
+/// ANSI hues the chromatic slots are anchored to, in degrees.
+const ANSI_HUES: &[(&str, f64)] = &[
+    ("red", 0.0),
+    ("yellow", 55.0),
+    ("green", 120.0),
+    ("cyan", 185.0),
+    ("blue", 220.0),
+    ("magenta", 300.0),
+];
+/// An image color within this many degrees of an ANSI hue is used for that slot.
+const HUE_TOLERANCE: f64 = 25.0;
+
+/// Decodes the image at `path` locally and derives a palette from it.
+pub fn extract(path: &Path, light: bool) -> Result<Palette> {
+    debug!("Extracting palette from {:?}", path);
+    let image =
+        image::open(path).with_context(|| format!("Failed to decode image {}", path.display()))?;
+    let sample = image.thumbnail(SAMPLE_SIZE, SAMPLE_SIZE).to_rgb8();
+    let pixels: Vec<Rgb> = sample
+        .pixels()
+        .map(|p| Rgb::new(p[0], p[1], p[2]))
+        .collect();
+    Ok(from_pixels(&pixels, light))
+}
+
+/// Clusters `pixels` into dominant colors and assigns them to theme keys.
+pub fn from_pixels(pixels: &[Rgb], light: bool) -> Palette {
+    let clusters = kmeans(pixels, CLUSTERS);
+    debug!("Dominant colors: {:?}", clusters);
+
+    let (dom_hue, dom_sat, _) = clusters
+        .first()
+        .map(|(c, _)| c.to_hsl())
+        .unwrap_or((0.0, 0.0, 0.5));
+
+    // Colorful clusters, most prominent first, feed the accents
+    let mut accents: Vec<(f64, f64, f64)> = clusters
+        .iter()
+        .filter_map(|(c, count)| {
+            let (h, s, l) = c.to_hsl();
+            (s >= 0.2 && (0.15..=0.9).contains(&l)).then_some((h, s, *count as f64 * s))
+        })
+        .collect();
+    accents.sort_by(|a, b| b.2.total_cmp(&a.2));
+    let accent_sat = if accents.is_empty() {
+        0.6
+    } else {
+        (accents.iter().map(|a| a.1).sum::<f64>() / accents.len() as f64).clamp(0.5, 0.8)
+    };
+
+    // Lightness of normal and bright accents for the chosen mode
+    let (normal_l, bright_l) = if light { (0.40, 0.32) } else { (0.60, 0.72) };
+
+    let bg = if light {
+        Rgb::from_hsl(dom_hue, dom_sat.min(0.25), 0.95)
+    } else {
+        Rgb::from_hsl(dom_hue, dom_sat.min(0.35), 0.09)
+    };
+    let adjust = |color: Rgb, min: f64| audit::adjust(color, bg, min);
+
+    let fg = adjust(
+        Rgb::from_hsl(dom_hue, dom_sat.min(0.15), if light { 0.15 } else { 0.92 }),
+        CONTRAST_FG,
+    );
+
+    let (primary_hue, primary_sat) = accents
+        .first()
+        .map(|a| (a.0, a.1.max(0.45)))
+        .unwrap_or((dom_hue, accent_sat));
+    let (secondary_hue, secondary_sat) = accents
+        .iter()
+        .find(|a| hue_distance(a.0, primary_hue) >= 40.0)
+        .map(|a| (a.0, a.1.max(0.45)))
+        .unwrap_or((primary_hue + 60.0, primary_sat));
+
+    // Each ANSI slot takes the image's own shade of that hue when it has one
+    let slot = |anchor: f64| {
+        accents
+            .iter()
+            .filter(|a| hue_distance(a.0, anchor) <= HUE_TOLERANCE)
+            .min_by(|a, b| hue_distance(a.0, anchor).total_cmp(&hue_distance(b.0, anchor)))
+            .map(|a| (a.0, a.1.max(0.45)))
+            .unwrap_or((anchor, accent_sat))
+    };
+
+    let mut colors = HashMap::new();
+    for (name, anchor) in ANSI_HUES {
+        let (h, s) = slot(*anchor);
+        colors.insert(*name, adjust(Rgb::from_hsl(h, s, normal_l), audit::TEXT));
+        colors.insert(
+            bright_key(name),
+            adjust(Rgb::from_hsl(h, s, bright_l), audit::TEXT),
+        );
+    }
+
+    let neutral = |l: f64| Rgb::from_hsl(dom_hue, dom_sat.min(0.15), l);
+    if light {
+        colors.insert("black", fg);
+        colors.insert("white", neutral(0.75));
+        colors.insert("bright_white", neutral(0.90));
+    } else {
+        colors.insert("black", neutral(0.25));
+        colors.insert("white", neutral(0.80));
+        colors.insert("bright_white", fg);
+    }
+    colors.insert("bright_black", adjust(neutral(0.45), audit::MUTED));
+
+    let (orange_hue, orange_sat) = slot(30.0);
+    let orange = adjust(Rgb::from_hsl(orange_hue, orange_sat, bright_l), audit::TEXT);
+    let primary = adjust(
+        Rgb::from_hsl(primary_hue, primary_sat, bright_l),
+        audit::TEXT,
+    );
+    let secondary = adjust(
+        Rgb::from_hsl(secondary_hue, secondary_sat, bright_l),
+        audit::TEXT,
+    );
+
+    let selection_bg = Rgb::from_hsl(primary_hue, 0.3, if light { 0.85 } else { 0.25 });
+    let selection_fg = audit::adjust(fg, selection_bg, audit::TEXT);
+
+    colors.insert("bg", bg);
+    colors.insert("fg", fg);
+    colors.insert("cursor", primary);
+    colors.insert("selection_bg", selection_bg);
+    colors.insert("selection_fg", selection_fg);
+    colors.insert("tabs", bg);
+    colors.insert("tabs_active", primary);
+    colors.insert("primary", primary);
+    colors.insert("secondary", secondary);
+    colors.insert("success", colors["bright_green"]);
+    colors.insert("error", colors["bright_red"]);
+    colors.insert("warn", orange);
+    colors.insert("info", colors["bright_cyan"]);
+    colors.insert("orange", orange);
+
+    // Slots copied from others (black, white) are checked here too
+    let mut palette = Palette { colors };
+    audit::enforce(&mut palette);
+    palette
+}
+
+fn bright_key(name: &str) -> &'static str {
+    match name {
+        "red" => "bright_red",
+        "yellow" => "bright_yellow",
+        "green" => "bright_green",
+        "cyan" => "bright_cyan",
+        "blue" => "bright_blue",
+        _ => "bright_magenta",
+    }
+}
+
+fn hue_distance(a: f64, b: f64) -> f64 {
+    let d = (a - b).rem_euclid(360.0);
+    d.min(360.0 - d)
+}
+
+/// K-means in RGB space with deterministic farthest-point seeding, so the same
+/// image always yields the same palette. Returns centers, most populous first.
+fn kmeans(pixels: &[Rgb], k: usize) -> Vec<(Rgb, usize)> {
+    if pixels.is_empty() {
+        return Vec::new();
+    }
+    let points: Vec<[f64; 3]> = pixels
+        .iter()
+        .map(|p| [p.r as f64, p.g as f64, p.b as f64])
+        .collect();
+    let dist = |a: &[f64; 3], b: &[f64; 3]| {
+        (a[0] - b[0]).powi(2) + (a[1] - b[1]).powi(2) + (a[2] - b[2]).powi(2)
+    };
+    let nearest = |p: &[f64; 3], centers: &[[f64; 3]]| {
+        centers
+            .iter()
+            .enumerate()
+            .map(|(i, c)| (i, dist(p, c)))
+            .min_by(|a, b| a.1.total_cmp(&b.1))
+            .expect("at least one center")
+    };
+
+    // Seed with the pixel closest to the mean, then repeatedly the farthest one
+    let n = points.len() as f64;
+    let mean = points.iter().fold([0.0; 3], |acc, p| {
+        [acc[0] + p[0] / n, acc[1] + p[1] / n, acc[2] + p[2] / n]
+    });
+    let first = points
+        .iter()
+        .min_by(|a, b| dist(a, &mean).total_cmp(&dist(b, &mean)))
+        .copied()
+        .expect("non-empty");
+    let mut centers = vec![first];
+    while centers.len() < k {
+        let (far, d) = points
+            .iter()
+            .map(|p| (p, nearest(p, &centers).1))
+            .max_by(|a, b| a.1.total_cmp(&b.1))
+            .expect("non-empty");
+        if d == 0.0 {
+            break; // Fewer distinct colors than clusters
+        }
+        centers.push(*far);
+    }
+
+    let mut assignment = vec![0; points.len()];
+    for _ in 0..MAX_ITERATIONS {
+        let mut changed = false;
+        for (i, p) in points.iter().enumerate() {
+            let (c, _) = nearest(p, &centers);
+            if assignment[i] != c {
+                assignment[i] = c;
+                changed = true;
+            }
+        }
+
+        let mut sums = vec![[0.0; 3]; centers.len()];
+        let mut counts = vec![0usize; centers.len()];
+        for (p, &c) in points.iter().zip(&assignment) {
+            sums[c][0] += p[0];
+            sums[c][1] += p[1];
+            sums[c][2] += p[2];
+            counts[c] += 1;
+        }
+        for (c, center) in centers.iter_mut().enumerate() {
+            if counts[c] > 0 {
+                let n = counts[c] as f64;
+                *center = [sums[c][0] / n, sums[c][1] / n, sums[c][2] / n];
+            }
+        }
+        if !changed {
+            break;
+        }
+    }
+
+    let mut counts = vec![0usize; centers.len()];
+    for &c in &assignment {
+        counts[c] += 1;
+    }
+    let mut clusters: Vec<(Rgb, usize)> = centers
+        .iter()
+        .zip(counts)
+        .filter(|(_, count)| *count > 0)
+        .map(|(c, count)| {
+            let to_u8 = |v: f64| v.round().clamp(0.0, 255.0) as u8;
+            (Rgb::new(to_u8(c[0]), to_u8(c[1]), to_u8(c[2])), count)
+        })
+        .collect();
+    clusters.sort_by_key(|c| std::cmp::Reverse(c.1));
+    clusters
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color::THEME_KEYS;
+    use crate::config::{IconChain, ThemeConfig};
+    use tempfile::tempdir;
+
+    /// A mostly dark-blue "wallpaper" with some orange and teal.
+    fn ocean() -> Vec<Rgb> {
+        let mut pixels = vec![Rgb::new(0x10, 0x2a, 0x4a); 600];
+        pixels.extend(vec![Rgb::new(0xe0, 0x7a, 0x30); 150]);
+        pixels.extend(vec![Rgb::new(0x20, 0xb0, 0xa0); 250]);
+        pixels
+    }
+
+    fn assert_contrast(palette: &Palette) {
+        let bg = palette.get("bg").unwrap();
+        assert!(palette.get("fg").unwrap().contrast(bg) >= CONTRAST_FG);
+        for key in ["primary", "secondary", "success", "error", "warn", "info"] {
+            let c = palette.get(key).unwrap();
+            assert!(c.contrast(bg) >= audit::TEXT, "{} {}", key, c);
+        }
+        for (name, _) in ANSI_HUES {
+            for key in [*name, bright_key(name)] {
+                let c = palette.get(key).unwrap();
+                assert!(c.contrast(bg) >= audit::TEXT, "{} {}", key, c);
+            }
+        }
+        assert!(palette.get("bright_black").unwrap().contrast(bg) >= audit::MUTED);
+        assert!(audit::check(palette).iter().all(audit::Check::passed));
+    }
+
+    #[test]
+    fn test_palette_is_complete_and_readable() {
+        let palette = from_pixels(&ocean(), false);
+        for (_, keys) in THEME_KEYS {
+            for key in *keys {
+                assert!(palette.get(key).is_some(), "missing {}", key);
+            }
+        }
+        assert!(palette.get("bg").unwrap().luminance() < 0.05);
+        assert_contrast(&palette);
+
+        // Blue dominates, so the background keeps its hue
+        let (h, _, _) = palette.get("bg").unwrap().to_hsl();
+        assert!(hue_distance(h, 212.0) < 15.0, "bg hue {}", h);
+        // The image's own teal is used for cyan
+        let (h, _, _) = palette.get("cyan").unwrap().to_hsl();
+        assert!(hue_distance(h, 173.0) < 5.0, "cyan hue {}", h);
+    }
+
+    #[test]
+    fn test_light_palette() {
+        let palette = from_pixels(&ocean(), true);
+        assert!(palette.get("bg").unwrap().luminance() > 0.8);
+        assert_contrast(&palette);
+    }
+
+    #[test]
+    fn test_grayscale_image() {
+        let pixels: Vec<Rgb> = (0..=255u8).map(|v| Rgb::new(v, v, v)).collect();
+        let palette = from_pixels(&pixels, false);
+        assert_contrast(&palette);
+    }
+
+    #[test]
+    fn test_extract_writes_valid_theme() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("wall.png");
+        let pixels = ocean();
+        let img = image::RgbImage::from_fn(40, 25, |x, y| {
+            let p = pixels[(y * 40 + x) as usize];
+            image::Rgb([p.r, p.g, p.b])
+        });
+        img.save(&path).unwrap();
+
+        let palette = extract(&path, false).unwrap();
+        let base: ThemeConfig = toml::from_str(
+            "[meta]\nname = \"base\"\n[settings]\nactive_icons = \"nerdfont\"\n[colors]\n[fonts]\nmono = \"JetBrainsMono Nerd Font\"\n",
+        )
+        .unwrap();
+        let text = palette.to_theme_toml("ocean", "Test palette", &base);
+
+        let theme: ThemeConfig = toml::from_str(&text).unwrap();
+        assert_eq!(theme.meta.name, "ocean");
+        assert_eq!(
+            theme.settings.active_icons,
+            IconChain::One("nerdfont".to_string())
+        );
+        assert_eq!(theme.fonts["mono"], "JetBrainsMono Nerd Font");
+        assert_eq!(theme.colors.len(), palette.colors.len());
+        assert_eq!(theme.colors["bg"].rgb(), palette.get("bg").unwrap());
+    }
+
+    #[test]
+    fn test_extract_rejects_non_images() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("notes.png");
+        std::fs::write(&path, "not an image").unwrap();
+        assert!(extract(&path, false).is_err());
+    }
+}
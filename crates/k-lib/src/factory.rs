@@ -1,20 +1,16 @@
-use crate::config::Cookbook;
-use colored::CustomColor;
+use crate::logger::LogStyle;
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
 
 pub struct TagFactory;
 
 impl TagFactory {
-    pub fn create_tag(config: &Cookbook, level: &str) -> String {
+    pub fn create_tag(config: &impl LogStyle, level: &str) -> String {
         // 1. Lookup Label
-        let label = config
-            .layout
-            .labels
-            .get(level)
-            .map(|s| s.as_str())
-            .unwrap_or(level);
+        let label = config.label(level).unwrap_or(level);
+        let tag = config.tag(level);
 
         // 2. Transform
-        let transformed = match config.layout.tag.transform.as_str() {
+        let transformed = match tag.transform {
             "uppercase" => label.to_uppercase(),
             "lowercase" => label.to_lowercase(),
             "capitalize" => {
@@ -27,58 +23,52 @@ impl TagFactory {
             _ => label.to_string(),
         };
 
-        // 3. Pad
-        let width = config.layout.tag.min_width;
-        // Basic centering logic
-        let len = transformed.chars().count();
+        // 3. Cut and pad, in terminal columns: a wide glyph takes two
+        let fitted = match tag.max_width {
+            Some(max) => truncate(&transformed, max),
+            None => transformed,
+        };
+        let width = tag.min_width;
+        let len = fitted.width();
         let padded = if len >= width {
-            transformed
+            fitted
         } else {
             let total_padding = width - len;
-            let left_pad = total_padding / 2;
-            let right_pad = total_padding - left_pad;
+            let (left_pad, right_pad) = match tag.alignment {
+                "left" => (0, total_padding),
+                "right" => (total_padding, 0),
+                _ => (total_padding / 2, total_padding - total_padding / 2),
+            };
             format!(
                 "{}{}{}",
                 " ".repeat(left_pad),
-                transformed,
+                fitted,
                 " ".repeat(right_pad)
             )
         };
 
         // 4. Bracket
-        format!(
-            "{}{}{}",
-            config.layout.tag.prefix, padded, config.layout.tag.suffix
-        )
+        format!("{}{}{}", tag.prefix, padded, tag.suffix)
     }
 }
 
-pub struct ColorResolver;
-
-impl ColorResolver {
-    pub fn hex_to_color(hex: &str) -> CustomColor {
-        let hex = hex.trim_start_matches('#');
-        if hex.len() == 6 {
-            let r = u8::from_str_radix(&hex[0..2], 16).unwrap_or(255);
-            let g = u8::from_str_radix(&hex[2..4], 16).unwrap_or(255);
-            let b = u8::from_str_radix(&hex[4..6], 16).unwrap_or(255);
-            CustomColor { r, g, b }
-        } else {
-            CustomColor {
-                r: 255,
-                g: 255,
-                b: 255,
-            }
-        }
-    }
+/// The longest prefix of `s` at most `max` columns wide.
+fn truncate(s: &str, max: usize) -> String {
+    let mut width = 0;
+    s.chars()
+        .take_while(|c| {
+            width += c.width().unwrap_or(0);
+            width <= max
+        })
+        .collect()
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::config::{
-        Cookbook, DictionaryConfig, IconsConfig, LayoutConfig, LoggingConfig, StructureConfig,
-        TagConfig, ThemeConfig, ThemeMeta, ThemeSettings,
+        Cookbook, DictionaryConfig, IconChain, IconsConfig, LayoutConfig, LevelTagConfig,
+        LoggingConfig, StructureConfig, TagConfig, ThemeConfig, ThemeMeta, ThemeSettings,
     };
     use std::collections::HashMap;
 
@@ -89,15 +79,18 @@ mod tests {
                     name: "Test".to_string(),
                 },
                 settings: ThemeSettings {
-                    active_icons: "nerdfont".to_string(),
+                    active_icons: IconChain::One("nerdfont".into()),
+                    schedule: None,
                 },
                 colors: HashMap::new(),
+                spellings: HashMap::new(),
                 fonts: HashMap::new(),
                 include: None,
+                variants: HashMap::new(),
+                variant: None,
             },
             icons: IconsConfig {
-                nerdfont: HashMap::new(),
-                ascii: HashMap::new(),
+                sets: HashMap::new(),
                 include: None,
             },
             layout: LayoutConfig {
@@ -107,6 +100,8 @@ mod tests {
                     transform: "uppercase".to_string(),
                     min_width: 10,
                     alignment: "center".to_string(),
+                    max_width: None,
+                    levels: HashMap::new(),
                 },
                 labels: HashMap::from([("error".to_string(), "Error".to_string())]),
                 structure: StructureConfig {
@@ -186,33 +181,50 @@ mod tests {
     }
 
     #[test]
-    fn test_color_resolver_valid() {
-        let color = ColorResolver::hex_to_color("#ff0000");
-        assert_eq!(color.r, 255);
-        assert_eq!(color.g, 0);
-        assert_eq!(color.b, 0);
-
-        let color = ColorResolver::hex_to_color("00ff00");
-        assert_eq!(color.r, 0);
-        assert_eq!(color.g, 255);
-        assert_eq!(color.b, 0);
-
-        let color = ColorResolver::hex_to_color("#0000ff");
-        assert_eq!(color.r, 0);
-        assert_eq!(color.g, 0);
-        assert_eq!(color.b, 255);
+    fn test_tag_alignment() {
+        let mut config = create_mock_config();
+        config.layout.tag.alignment = "left".to_string();
+        assert_eq!(TagFactory::create_tag(&config, "error"), "[ERROR     ]");
+        config.layout.tag.alignment = "right".to_string();
+        assert_eq!(TagFactory::create_tag(&config, "error"), "[     ERROR]");
     }
 
     #[test]
-    fn test_color_resolver_invalid() {
-        // Invalid length
-        let c = ColorResolver::hex_to_color("123");
-        assert_eq!(c.r, 255);
-        assert_eq!(c.g, 255);
-        assert_eq!(c.b, 255);
-
-        // Invalid chars (g is not hex) - from_str_radix will fail, unwrap_or(255)
-        let c = ColorResolver::hex_to_color("gg0000");
-        assert_eq!(c.r, 255);
+    fn test_tag_pads_by_display_width() {
+        let mut config = create_mock_config();
+        // Two glyphs, four columns
+        config
+            .layout
+            .labels
+            .insert("warn".to_string(), "警告".to_string());
+        assert_eq!(TagFactory::create_tag(&config, "warn"), "[   警告   ]");
+
+        config.layout.tag.max_width = Some(3);
+        assert_eq!(TagFactory::create_tag(&config, "warn"), "[    警    ]");
+        assert_eq!(TagFactory::create_tag(&config, "error"), "[   ERR    ]");
+    }
+
+    #[test]
+    fn test_tag_level_overrides() {
+        let mut config = create_mock_config();
+        config.layout.tag.levels.insert(
+            "error".to_string(),
+            LevelTagConfig {
+                prefix: Some(" ".to_string()),
+                suffix: Some(" ".to_string()),
+                transform: Some("lowercase".to_string()),
+                bold: true,
+                reverse: true,
+                ..Default::default()
+            },
+        );
+        assert_eq!(TagFactory::create_tag(&config, "error"), "   error    ");
+        // Other levels keep `[tag]`
+        assert_eq!(TagFactory::create_tag(&config, "info"), "[   INFO   ]");
+
+        let style = config.tag("error");
+        assert!(style.bold && style.reverse);
+        assert_eq!(style.fg, None);
+        assert!(!config.tag("info").bold);
     }
 }
@@ -0,0 +1,176 @@
+use std::io::{self, Read, Write};
+use thiserror::Error;
+
+/// Version of k-lib writing the files, recorded in every header.
+pub const LIB_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// A binary file kitchn writes with bincode. bincode has no field tags, so
+/// data written for another shape of a type can decode into wrong values;
+/// the header says which shape it was written for.
+///
+/// Layout: 8 bytes of magic, the format version (`u32`, little endian), then
+/// the k-lib version as a `u16` length and UTF-8 bytes.
+#[derive(Debug, Clone, Copy)]
+pub struct FileFormat {
+    pub name: &'static str,
+    pub magic: [u8; 8],
+    /// Bumped whenever the encoded types change.
+    pub version: u32,
+}
+
+/// The baked cookbook. A cache, so any mismatch means re-reading the TOMLs.
+pub const PASTRY: FileFormat = FileFormat {
+    name: "pastry.bin",
+    magic: *b"KITCHN\0C",
+    version: 1,
+};
+
+/// The pantry. A headerless file is the bare ingredient map from before
+/// targets were tracked, and is migrated on load.
+pub const PANTRY: FileFormat = FileFormat {
+    name: "pantry.db",
+    magic: *b"KITCHN\0P",
+    version: 1,
+};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Header {
+    pub version: u32,
+    pub lib_version: String,
+}
+
+#[derive(Error, Debug)]
+pub enum FormatError {
+    #[error("{file} has no header (written by an older kitchn)")]
+    Missing { file: &'static str },
+    #[error(
+        "{file} is format {found} from k-lib {lib_version}, expected format {expected} from k-lib {LIB_VERSION}"
+    )]
+    Mismatch {
+        file: &'static str,
+        found: u32,
+        expected: u32,
+        lib_version: String,
+    },
+    #[error("{file} has a corrupt header")]
+    Corrupt { file: &'static str },
+    #[error("IO error: {0}")]
+    Io(#[from] io::Error),
+}
+
+impl FileFormat {
+    pub fn write_header(&self, w: &mut impl Write) -> io::Result<()> {
+        let lib_version = LIB_VERSION.as_bytes();
+        w.write_all(&self.magic)?;
+        w.write_all(&self.version.to_le_bytes())?;
+        w.write_all(&(lib_version.len() as u16).to_le_bytes())?;
+        w.write_all(lib_version)
+    }
+
+    /// Reads the header off `r`. `None` if `r` does not start with the
+    /// magic: a file from before headers existed, or not this file at all.
+    pub fn read_header(&self, r: &mut impl Read) -> Result<Option<Header>, FormatError> {
+        let mut magic = [0; 8];
+        match r.read_exact(&mut magic) {
+            Ok(()) if magic == self.magic => {}
+            Ok(()) => return Ok(None),
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+            Err(e) => return Err(e.into()),
+        }
+
+        let corrupt = |e: io::Error| match e.kind() {
+            io::ErrorKind::UnexpectedEof => FormatError::Corrupt { file: self.name },
+            _ => FormatError::Io(e),
+        };
+        let mut version = [0; 4];
+        r.read_exact(&mut version).map_err(corrupt)?;
+        let mut len = [0; 2];
+        r.read_exact(&mut len).map_err(corrupt)?;
+        let mut lib_version = vec![0; u16::from_le_bytes(len) as usize];
+        r.read_exact(&mut lib_version).map_err(corrupt)?;
+
+        Ok(Some(Header {
+            version: u32::from_le_bytes(version),
+            lib_version: String::from_utf8(lib_version)
+                .map_err(|_| FormatError::Corrupt { file: self.name })?,
+        }))
+    }
+
+    /// Reads the header off `r`, which must have been written by this very
+    /// k-lib version in the current format.
+    pub fn read_current(&self, r: &mut impl Read) -> Result<Header, FormatError> {
+        let header = self
+            .read_header(r)?
+            .ok_or(FormatError::Missing { file: self.name })?;
+        if header.version != self.version || header.lib_version != LIB_VERSION {
+            return Err(self.mismatch(header));
+        }
+        Ok(header)
+    }
+
+    pub fn mismatch(&self, header: Header) -> FormatError {
+        FormatError::Mismatch {
+            file: self.name,
+            found: header.version,
+            expected: self.version,
+            lib_version: header.lib_version,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_header_roundtrip() {
+        let mut bytes = Vec::new();
+        PASTRY.write_header(&mut bytes).unwrap();
+        bytes.extend_from_slice(b"payload");
+
+        let mut rest = &bytes[..];
+        let header = PASTRY.read_current(&mut rest).unwrap();
+        assert_eq!(header.version, PASTRY.version);
+        assert_eq!(header.lib_version, LIB_VERSION);
+        assert_eq!(rest, b"payload");
+
+        // Another file's magic is no header at all
+        assert_eq!(PANTRY.read_header(&mut &bytes[..]).unwrap(), None);
+        assert!(matches!(
+            PANTRY.read_current(&mut &bytes[..]),
+            Err(FormatError::Missing { .. })
+        ));
+        assert_eq!(PASTRY.read_header(&mut &b"KIT"[..]).unwrap(), None);
+        assert!(matches!(
+            PASTRY.read_header(&mut &bytes[..12]),
+            Err(FormatError::Corrupt { .. })
+        ));
+    }
+
+    #[test]
+    fn test_mismatch_is_rejected() {
+        let newer = FileFormat {
+            version: PASTRY.version + 1,
+            ..PASTRY
+        };
+        let mut bytes = Vec::new();
+        newer.write_header(&mut bytes).unwrap();
+        let err = PASTRY.read_current(&mut &bytes[..]).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            format!(
+                "pastry.bin is format 2 from k-lib {0}, expected format 1 from k-lib {0}",
+                LIB_VERSION
+            )
+        );
+
+        let mut bytes = PASTRY.magic.to_vec();
+        bytes.extend_from_slice(&PASTRY.version.to_le_bytes());
+        bytes.extend_from_slice(&5u16.to_le_bytes());
+        bytes.extend_from_slice(b"0.0.1");
+        assert!(matches!(
+            PASTRY.read_current(&mut &bytes[..]),
+            Err(FormatError::Mismatch { .. })
+        ));
+    }
+}
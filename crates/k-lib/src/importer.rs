@@ -0,0 +1,639 @@
+use crate::color::{ANSI_KEYS, Palette, Rgb, THEME_KEYS};
+use anyhow::{Context, Result, anyhow};
+use log::debug;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+
+/// Color scheme formats `kitchn theme import` understands.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// base16 / base24 YAML (classic flat keys or the newer `palette:` table)
+    Base16,
+    /// Alacritty `colors` section, TOML or YAML
+    Alacritty,
+    /// kitty `.conf`
+    Kitty,
+    /// X resources (`*.color0: #...`, `#define` aware)
+    Xresources,
+    /// Windows Terminal color scheme JSON, or a `settings.json` with `schemes`
+    WindowsTerminal,
+}
+
+impl Format {
+    pub const ALL: &[Format] = &[
+        Format::Base16,
+        Format::Alacritty,
+        Format::Kitty,
+        Format::Xresources,
+        Format::WindowsTerminal,
+    ];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Format::Base16 => "base16",
+            Format::Alacritty => "alacritty",
+            Format::Kitty => "kitty",
+            Format::Xresources => "xresources",
+            Format::WindowsTerminal => "windows-terminal",
+        }
+    }
+
+    /// Guesses the format from the file name, looking at `content` where the
+    /// extension is ambiguous (YAML is used by both base16 and Alacritty).
+    pub fn detect(path: &Path, content: &str) -> Option<Format> {
+        let file_name = path.file_name()?.to_string_lossy().to_lowercase();
+        let ext = path
+            .extension()
+            .map(|e| e.to_string_lossy().to_lowercase())
+            .unwrap_or_default();
+        match ext.as_str() {
+            "json" => Some(Format::WindowsTerminal),
+            "conf" => Some(Format::Kitty),
+            "toml" => Some(Format::Alacritty),
+            "yaml" | "yml" => {
+                let is_alacritty = content.lines().any(|l| l.trim_end().starts_with("colors:"));
+                Some(if is_alacritty {
+                    Format::Alacritty
+                } else {
+                    Format::Base16
+                })
+            }
+            _ if file_name.contains("xresources") || file_name.contains("xdefaults") => {
+                Some(Format::Xresources)
+            }
+            _ if content.lines().any(|l| {
+                let l = l.trim_start();
+                (l.starts_with('*') || l.contains("*color")) && l.contains(':')
+            }) =>
+            {
+                Some(Format::Xresources)
+            }
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Format {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for Format {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Format::ALL
+            .iter()
+            .copied()
+            .find(|f| f.as_str() == s)
+            .ok_or_else(|| anyhow!("unknown scheme format '{}'", s))
+    }
+}
+
+/// A key kitchn expects that the source scheme did not provide.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Gap {
+    pub key: &'static str,
+    /// The key whose color was used instead, if any.
+    pub filled_from: Option<&'static str>,
+}
+
+#[derive(Debug)]
+pub struct Imported {
+    /// Scheme name as given by the source, if it has one.
+    pub name: Option<String>,
+    pub format: Format,
+    pub palette: Palette,
+    pub gaps: Vec<Gap>,
+}
+
+/// Where semantic keys come from, in order of preference. Mirrors how the
+/// shipped theme maps its palette (e.g. `success` is the bright green).
+const DERIVED: &[(&str, &[&str])] = &[
+    ("primary", &["bright_blue", "blue"]),
+    ("secondary", &["bright_magenta", "magenta"]),
+    ("success", &["bright_green", "green"]),
+    ("error", &["bright_red", "red"]),
+    ("info", &["bright_cyan", "cyan"]),
+    ("warn", &["orange", "bright_yellow", "yellow"]),
+    ("tabs", &["bg"]),
+    ("tabs_active", &["primary"]),
+];
+
+/// Stand-ins for keys a source format may lack. Using one is reported as a gap.
+const FALLBACKS: &[(&str, &[&str])] = &[
+    ("cursor", &["fg"]),
+    ("selection_bg", &["bright_black", "black"]),
+    ("selection_fg", &["fg"]),
+    ("orange", &["bright_yellow", "yellow"]),
+    ("black", &["bg"]),
+    ("white", &["fg"]),
+    ("bright_black", &["black"]),
+    ("bright_white", &["white", "fg"]),
+    ("red", &["bright_red"]),
+    ("green", &["bright_green"]),
+    ("yellow", &["bright_yellow"]),
+    ("blue", &["bright_blue"]),
+    ("magenta", &["bright_magenta"]),
+    ("cyan", &["bright_cyan"]),
+    ("bright_red", &["red"]),
+    ("bright_green", &["green"]),
+    ("bright_yellow", &["yellow"]),
+    ("bright_blue", &["blue"]),
+    ("bright_magenta", &["magenta"]),
+    ("bright_cyan", &["cyan"]),
+];
+
+/// Reads `path` and converts it to a kitchn palette. `format` overrides
+/// detection; `scheme` picks one scheme from a file that holds several.
+pub fn import(path: &Path, format: Option<Format>, scheme: Option<&str>) -> Result<Imported> {
+    let content =
+        fs::read_to_string(path).with_context(|| format!("Failed to read {}", path.display()))?;
+    let format = match format.or_else(|| Format::detect(path, &content)) {
+        Some(format) => format,
+        None => {
+            return Err(anyhow!(
+                "Cannot tell the format of {}, pass --format",
+                path.display()
+            ));
+        }
+    };
+    debug!("Importing {:?} as {}", path, format);
+    parse(&content, format, scheme)
+        .with_context(|| format!("Failed to import {} as {}", path.display(), format))
+}
+
+/// Converts scheme source text in the given format.
+pub fn parse(content: &str, format: Format, scheme: Option<&str>) -> Result<Imported> {
+    let (name, source) = match format {
+        Format::Base16 => base16(content)?,
+        Format::Alacritty => alacritty(content)?,
+        Format::Kitty => kitty(content),
+        Format::Xresources => xresources(content),
+        Format::WindowsTerminal => windows_terminal(content, scheme)?,
+    };
+    if source.is_empty() {
+        return Err(anyhow!("no colors found"));
+    }
+    let (palette, gaps) = complete(source);
+    Ok(Imported {
+        name,
+        format,
+        palette,
+        gaps,
+    })
+}
+
+/// Fills in what the source left out and derives the semantic keys.
+fn complete(mut colors: HashMap<&'static str, Rgb>) -> (Palette, Vec<Gap>) {
+    let mut gaps = Vec::new();
+
+    for (key, sources) in FALLBACKS {
+        if colors.contains_key(key) {
+            continue;
+        }
+        let found = sources.iter().find_map(|s| colors.get(s).map(|c| (*s, *c)));
+        if let Some((from, color)) = found {
+            colors.insert(key, color);
+            gaps.push(Gap {
+                key,
+                filled_from: Some(from),
+            });
+        }
+    }
+
+    for (key, sources) in DERIVED {
+        if let Some(color) = sources.iter().find_map(|s| colors.get(s).copied()) {
+            colors.insert(key, color);
+        }
+    }
+
+    for (_, keys) in THEME_KEYS {
+        for key in *keys {
+            if !colors.contains_key(key) {
+                gaps.push(Gap {
+                    key,
+                    filled_from: None,
+                });
+            }
+        }
+    }
+
+    (Palette { colors }, gaps)
+}
+
+/// Accepts `#rrggbb`, `rrggbb` and Alacritty's `0xrrggbb`.
+fn color(value: &str) -> Option<Rgb> {
+    let value = value.trim().trim_matches(|c| c == '"' || c == '\'');
+    Rgb::from_hex(value.strip_prefix("0x").unwrap_or(value))
+}
+
+fn insert(colors: &mut HashMap<&'static str, Rgb>, key: &'static str, value: Option<&Value>) {
+    if let Some(c) = value.and_then(Value::as_str).and_then(color) {
+        colors.insert(key, c);
+    }
+}
+
+type Source = (Option<String>, HashMap<&'static str, Rgb>);
+
+fn base16(content: &str) -> Result<Source> {
+    let doc: Value = serde_yaml::from_str(content)?;
+    // tinted-theming nests the colors under `palette`; classic schemes are flat
+    let palette = doc.get("palette").unwrap_or(&doc);
+    let name = ["name", "scheme"]
+        .iter()
+        .find_map(|k| doc.get(k).and_then(Value::as_str))
+        .map(str::to_string);
+
+    let mut colors = HashMap::new();
+    let base = |n: &str| palette.get(format!("base{}", n));
+    for (key, slot) in [
+        ("bg", "00"),
+        ("fg", "05"),
+        ("cursor", "05"),
+        ("selection_bg", "02"),
+        ("selection_fg", "05"),
+        ("black", "00"),
+        ("red", "08"),
+        ("green", "0B"),
+        ("yellow", "0A"),
+        ("blue", "0D"),
+        ("magenta", "0E"),
+        ("cyan", "0C"),
+        ("white", "05"),
+        ("bright_black", "03"),
+        ("bright_white", "07"),
+        ("orange", "09"),
+    ] {
+        insert(&mut colors, key, base(slot));
+    }
+
+    // base24 has real bright colors; base16 reuses the normal ones
+    for (key, base24, base16) in [
+        ("bright_red", "12", "08"),
+        ("bright_yellow", "13", "0A"),
+        ("bright_green", "14", "0B"),
+        ("bright_cyan", "15", "0C"),
+        ("bright_blue", "16", "0D"),
+        ("bright_magenta", "17", "0E"),
+    ] {
+        insert(&mut colors, key, base(base24).or_else(|| base(base16)));
+    }
+    Ok((name, colors))
+}
+
+fn alacritty(content: &str) -> Result<Source> {
+    // Older configs are YAML, current ones TOML; both share the same shape
+    let doc: Value = match toml::from_str::<toml::Value>(content) {
+        Ok(value) => serde_json::to_value(value)?,
+        Err(_) => serde_yaml::from_str(content)?,
+    };
+    let colors_table = doc
+        .get("colors")
+        .ok_or_else(|| anyhow!("no `colors` section"))?;
+    let section = |name: &str, key: &str| colors_table.get(name).and_then(|s| s.get(key));
+
+    let mut colors = HashMap::new();
+    insert(&mut colors, "bg", section("primary", "background"));
+    insert(&mut colors, "fg", section("primary", "foreground"));
+    insert(&mut colors, "cursor", section("cursor", "cursor"));
+    insert(
+        &mut colors,
+        "selection_bg",
+        section("selection", "background"),
+    );
+    insert(&mut colors, "selection_fg", section("selection", "text"));
+    for (i, key) in ANSI_KEYS.iter().enumerate() {
+        let group = if i < 8 { "normal" } else { "bright" };
+        let name = key.trim_start_matches("bright_");
+        insert(&mut colors, key, section(group, name));
+    }
+    Ok((None, colors))
+}
+
+fn kitty(content: &str) -> Source {
+    let mut colors = HashMap::new();
+    for line in content.lines() {
+        let line = line.trim();
+        if line.starts_with('#') {
+            continue;
+        }
+        let mut parts = line.split_whitespace();
+        let (Some(key), Some(value)) = (parts.next(), parts.next()) else {
+            continue;
+        };
+        let Some(c) = color(value) else {
+            continue;
+        };
+        let key = match key {
+            "background" => "bg",
+            "foreground" => "fg",
+            "cursor" => "cursor",
+            "selection_background" => "selection_bg",
+            "selection_foreground" => "selection_fg",
+            _ => match key
+                .strip_prefix("color")
+                .and_then(|n| n.parse::<usize>().ok())
+            {
+                Some(n) if n < 16 => ANSI_KEYS[n],
+                _ => continue,
+            },
+        };
+        colors.insert(key, c);
+    }
+    // kitty themes put the name in a `## name:` comment
+    let name = content.lines().find_map(|l| {
+        l.trim()
+            .strip_prefix("## name:")
+            .map(|n| n.trim().to_string())
+    });
+    (name, colors)
+}
+
+fn xresources(content: &str) -> Source {
+    let mut defines: HashMap<&str, &str> = HashMap::new();
+    let mut colors = HashMap::new();
+    for line in content.lines() {
+        let line = line.trim();
+        if line.starts_with('!') || line.is_empty() {
+            continue;
+        }
+        if let Some(rest) = line.strip_prefix("#define") {
+            let mut parts = rest.split_whitespace();
+            if let (Some(k), Some(v)) = (parts.next(), parts.next()) {
+                defines.insert(k, v);
+            }
+            continue;
+        }
+        let Some((resource, value)) = line.split_once(':') else {
+            continue;
+        };
+        let value = value.trim();
+        let value = defines.get(value).copied().unwrap_or(value);
+        let Some(c) = color(value) else {
+            continue;
+        };
+
+        // `*.color1`, `*color1`, `URxvt.color1`, ... all end in the attribute name
+        let attribute = resource
+            .rsplit(['.', '*'])
+            .next()
+            .unwrap_or(resource)
+            .trim();
+        let key = match attribute {
+            "background" => "bg",
+            "foreground" => "fg",
+            "cursorColor" => "cursor",
+            "highlightColor" => "selection_bg",
+            "highlightTextColor" => "selection_fg",
+            _ => match attribute
+                .strip_prefix("color")
+                .and_then(|n| n.parse::<usize>().ok())
+            {
+                Some(n) if n < 16 => ANSI_KEYS[n],
+                _ => continue,
+            },
+        };
+        colors.insert(key, c);
+    }
+    (None, colors)
+}
+
+fn windows_terminal(content: &str, scheme: Option<&str>) -> Result<Source> {
+    let doc: Value = serde_json::from_str(content)?;
+    let scheme_doc = match doc.get("schemes").and_then(Value::as_array) {
+        Some(schemes) => match scheme {
+            Some(wanted) => schemes
+                .iter()
+                .find(|s| s.get("name").and_then(Value::as_str) == Some(wanted))
+                .ok_or_else(|| anyhow!("no scheme named '{}'", wanted))?,
+            None => schemes
+                .first()
+                .ok_or_else(|| anyhow!("`schemes` is empty"))?,
+        },
+        None => &doc,
+    };
+    let name = scheme_doc
+        .get("name")
+        .and_then(Value::as_str)
+        .map(str::to_string);
+
+    let mut colors = HashMap::new();
+    for (key, field) in [
+        ("bg", "background"),
+        ("fg", "foreground"),
+        ("cursor", "cursorColor"),
+        ("selection_bg", "selectionBackground"),
+        ("black", "black"),
+        ("red", "red"),
+        ("green", "green"),
+        ("yellow", "yellow"),
+        ("blue", "blue"),
+        ("magenta", "purple"),
+        ("cyan", "cyan"),
+        ("white", "white"),
+        ("bright_black", "brightBlack"),
+        ("bright_red", "brightRed"),
+        ("bright_green", "brightGreen"),
+        ("bright_yellow", "brightYellow"),
+        ("bright_blue", "brightBlue"),
+        ("bright_magenta", "brightPurple"),
+        ("bright_cyan", "brightCyan"),
+        ("bright_white", "brightWhite"),
+    ] {
+        insert(&mut colors, key, scheme_doc.get(field));
+    }
+    Ok((name, colors))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hex(imported: &Imported, key: &str) -> String {
+        imported.palette.get(key).unwrap().to_hex()
+    }
+
+    fn filled_from(imported: &Imported, key: &str) -> Option<&'static str> {
+        imported
+            .gaps
+            .iter()
+            .find(|g| g.key == key)
+            .and_then(|g| g.filled_from)
+    }
+
+    #[test]
+    fn test_base16() {
+        let yaml = r#"
+scheme: "Ocean"
+author: "Someone"
+base00: "2b303b"
+base01: "343d46"
+base02: "4f5b66"
+base03: "65737e"
+base04: "a7adba"
+base05: "c0c5ce"
+base06: "dfe1e8"
+base07: "eff1f5"
+base08: "bf616a"
+base09: "d08770"
+base0A: "ebcb8b"
+base0B: "a3be8c"
+base0C: "96b5b4"
+base0D: "8fa1b3"
+base0E: "b48ead"
+base0F: "ab7967"
+"#;
+        let imported = parse(yaml, Format::Base16, None).unwrap();
+        assert_eq!(imported.name.as_deref(), Some("Ocean"));
+        assert_eq!(hex(&imported, "bg"), "#2B303B");
+        assert_eq!(hex(&imported, "fg"), "#C0C5CE");
+        assert_eq!(hex(&imported, "orange"), "#D08770");
+        assert_eq!(hex(&imported, "warn"), "#D08770");
+        assert_eq!(hex(&imported, "primary"), "#8FA1B3");
+        assert_eq!(hex(&imported, "bright_red"), "#BF616A");
+        assert!(imported.gaps.is_empty(), "{:?}", imported.gaps);
+    }
+
+    #[test]
+    fn test_base24_palette_table() {
+        let yaml = r##"
+system: "base24"
+name: "Neon"
+palette:
+  base00: "#000000"
+  base05: "#ffffff"
+  base08: "#aa0000"
+  base12: "#ff5555"
+"##;
+        let imported = parse(yaml, Format::Base16, None).unwrap();
+        assert_eq!(imported.name.as_deref(), Some("Neon"));
+        assert_eq!(hex(&imported, "red"), "#AA0000");
+        assert_eq!(hex(&imported, "bright_red"), "#FF5555");
+        assert_eq!(hex(&imported, "error"), "#FF5555");
+    }
+
+    #[test]
+    fn test_alacritty_toml_and_yaml() {
+        let toml = r##"
+[colors.primary]
+background = "#1d1f21"
+foreground = "#c5c8c6"
+
+[colors.normal]
+black = "#1d1f21"
+red = "#cc6666"
+green = "#b5bd68"
+yellow = "#f0c674"
+blue = "#81a2be"
+magenta = "#b294bb"
+cyan = "#8abeb7"
+white = "#c5c8c6"
+
+[colors.bright]
+black = "#666666"
+red = "#d54e53"
+green = "#b9ca4a"
+yellow = "#e7c547"
+blue = "#7aa6da"
+magenta = "#c397d8"
+cyan = "#70c0b1"
+white = "#eaeaea"
+"##;
+        let imported = parse(toml, Format::Alacritty, None).unwrap();
+        assert_eq!(hex(&imported, "bg"), "#1D1F21");
+        assert_eq!(hex(&imported, "bright_blue"), "#7AA6DA");
+        assert_eq!(hex(&imported, "primary"), "#7AA6DA");
+        assert_eq!(filled_from(&imported, "cursor"), Some("fg"));
+        assert_eq!(filled_from(&imported, "orange"), Some("bright_yellow"));
+
+        let yaml = "colors:\n  primary:\n    background: '0x282828'\n    foreground: '0xebdbb2'\n  normal:\n    red: '0xcc241d'\n";
+        let imported = parse(yaml, Format::Alacritty, None).unwrap();
+        assert_eq!(hex(&imported, "bg"), "#282828");
+        assert_eq!(hex(&imported, "bright_red"), "#CC241D");
+        assert_eq!(filled_from(&imported, "bright_red"), Some("red"));
+        assert!(
+            imported
+                .gaps
+                .iter()
+                .any(|g| g.key == "green" && g.filled_from.is_none())
+        );
+    }
+
+    #[test]
+    fn test_kitty() {
+        let conf = "## name: Tokyo Night\nbackground #1a1b26\nforeground #c0caf5\nselection_background #33467c\ncolor1 #f7768e\ncolor9 #ff7a93\n# color2 #000000\n";
+        let imported = parse(conf, Format::Kitty, None).unwrap();
+        assert_eq!(imported.name.as_deref(), Some("Tokyo Night"));
+        assert_eq!(hex(&imported, "selection_bg"), "#33467C");
+        assert_eq!(hex(&imported, "red"), "#F7768E");
+        assert_eq!(hex(&imported, "error"), "#FF7A93");
+        assert!(imported.palette.get("green").is_none());
+    }
+
+    #[test]
+    fn test_xresources_with_defines() {
+        let xres = "! comment\n#define bg #282a36\n*.background: bg\n*foreground: #f8f8f2\nURxvt*color4: #bd93f9\n*.color12: #caa9fa\n";
+        let imported = parse(xres, Format::Xresources, None).unwrap();
+        assert_eq!(hex(&imported, "bg"), "#282A36");
+        assert_eq!(hex(&imported, "fg"), "#F8F8F2");
+        assert_eq!(hex(&imported, "blue"), "#BD93F9");
+        assert_eq!(hex(&imported, "primary"), "#CAA9FA");
+    }
+
+    #[test]
+    fn test_windows_terminal() {
+        let json = r##"{
+            "schemes": [
+                { "name": "One", "background": "#000000", "foreground": "#FFFFFF", "purple": "#AA00AA" },
+                { "name": "Two", "background": "#101010", "foreground": "#EEEEEE", "brightPurple": "#FF55FF" }
+            ]
+        }"##;
+        let imported = parse(json, Format::WindowsTerminal, None).unwrap();
+        assert_eq!(imported.name.as_deref(), Some("One"));
+        assert_eq!(hex(&imported, "magenta"), "#AA00AA");
+
+        let imported = parse(json, Format::WindowsTerminal, Some("Two")).unwrap();
+        assert_eq!(hex(&imported, "bg"), "#101010");
+        assert_eq!(hex(&imported, "secondary"), "#FF55FF");
+
+        assert!(parse(json, Format::WindowsTerminal, Some("Three")).is_err());
+    }
+
+    #[test]
+    fn test_detect() {
+        let p = Path::new;
+        assert_eq!(
+            Format::detect(p("x.json"), ""),
+            Some(Format::WindowsTerminal)
+        );
+        assert_eq!(Format::detect(p("x.conf"), ""), Some(Format::Kitty));
+        assert_eq!(Format::detect(p("x.toml"), ""), Some(Format::Alacritty));
+        assert_eq!(
+            Format::detect(p("x.yml"), "colors:\n  primary:\n"),
+            Some(Format::Alacritty)
+        );
+        assert_eq!(
+            Format::detect(p("x.yaml"), "base00: \"000000\"\n"),
+            Some(Format::Base16)
+        );
+        assert_eq!(
+            Format::detect(p(".Xresources"), ""),
+            Some(Format::Xresources)
+        );
+        assert_eq!(
+            Format::detect(p("dracula"), "*.color0: #000000\n"),
+            Some(Format::Xresources)
+        );
+        assert_eq!(Format::detect(p("notes.txt"), "hello"), None);
+        assert_eq!(
+            "windows-terminal".parse::<Format>().unwrap(),
+            Format::WindowsTerminal
+        );
+    }
+}
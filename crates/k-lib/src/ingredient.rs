@@ -1,8 +1,11 @@
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, JsonSchema)]
 pub struct Ingredient {
+    /// `[package]` in `.ing` files; `[meta]` is the older spelling.
     #[serde(alias = "package")]
+    #[schemars(rename = "package")]
     pub meta: IngredientManifest,
     #[serde(default)]
     pub templates: Vec<Template>,
@@ -12,7 +15,7 @@ pub struct Ingredient {
     pub hooks: Hooks,
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, JsonSchema)]
 pub struct IngredientManifest {
     pub name: String,
     pub version: String,
@@ -24,15 +27,20 @@ pub struct IngredientManifest {
     pub ignored: bool,
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone, JsonSchema)]
 pub struct Template {
     pub target: String,
     pub content: String,
+    /// Prepend a "generated by kitchn" comment using the target's comment syntax.
+    #[serde(default)]
+    pub header: bool,
 }
 
-#[derive(Debug, Serialize, Deserialize, Default, Clone)]
+#[derive(Debug, Serialize, Deserialize, Default, Clone, JsonSchema)]
 pub struct Hooks {
     pub reload: Option<String>,
+    /// Runs after `kitchn pantry remove` has deleted or restored the targets.
+    pub on_remove: Option<String>,
 }
 
 #[cfg(test)]
@@ -54,6 +62,7 @@ mod tests {
 
             [hooks]
             reload = "echo reload"
+            on_remove = "echo bye"
         "#;
 
         let pkg: Ingredient = toml::from_str(toml).unwrap();
@@ -62,6 +71,7 @@ mod tests {
         assert_eq!(pkg.templates.len(), 1);
         assert_eq!(pkg.templates[0].target, "~/.config/test");
         assert_eq!(pkg.hooks.reload.unwrap(), "echo reload");
+        assert_eq!(pkg.hooks.on_remove.unwrap(), "echo bye");
     }
 
     #[test]
@@ -1,7 +1,17 @@
+pub mod audit;
+pub mod color;
 pub mod config;
 pub mod db;
+pub mod dirs;
+pub mod exporter;
+pub mod extractor;
 pub mod factory;
+pub mod format;
+pub mod importer;
 pub mod ingredient;
 pub mod logger;
 pub mod packager;
+pub mod pastry;
 pub mod processor;
+pub mod scheduler;
+pub mod schema;
@@ -1,48 +1,123 @@
-use crate::config::Cookbook;
-use crate::factory::{ColorResolver, TagFactory};
+use crate::color::Color;
+use crate::config::{Cookbook, LevelTagConfig};
+use crate::factory::TagFactory;
 use anyhow::{Context, Result};
 use chrono::Local;
-use colored::Colorize;
+use colored::{ColoredString, Colorize, CustomColor};
 use std::fs::{self, OpenOptions};
 use std::io::Write;
 use std::path::PathBuf;
 
-pub fn log_to_terminal(config: &Cookbook, level: &str, scope: &str, msg: &str) {
-    let icon_set_key = &config.theme.settings.active_icons;
-    let icon = if icon_set_key == "nerdfont" {
-        config
-            .icons
-            .nerdfont
-            .get(level)
-            .map(|s| s.as_str())
-            .unwrap_or("?")
-    } else {
-        config
-            .icons
-            .ascii
-            .get(level)
-            .map(|s| s.as_str())
-            .unwrap_or("?")
-    };
+/// What the logger reads from a cookbook. Implemented by [`Cookbook`] and
+/// by the archived cookbook in `pastry.bin`, which is read in place.
+pub trait LogStyle {
+    /// The icon for `level` from the first set of the `active_icons` chain
+    /// that has one.
+    fn icon(&self, level: &str) -> Option<&str>;
+    fn color(&self, key: &str) -> Option<Color>;
+    fn label(&self, level: &str) -> Option<&str>;
+    /// `[tag]` with the overrides of `[tag.levels.<level>]` applied.
+    fn tag(&self, level: &str) -> TagStyle<'_>;
+    fn terminal_structure(&self) -> &str;
+    fn file_structure(&self) -> &str;
+    fn logging(&self) -> LogFiles<'_>;
+}
+
+/// `[tag]` of `layout.toml` for one level, borrowed.
+pub struct TagStyle<'a> {
+    pub prefix: &'a str,
+    pub suffix: &'a str,
+    pub transform: &'a str,
+    pub min_width: usize,
+    pub max_width: Option<usize>,
+    pub alignment: &'a str,
+    pub fg: Option<&'a str>,
+    pub bg: Option<&'a str>,
+    pub bold: bool,
+    pub reverse: bool,
+}
+
+/// `[logging]` of `layout.toml`, borrowed.
+pub struct LogFiles<'a> {
+    pub base_dir: &'a str,
+    pub path_structure: &'a str,
+    pub filename_structure: &'a str,
+    pub timestamp_format: &'a str,
+    pub write_by_default: bool,
+    pub app_name: &'a str,
+}
+
+impl LogStyle for Cookbook {
+    fn icon(&self, level: &str) -> Option<&str> {
+        self.icons.resolve(&self.theme.settings.active_icons, level)
+    }
+
+    fn color(&self, key: &str) -> Option<Color> {
+        self.theme.colors.get(key).copied()
+    }
+
+    fn label(&self, level: &str) -> Option<&str> {
+        self.layout.labels.get(level).map(String::as_str)
+    }
+
+    fn tag(&self, level: &str) -> TagStyle<'_> {
+        let tag = &self.layout.tag;
+        let level = tag.levels.get(level);
+        let field = |f: fn(&LevelTagConfig) -> &Option<String>| level.and_then(|l| f(l).as_deref());
+        TagStyle {
+            prefix: field(|l| &l.prefix).unwrap_or(&tag.prefix),
+            suffix: field(|l| &l.suffix).unwrap_or(&tag.suffix),
+            transform: field(|l| &l.transform).unwrap_or(&tag.transform),
+            min_width: tag.min_width,
+            max_width: tag.max_width,
+            alignment: &tag.alignment,
+            fg: field(|l| &l.fg),
+            bg: field(|l| &l.bg),
+            bold: level.is_some_and(|l| l.bold),
+            reverse: level.is_some_and(|l| l.reverse),
+        }
+    }
+
+    fn terminal_structure(&self) -> &str {
+        &self.layout.structure.terminal
+    }
+
+    fn file_structure(&self) -> &str {
+        &self.layout.structure.file
+    }
+
+    fn logging(&self) -> LogFiles<'_> {
+        let logging = &self.layout.logging;
+        LogFiles {
+            base_dir: &logging.base_dir,
+            path_structure: &logging.path_structure,
+            filename_structure: &logging.filename_structure,
+            timestamp_format: &logging.timestamp_format,
+            write_by_default: logging.write_by_default,
+            app_name: &logging.app_name,
+        }
+    }
+}
+
+pub fn log_to_terminal(config: &impl LogStyle, level: &str, scope: &str, msg: &str) {
+    let icon = config.icon(level).unwrap_or("?");
 
     let tag = TagFactory::create_tag(config, level);
 
-    let level_color_hex = config
-        .theme
-        .colors
-        .get(level)
-        .or_else(|| config.theme.colors.get("fg"))
-        .map(|s| s.as_str())
-        .unwrap_or("#ffffff");
-    let level_color = ColorResolver::hex_to_color(level_color_hex);
+    let level_color: CustomColor = config
+        .color(level)
+        .or_else(|| config.color("fg"))
+        .map(CustomColor::from)
+        .unwrap_or(CustomColor::new(255, 255, 255));
+    let tag = paint_tag(&tag, &config.tag(level), level_color, config);
 
-    let structure = &config.layout.structure.terminal;
+    let structure = config.terminal_structure();
 
     let parts = parse_structure(structure);
 
     for part in parts {
         match part.as_str() {
-            "{tag}" => print!("{}", tag.custom_color(level_color)),
+            "{tag}" => print!("{}", tag),
             "{icon}" => print!("{}", icon.custom_color(level_color)),
             "{scope}" => print!("{}", scope.white().dimmed()),
             "{msg}" => print_formatted_msg(msg, config),
@@ -52,6 +127,33 @@ pub fn log_to_terminal(config: &Cookbook, level: &str, scope: &str, msg: &str) {
     println!();
 }
 
+/// `tag` in the level color, or in the colors and attributes of its
+/// `[tag.levels]` entry.
+fn paint_tag(
+    tag: &str,
+    style: &TagStyle,
+    level_color: CustomColor,
+    config: &impl LogStyle,
+) -> ColoredString {
+    let color = |value: &str| {
+        config
+            .color(value)
+            .or_else(|| value.parse::<Color>().ok())
+            .map(CustomColor::from)
+    };
+    let mut painted = tag.custom_color(style.fg.and_then(color).unwrap_or(level_color));
+    if let Some(bg) = style.bg.and_then(color) {
+        painted = painted.on_custom_color(bg);
+    }
+    if style.bold {
+        painted = painted.bold();
+    }
+    if style.reverse {
+        painted = painted.reversed();
+    }
+    painted
+}
+
 fn parse_structure(structure: &str) -> Vec<String> {
     let mut parts = Vec::new();
     let mut current = String::new();
@@ -87,7 +189,7 @@ fn parse_structure(structure: &str) -> Vec<String> {
     parts
 }
 
-fn print_formatted_msg(msg: &str, config: &Cookbook) {
+fn print_formatted_msg(msg: &str, config: &impl LogStyle) {
     let mut i = 0;
     while i < msg.len() {
         if let Some(start_tag_open) = msg[i..].find('<') {
@@ -119,19 +221,18 @@ fn print_formatted_msg(msg: &str, config: &Cookbook) {
     }
 }
 
-fn apply_style(text: &str, style: &str, config: &Cookbook) {
+fn apply_style(text: &str, style: &str, config: &impl LogStyle) {
     if style == "bold" {
         print!("{}", text.bold());
-    } else if let Some(hex) = config.theme.colors.get(style) {
-        let color = ColorResolver::hex_to_color(hex);
-        print!("{}", text.custom_color(color));
+    } else if let Some(color) = config.color(style) {
+        print!("{}", text.custom_color(CustomColor::from(color)));
     } else {
         print!("{}", text);
     }
 }
 
 pub fn log_to_file(
-    config: &Cookbook,
+    config: &impl LogStyle,
     level: &str,
     scope: &str,
     msg: &str,
@@ -140,37 +241,30 @@ pub fn log_to_file(
     let clean_msg = strip_tags(msg);
     let now = Local::now();
     let tag = TagFactory::create_tag(config, level);
-    let timestamp = now
-        .format(&config.layout.logging.timestamp_format)
-        .to_string();
+    let logging = config.logging();
+    let timestamp = now.format(logging.timestamp_format).to_string();
 
-    let app_name = app_override.unwrap_or(&config.layout.logging.app_name);
+    let app_name = app_override.unwrap_or(logging.app_name);
 
-    let mut content = config.layout.structure.file.clone();
+    let mut content = config.file_structure().to_string();
     content = content.replace("{timestamp}", &timestamp);
     content = content.replace("{tag}", &tag);
     content = content.replace("{msg}", &clean_msg);
     content = content.replace("{scope}", scope);
 
-    let base_dir_str = &config.layout.logging.base_dir;
-    let base_dir = if base_dir_str.starts_with("~") {
-        let home = directories::UserDirs::new().context("Could not find home dir")?;
-        PathBuf::from(base_dir_str.replace("~", home.home_dir().to_str().unwrap()))
-    } else {
-        PathBuf::from(base_dir_str)
-    };
+    let base_dir = log_dir(config)?;
 
     let year = now.format("%Y").to_string();
     let month = now.format("%m").to_string();
     let day = now.format("%d").to_string();
 
-    let mut rel_path = config.layout.logging.path_structure.clone();
+    let mut rel_path = logging.path_structure.to_string();
     rel_path = rel_path.replace("{year}", &year);
     rel_path = rel_path.replace("{month}", &month);
     rel_path = rel_path.replace("{scope}", scope);
     rel_path = rel_path.replace("{app}", app_name);
 
-    let mut filename = config.layout.logging.filename_structure.clone();
+    let mut filename = logging.filename_structure.to_string();
     filename = filename.replace("{level}", level);
     filename = filename.replace("{year}", &year);
     filename = filename.replace("{month}", &month);
@@ -192,6 +286,19 @@ pub fn log_to_file(
     Ok(())
 }
 
+/// `layout.logging.base_dir` with a leading `~` expanded.
+pub fn log_dir(config: &impl LogStyle) -> Result<PathBuf> {
+    let base_dir_str = config.logging().base_dir;
+    if base_dir_str.starts_with("~") {
+        let home = directories::UserDirs::new().context("Could not find home dir")?;
+        Ok(PathBuf::from(
+            base_dir_str.replace("~", home.home_dir().to_str().unwrap()),
+        ))
+    } else {
+        Ok(PathBuf::from(base_dir_str))
+    }
+}
+
 fn strip_tags(msg: &str) -> String {
     let mut result = String::new();
     let mut i = 0;
@@ -217,8 +324,8 @@ fn strip_tags(msg: &str) -> String {
 mod tests {
     use super::*;
     use crate::config::{
-        DictionaryConfig, IconsConfig, LayoutConfig, LoggingConfig, StructureConfig, TagConfig,
-        ThemeConfig, ThemeMeta, ThemeSettings,
+        DictionaryConfig, IconChain, IconsConfig, LayoutConfig, LoggingConfig, StructureConfig,
+        TagConfig, ThemeConfig, ThemeMeta, ThemeSettings,
     };
     use std::collections::HashMap;
     use tempfile::tempdir;
@@ -230,15 +337,18 @@ mod tests {
                     name: "Test".to_string(),
                 },
                 settings: ThemeSettings {
-                    active_icons: "nerdfont".to_string(),
+                    active_icons: IconChain::One("nerdfont".into()),
+                    schedule: None,
                 },
                 colors: HashMap::new(),
+                spellings: HashMap::new(),
                 fonts: HashMap::new(),
                 include: None,
+                variants: HashMap::new(),
+                variant: None,
             },
             icons: IconsConfig {
-                nerdfont: HashMap::new(),
-                ascii: HashMap::new(),
+                sets: HashMap::new(),
                 include: None,
             },
             layout: LayoutConfig {
@@ -248,6 +358,8 @@ mod tests {
                     transform: "uppercase".to_string(),
                     min_width: 10,
                     alignment: "center".to_string(),
+                    max_width: None,
+                    levels: HashMap::new(),
                 },
                 labels: HashMap::new(),
                 structure: StructureConfig {
@@ -0,0 +1,293 @@
+use crate::color::Color;
+use crate::config::{
+    ArchivedCookbook, ArchivedIconChain, ArchivedLevelTagConfig, ArchivedPreset, ConfigError,
+    Cookbook, Provenance,
+};
+use crate::dirs::Dirs;
+use crate::format::{FormatError, PASTRY};
+use crate::logger::{LogFiles, LogStyle, TagStyle};
+use log::debug;
+use memmap2::Mmap;
+use rkyv::option::ArchivedOption;
+use rkyv::rancor;
+use rkyv::string::ArchivedString;
+use std::fs::{self, File};
+use std::path::Path;
+
+/// Offset alignment of the archive inside the file. The map itself is page
+/// aligned, so this keeps every archived field aligned too.
+const ALIGN: usize = 16;
+
+/// A baked cookbook (`pastry.bin`), mapped into memory and read in place.
+///
+/// Layout: the [`PASTRY`] header, the [`Provenance`] (bincode), zero padding
+/// to [`ALIGN`], then the cookbook as an rkyv archive. Opening it validates
+/// the archive once; lookups after that read straight from the map.
+pub struct Pastry {
+    map: Mmap,
+    offset: usize,
+}
+
+impl Pastry {
+    /// Bakes `cookbook`, recording the [`Provenance`] of `dirs`.
+    pub fn write(cookbook: &Cookbook, path: &Path, dirs: &Dirs) -> Result<(), ConfigError> {
+        let provenance = Provenance::record(dirs)?;
+        let archive = rkyv::to_bytes::<rancor::Error>(cookbook).map_err(std::io::Error::other)?;
+
+        let mut bytes = Vec::with_capacity(archive.len() + 1024);
+        PASTRY.write_header(&mut bytes)?;
+        bincode::serde::encode_into_std_write(&provenance, &mut bytes, bincode::config::standard())
+            .map_err(std::io::Error::other)?;
+        bytes.resize(bytes.len().next_multiple_of(ALIGN), 0);
+        bytes.extend_from_slice(&archive);
+
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        // Other processes may have the old file mapped: never write it in
+        // place, replace it
+        let tmp = path.with_extension("bin.tmp");
+        fs::write(&tmp, &bytes)?;
+        fs::rename(&tmp, path)?;
+        Ok(())
+    }
+
+    /// Maps the cache at `path` if it is fresh: written by this k-lib version,
+    /// newer than the running executable, and its [`Provenance`] still matches
+    /// `dirs`. `None` otherwise.
+    pub fn open(path: &Path, dirs: &Dirs) -> Result<Option<Self>, ConfigError> {
+        let file = File::open(path)?;
+        let mtime = file.metadata()?.modified()?;
+
+        // Check if the running executable is newer than the cache
+        // This ensures that if we update the embedded defaults, the cache is invalidated
+        if let Ok(exe_path) = std::env::current_exe()
+            && let Ok(exe_meta) = fs::metadata(&exe_path)
+            && let Ok(exe_mtime) = exe_meta.modified()
+            && exe_mtime > mtime
+        {
+            return Ok(None); // Executable is newer
+        }
+
+        // SAFETY: `write` replaces the file rather than modifying it, so the
+        // mapped bytes do not change underneath us
+        let map = unsafe { Mmap::map(&file)? };
+
+        let mut rest = &map[..];
+        match PASTRY.read_current(&mut rest) {
+            Ok(_) => {}
+            Err(FormatError::Io(e)) => return Err(e.into()),
+            Err(e) => {
+                debug!("Ignoring binary cache: {}", e);
+                return Ok(None);
+            }
+        }
+        let (provenance, used): (Provenance, usize) =
+            match bincode::serde::decode_from_slice(rest, bincode::config::standard()) {
+                Ok(decoded) => decoded,
+                Err(e) => {
+                    debug!("Failed to decode binary cache provenance: {}", e);
+                    return Ok(None);
+                }
+            };
+        if !provenance.is_current(dirs) {
+            return Ok(None);
+        }
+
+        let offset = (map.len() - rest.len() + used).next_multiple_of(ALIGN);
+        let archive = map.get(offset..).unwrap_or_default();
+        if let Err(e) = rkyv::access::<ArchivedCookbook, rancor::Error>(archive) {
+            debug!("Invalid binary cache archive: {}", e);
+            return Ok(None);
+        }
+        Ok(Some(Pastry { map, offset }))
+    }
+
+    pub fn cookbook(&self) -> &ArchivedCookbook {
+        // SAFETY: validated in `open`, and the map is read-only
+        unsafe { rkyv::access_unchecked::<ArchivedCookbook>(&self.map[self.offset..]) }
+    }
+
+    pub fn preset(&self, key: &str) -> Option<&ArchivedPreset> {
+        self.cookbook().dictionary.presets.get(key)
+    }
+
+    /// The owned cookbook, for callers that modify it or need every value.
+    pub fn to_cookbook(&self) -> Result<Cookbook, ConfigError> {
+        rkyv::deserialize::<Cookbook, rancor::Error>(self.cookbook())
+            .map_err(|e| ConfigError::Io(std::io::Error::other(e)))
+    }
+}
+
+impl LogStyle for ArchivedCookbook {
+    fn icon(&self, level: &str) -> Option<&str> {
+        let sets = match &self.theme.settings.active_icons {
+            ArchivedIconChain::One(set) => std::slice::from_ref(set),
+            ArchivedIconChain::Chain(sets) => sets.as_slice(),
+        };
+        sets.iter()
+            .find_map(|set| self.icons.sets.get(set.as_str())?.get(level))
+            .map(|icon| icon.as_str())
+    }
+
+    fn color(&self, key: &str) -> Option<Color> {
+        self.theme.colors.get(key).map(Color::from)
+    }
+
+    fn label(&self, level: &str) -> Option<&str> {
+        self.layout.labels.get(level).map(|label| label.as_str())
+    }
+
+    fn tag(&self, level: &str) -> TagStyle<'_> {
+        let tag = &self.layout.tag;
+        let level = tag.levels.get(level);
+        let field = |f: fn(&ArchivedLevelTagConfig) -> &ArchivedOption<ArchivedString>| {
+            level.and_then(|l| f(l).as_ref()).map(|s| s.as_str())
+        };
+        TagStyle {
+            prefix: field(|l| &l.prefix).unwrap_or(&tag.prefix),
+            suffix: field(|l| &l.suffix).unwrap_or(&tag.suffix),
+            transform: field(|l| &l.transform).unwrap_or(&tag.transform),
+            min_width: tag.min_width.to_native() as usize,
+            max_width: tag.max_width.as_ref().map(|w| w.to_native() as usize),
+            alignment: &tag.alignment,
+            fg: field(|l| &l.fg),
+            bg: field(|l| &l.bg),
+            bold: level.is_some_and(|l| l.bold),
+            reverse: level.is_some_and(|l| l.reverse),
+        }
+    }
+
+    fn terminal_structure(&self) -> &str {
+        &self.layout.structure.terminal
+    }
+
+    fn file_structure(&self) -> &str {
+        &self.layout.structure.file
+    }
+
+    fn logging(&self) -> LogFiles<'_> {
+        let logging = &self.layout.logging;
+        LogFiles {
+            base_dir: &logging.base_dir,
+            path_structure: &logging.path_structure,
+            filename_structure: &logging.filename_structure,
+            timestamp_format: &logging.timestamp_format,
+            write_by_default: logging.write_by_default,
+            app_name: &logging.app_name,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    /// The cookbook in `config_dir` alone, without system layers.
+    fn dirs(config_dir: &Path) -> Dirs {
+        Dirs {
+            config: config_dir.to_path_buf(),
+            ..Dirs::under(config_dir)
+        }
+    }
+
+    fn bake(config_dir: &Path) -> std::path::PathBuf {
+        fs::write(
+            config_dir.join("theme.toml"),
+            "[meta]\nname = \"baked\"\n[settings]\nactive_icons = [\"nerdfont\", \"emoji\"]\n[colors]\nfg = \"#eeeeee\"\nsuccess = \"#50fa7b80\"\n[fonts]\n",
+        )
+        .unwrap();
+        fs::write(
+            config_dir.join("icons.toml"),
+            "[nerdfont]\nsuccess = \"S\"\n[emoji]\nsuccess = \"e\"\ninfo = \"i\"\n",
+        )
+        .unwrap();
+        fs::write(
+            config_dir.join("layout.toml"),
+            r#"
+[tag]
+prefix = "["
+suffix = "]"
+transform = "uppercase"
+min_width = 7
+alignment = "center"
+[tag.levels.error]
+prefix = " "
+fg = "bg"
+bold = true
+[labels]
+success = "ok"
+[structure]
+terminal = "{tag} {msg}"
+file = "{msg}"
+[logging]
+base_dir = "logs"
+path_structure = "{app}"
+filename_structure = "log"
+timestamp_format = "%Y"
+write_by_default = true
+"#,
+        )
+        .unwrap();
+        let bin = config_dir.join("cache/pastry.bin");
+        let cookbook = Cookbook::load_with_cache(&dirs(config_dir), &bin, true).unwrap();
+        Pastry::write(&cookbook, &bin, &dirs(config_dir)).unwrap();
+        bin
+    }
+
+    #[test]
+    fn test_archived_lookups_match_the_cookbook() {
+        let dir = tempdir().unwrap();
+        let bin = bake(dir.path());
+        let pastry = Pastry::open(&bin, &dirs(dir.path())).unwrap().unwrap();
+        let archived = pastry.cookbook();
+        let owned = pastry.to_cookbook().unwrap();
+
+        assert_eq!(archived.icon("success"), Some("S"));
+        assert_eq!(archived.icon("success"), owned.icon("success"));
+        // Down the chain
+        assert_eq!(archived.icon("info"), Some("i"));
+        assert_eq!(archived.icon("info"), owned.icon("info"));
+        assert_eq!(archived.icon("error"), None);
+        assert_eq!(
+            archived.color("success"),
+            Some(Color::new(0x50, 0xfa, 0x7b, 0x80))
+        );
+        assert_eq!(archived.color("nope"), None);
+        assert_eq!(archived.label("success"), Some("ok"));
+        assert_eq!(archived.tag("success").min_width, 7);
+        let badge = archived.tag("error");
+        assert_eq!((badge.prefix, badge.fg), (" ", Some("bg")));
+        assert!(badge.bold && !badge.reverse);
+        assert_eq!(badge.prefix, owned.tag("error").prefix);
+        assert_eq!(archived.tag("success").prefix, "[");
+        assert_eq!(archived.terminal_structure(), "{tag} {msg}");
+        assert!(archived.logging().write_by_default);
+        assert_eq!(archived.logging().app_name, "kitchn");
+
+        let preset = pastry.preset("cook_ok").unwrap();
+        assert_eq!(preset.level, owned.dictionary.presets["cook_ok"].level);
+        assert!(pastry.preset("nope").is_none());
+        assert_eq!(owned.theme.meta.name, "baked");
+        // Templates rendered from the cache see the theme's own spelling
+        assert_eq!(owned.theme.spellings["fg"], "#eeeeee");
+    }
+
+    #[test]
+    fn test_damaged_or_stale_archive_is_not_used() {
+        let dir = tempdir().unwrap();
+        let bin = bake(dir.path());
+
+        let mut bytes = fs::read(&bin).unwrap();
+        let len = bytes.len();
+        bytes.truncate(len - 8);
+        fs::write(&bin, &bytes).unwrap();
+        assert!(Pastry::open(&bin, &dirs(dir.path())).unwrap().is_none());
+
+        let bin = bake(dir.path());
+        assert!(Pastry::open(&bin, &dirs(dir.path())).unwrap().is_some());
+        fs::write(dir.path().join("icons.toml"), "[nerdfont]\n[ascii]\n").unwrap();
+        assert!(Pastry::open(&bin, &dirs(dir.path())).unwrap().is_none());
+    }
+}
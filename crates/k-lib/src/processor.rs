@@ -1,34 +1,74 @@
 use anyhow::{Context, Result};
 use log::debug;
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::process::Command;
 use tera::{Context as TeraContext, Tera};
 
+use crate::color::Color;
 use crate::config::Cookbook;
+use crate::db::Pantry;
 use crate::ingredient::Ingredient;
 use crate::logger;
 
 use std::collections::HashMap;
 use tera::{Value, to_value, try_get_value};
 
-pub fn apply(ingredient: &Ingredient, config: &Cookbook, _force: bool) -> Result<bool> {
+pub fn apply(ingredient: &Ingredient, config: &Cookbook, force: bool) -> Result<bool> {
+    cook(ingredient, config, force, None)
+}
+
+/// Like [`apply`], but records every written target in `pantry` (backing up
+/// pre-existing originals) so the ingredient can be removed cleanly later.
+pub fn apply_tracked(
+    ingredient: &Ingredient,
+    config: &Cookbook,
+    force: bool,
+    pantry: &mut Pantry,
+) -> Result<bool> {
+    cook(ingredient, config, force, Some(pantry))
+}
+
+fn cook(
+    ingredient: &Ingredient,
+    config: &Cookbook,
+    _force: bool,
+    pantry: Option<&mut Pantry>,
+) -> Result<bool> {
     debug!("Applying ingredient: {}", ingredient.meta.name);
+    let rendered = render(ingredient, config)?;
+    process_ingredient(ingredient, rendered, config, pantry)
+}
+
+/// Renders every template and file of `ingredient` against `config` without
+/// touching the filesystem. Targets are returned with `~` already expanded.
+pub fn render(ingredient: &Ingredient, config: &Cookbook) -> Result<Vec<(PathBuf, String)>> {
     let mut tera = Tera::default();
     tera.register_filter("hex_to_rgb", hex_to_rgb);
+    tera.register_filter("hex", hex);
 
     let mut ctx = TeraContext::new();
 
-    // Context Setup
-    ctx.insert("colors", &config.theme.colors);
+    // Context Setup. Colors read as the theme spelled them; `| hex` and
+    // `| hex_to_rgb` give the typed forms
+    let theme = &config.theme;
+    let colors: HashMap<&str, String> = theme
+        .colors
+        .iter()
+        .map(|(key, color)| {
+            let spelling = theme.spellings.get(key).cloned();
+            (key.as_str(), spelling.unwrap_or_else(|| color.to_string()))
+        })
+        .collect();
+    ctx.insert("colors", &colors);
     ctx.insert("fonts", &config.theme.fonts);
+    // e.g. `{% if variant == "light" %}` for apps that need a mode switch
+    ctx.insert("variant", &config.theme.variant);
 
-    let active_icons = if config.theme.settings.active_icons == "nerdfont" {
-        &config.icons.nerdfont
-    } else {
-        &config.icons.ascii
-    };
-    ctx.insert("icons", active_icons);
+    // `icons` follows the `active_icons` chain, `icon_sets.<set>` picks one
+    let chain = &config.theme.settings.active_icons;
+    ctx.insert("icons", &config.icons.resolve_all(chain));
+    ctx.insert("icon_sets", &config.icons.sets);
 
     // Debug log available context keys
     if log::log_enabled!(log::Level::Debug) {
@@ -39,43 +79,106 @@ pub fn apply(ingredient: &Ingredient, config: &Cookbook, _force: bool) -> Result
         );
     }
 
-    process_ingredient(ingredient, &mut tera, &mut ctx, config)
+    let mut rendered = Vec::new();
+    for tpl in ingredient.templates.iter().chain(&ingredient.files) {
+        let path = expand_target(&tpl.target)?;
+        debug!("Rendering target: {:?}", path);
+        // We create a one-off template due to dynamic content
+        let mut content = tera
+            .render_str(&tpl.content, &ctx)
+            .with_context(|| format!("Failed to render template for {}", tpl.target))?;
+        if tpl.header {
+            content = with_header(&path, content, ingredient);
+        }
+        rendered.push((path, content));
+    }
+    Ok(rendered)
 }
 
-/// Tera filter: hex_to_rgb
-fn hex_to_rgb(value: &Value, _args: &HashMap<String, Value>) -> tera::Result<Value> {
-    let s = try_get_value!("hex_to_rgb", "value", String, value);
-    let hex = s.trim_start_matches('#');
+/// Prepends a "generated by kitchn" comment in the syntax of the target's file
+/// type. Formats without comments (JSON) and file types whose comment syntax
+/// is not known are left untouched; scripts with a shebang count as `#`.
+fn with_header(path: &Path, content: String, ingredient: &Ingredient) -> String {
+    let ext = path
+        .extension()
+        .map(|e| e.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+
+    let (open, close) = match ext.as_str() {
+        "json" => {
+            debug!("Skipping header for {:?}: JSON has no comments", path);
+            return content;
+        }
+        "css" | "rasi" => ("/*", " */"),
+        "js" | "ts" | "jsonc" | "json5" | "kdl" | "scss" | "less" | "c" | "h" | "cpp" | "hpp"
+        | "rs" | "go" | "qml" => ("//", ""),
+        "lua" | "sql" | "hs" => ("--", ""),
+        "toml" | "conf" | "ini" | "cfg" | "sh" | "bash" | "zsh" | "fish" | "py" | "yaml"
+        | "yml" => ("#", ""),
+        _ if content.starts_with("#!") => ("#", ""),
+        _ => {
+            debug!("Skipping header for {:?}: unknown comment syntax", path);
+            return content;
+        }
+    };
 
-    if hex.len() != 6 {
-        return Err(tera::Error::msg(format!("Invalid hex color: {}", s)));
+    let header = format!(
+        "{} Generated by kitchn from ingredient '{}' v{}. Changes will be overwritten.{}\n",
+        open, ingredient.meta.name, ingredient.meta.version, close
+    );
+
+    // Keep shebangs on the first line so scripts stay executable
+    if content.starts_with("#!") {
+        let split = content.find('\n').map(|i| i + 1).unwrap_or(content.len());
+        let (shebang, rest) = content.split_at(split);
+        let sep = if shebang.ends_with('\n') { "" } else { "\n" };
+        return format!("{}{}{}{}", shebang, sep, header, rest);
     }
 
-    let r = u8::from_str_radix(&hex[0..2], 16).map_err(|_| tera::Error::msg("Invalid hex"))?;
-    let g = u8::from_str_radix(&hex[2..4], 16).map_err(|_| tera::Error::msg("Invalid hex"))?;
-    let b = u8::from_str_radix(&hex[4..6], 16).map_err(|_| tera::Error::msg("Invalid hex"))?;
+    header + &content
+}
+
+/// Tera filter: hex. Takes any color kitchn understands and yields `#RRGGBB`,
+/// or `#RRGGBBAA` for translucent colors.
+fn hex(value: &Value, _args: &HashMap<String, Value>) -> tera::Result<Value> {
+    let s = try_get_value!("hex", "value", String, value);
+    let color: Color = s.parse().map_err(tera::Error::msg)?;
+    Ok(to_value(color.to_string()).unwrap())
+}
+
+/// Tera filter: hex_to_rgb. Takes any color kitchn understands (see
+/// [`Color`]) and yields `[r, g, b]`, or `[r, g, b, a]` for translucent colors.
+fn hex_to_rgb(value: &Value, _args: &HashMap<String, Value>) -> tera::Result<Value> {
+    let s = try_get_value!("hex_to_rgb", "value", String, value);
+    let color: Color = s.parse().map_err(tera::Error::msg)?;
 
-    Ok(to_value(vec![r, g, b]).unwrap())
+    let mut channels = vec![color.r, color.g, color.b];
+    if !color.is_opaque() {
+        channels.push(color.a);
+    }
+    Ok(to_value(channels).unwrap())
 }
 
 fn process_ingredient(
     pkg: &Ingredient,
-    tera: &mut Tera,
-    ctx: &mut TeraContext,
+    rendered: Vec<(PathBuf, String)>,
     config: &Cookbook,
+    mut pantry: Option<&mut Pantry>,
 ) -> Result<bool> {
     debug!(
         "Processing ingredient templates and hooks for: {}",
         pkg.meta.name
     );
-    // Render Templates
-    for tpl in &pkg.templates {
-        render_and_write(&tpl.target, &tpl.content, tera, ctx)?;
-    }
-
-    // Render Files
-    for file in &pkg.files {
-        render_and_write(&file.target, &file.content, tera, ctx)?;
+    // Write Templates and Files
+    for (path, content) in rendered {
+        if let Some(db) = pantry.as_deref_mut() {
+            db.write_target(pkg, &config.theme.meta.name, &path, &content)?;
+            continue;
+        }
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(&path, content)?;
     }
 
     let mut hooks_success = true;
@@ -83,101 +186,108 @@ fn process_ingredient(
     // Run Hooks
     if let Some(cmd) = &pkg.hooks.reload {
         debug!("Found reload hook requested: '{}'", cmd);
+        hooks_success = run_hook(config, cmd)?;
+    }
 
-        // Retrieve presets or fall back to defaults
-        let (run_lvl, run_scope, run_msg) = config
-            .dictionary
-            .presets
-            .get("hook_run")
-            .map(|p| {
-                (
-                    p.level.as_str(),
-                    p.scope.as_deref().unwrap_or("HOOK"),
-                    p.msg.as_str(),
-                )
-            })
-            .unwrap_or(("secondary", "HOOK", "running hooks"));
-
-        let (ok_lvl, ok_scope, ok_msg) = config
-            .dictionary
-            .presets
-            .get("hook_ok")
-            .map(|p| {
-                (
-                    p.level.as_str(),
-                    p.scope.as_deref().unwrap_or("HOOK"),
-                    p.msg.as_str(),
-                )
-            })
-            .unwrap_or(("success", "HOOK", "hooks executed"));
-
-        let (err_lvl, err_scope, err_msg) = config
-            .dictionary
-            .presets
-            .get("hook_fail")
-            .map(|p| {
-                (
-                    p.level.as_str(),
-                    p.scope.as_deref().unwrap_or("HOOK"),
-                    p.msg.as_str(),
-                )
-            })
-            .unwrap_or(("error", "HOOK", "hooks failed"));
-
-        logger::log_to_terminal(config, run_lvl, run_scope, run_msg);
-
-        // Execute Hook
-        debug!("Executing hook via 'sh -c': {}", cmd);
-        let start = std::time::Instant::now();
-
-        let output = Command::new("sh")
-            .arg("-c")
-            .arg(cmd)
-            .output()
-            .context("Failed to execute hook")?;
-
-        let duration = start.elapsed();
-        debug!(
-            "Hook completed in {:?} with exit code: {}",
-            duration, output.status
-        );
+    Ok(hooks_success)
+}
 
-        // Always log stdout/stderr to debug log
-        if !output.stdout.is_empty() {
-            let s = String::from_utf8_lossy(&output.stdout);
-            debug!("Hook stdout:\n{}", s.trim());
-            // Mirror to terminal info log if non-empty
-            for line in s.lines() {
-                logger::log_to_terminal(config, "info", run_scope, line);
-            }
-        } else {
-            debug!("Hook stdout: <empty>");
-        }
+/// Runs a hook command via `sh -c`, mirroring its output to the terminal log.
+///
+/// Returns `Ok(false)` if the command ran but exited unsuccessfully.
+pub fn run_hook(config: &Cookbook, cmd: &str) -> Result<bool> {
+    // Retrieve presets or fall back to defaults
+    let (run_lvl, run_scope, run_msg) = config
+        .dictionary
+        .presets
+        .get("hook_run")
+        .map(|p| {
+            (
+                p.level.as_str(),
+                p.scope.as_deref().unwrap_or("HOOK"),
+                p.msg.as_str(),
+            )
+        })
+        .unwrap_or(("secondary", "HOOK", "running hooks"));
+
+    let (ok_lvl, ok_scope, ok_msg) = config
+        .dictionary
+        .presets
+        .get("hook_ok")
+        .map(|p| {
+            (
+                p.level.as_str(),
+                p.scope.as_deref().unwrap_or("HOOK"),
+                p.msg.as_str(),
+            )
+        })
+        .unwrap_or(("success", "HOOK", "hooks executed"));
+
+    let (err_lvl, err_scope, err_msg) = config
+        .dictionary
+        .presets
+        .get("hook_fail")
+        .map(|p| {
+            (
+                p.level.as_str(),
+                p.scope.as_deref().unwrap_or("HOOK"),
+                p.msg.as_str(),
+            )
+        })
+        .unwrap_or(("error", "HOOK", "hooks failed"));
+
+    logger::log_to_terminal(config, run_lvl, run_scope, run_msg);
+
+    // Execute Hook
+    debug!("Executing hook via 'sh -c': {}", cmd);
+    let start = std::time::Instant::now();
+
+    let output = Command::new("sh")
+        .arg("-c")
+        .arg(cmd)
+        .output()
+        .context("Failed to execute hook")?;
+
+    let duration = start.elapsed();
+    debug!(
+        "Hook completed in {:?} with exit code: {}",
+        duration, output.status
+    );
 
-        if !output.stderr.is_empty() {
-            let s = String::from_utf8_lossy(&output.stderr);
-            debug!("Hook stderr:\n{}", s.trim());
-            // Mirror to terminal error log if non-empty
-            for line in s.lines() {
-                logger::log_to_terminal(config, "error", run_scope, line);
-            }
-        } else {
-            debug!("Hook stderr: <empty>");
+    // Always log stdout/stderr to debug log
+    if !output.stdout.is_empty() {
+        let s = String::from_utf8_lossy(&output.stdout);
+        debug!("Hook stdout:\n{}", s.trim());
+        // Mirror to terminal info log if non-empty
+        for line in s.lines() {
+            logger::log_to_terminal(config, "info", run_scope, line);
         }
+    } else {
+        debug!("Hook stdout: <empty>");
+    }
 
-        if output.status.success() {
-            logger::log_to_terminal(config, ok_lvl, ok_scope, ok_msg);
-        } else {
-            logger::log_to_terminal(config, err_lvl, err_scope, err_msg);
-            hooks_success = false;
+    if !output.stderr.is_empty() {
+        let s = String::from_utf8_lossy(&output.stderr);
+        debug!("Hook stderr:\n{}", s.trim());
+        // Mirror to terminal error log if non-empty
+        for line in s.lines() {
+            logger::log_to_terminal(config, "error", run_scope, line);
         }
+    } else {
+        debug!("Hook stderr: <empty>");
     }
 
-    Ok(hooks_success)
+    if output.status.success() {
+        logger::log_to_terminal(config, ok_lvl, ok_scope, ok_msg);
+        Ok(true)
+    } else {
+        logger::log_to_terminal(config, err_lvl, err_scope, err_msg);
+        Ok(false)
+    }
 }
 
-fn render_and_write(target: &str, content: &str, tera: &mut Tera, ctx: &TeraContext) -> Result<()> {
-    debug!("Rendering target: {}", target);
+/// Expands a leading `~` in an ingredient target to the user's home directory.
+pub fn expand_target(target: &str) -> Result<PathBuf> {
     // Basic expansion of ~
     let target_expanded = if target.starts_with("~") {
         let home = directories::UserDirs::new()
@@ -191,18 +301,5 @@ fn render_and_write(target: &str, content: &str, tera: &mut Tera, ctx: &TeraCont
     };
 
     debug!("Expanded target path: {}", target_expanded);
-
-    let path = PathBuf::from(&target_expanded);
-    if let Some(parent) = path.parent() {
-        fs::create_dir_all(parent)?;
-    }
-
-    // Render content
-    // We create a one-off template due to dynamic content
-    let rendered = tera
-        .render_str(content, ctx)
-        .context("Failed to render template")?;
-
-    fs::write(path, rendered)?;
-    Ok(())
+    Ok(PathBuf::from(target_expanded))
 }
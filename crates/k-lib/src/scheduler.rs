@@ -0,0 +1,212 @@
+use crate::config::Schedule;
+use anyhow::{Result, anyhow};
+use chrono::{DateTime, NaiveDate, NaiveTime, TimeZone, Utc};
+
+pub const LIGHT: &str = "light";
+pub const DARK: &str = "dark";
+
+/// Sun position over a day at some location.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Daylight {
+    Sun {
+        rise: DateTime<Utc>,
+        set: DateTime<Utc>,
+    },
+    /// The sun never sets (high latitudes in summer).
+    PolarDay,
+    /// The sun never rises (high latitudes in winter).
+    PolarNight,
+}
+
+/// The variant `schedule` asks for at `now`.
+///
+/// Coordinates take precedence over fixed times. Fixed times are compared
+/// against the wall clock of `now`'s timezone and may wrap past midnight.
+pub fn variant_at<Tz: TimeZone>(schedule: &Schedule, now: &DateTime<Tz>) -> Result<&'static str> {
+    if let (Some(latitude), Some(longitude)) = (schedule.latitude, schedule.longitude) {
+        let is_day = match daylight(now.date_naive(), latitude, longitude) {
+            Daylight::Sun { rise, set } => {
+                let now = now.with_timezone(&Utc);
+                rise <= now && now < set
+            }
+            Daylight::PolarDay => true,
+            Daylight::PolarNight => false,
+        };
+        return Ok(if is_day { LIGHT } else { DARK });
+    }
+
+    let (light, dark) = fixed_times(schedule)?;
+    let time = now.time();
+
+    let is_day = if light <= dark {
+        light <= time && time < dark
+    } else {
+        time >= light || time < dark
+    };
+    Ok(if is_day { LIGHT } else { DARK })
+}
+
+/// Checks `schedule` without asking it for a variant, so a broken schedule
+/// fails when the theme loads rather than silently in `kitchn watch`.
+pub fn validate(schedule: &Schedule) -> Result<()> {
+    if schedule.latitude.is_some() && schedule.longitude.is_some() {
+        return Ok(());
+    }
+    fixed_times(schedule).map(|_| ())
+}
+
+/// The `light` and `dark` times of a schedule without coordinates.
+fn fixed_times(schedule: &Schedule) -> Result<(NaiveTime, NaiveTime)> {
+    let (Some(light), Some(dark)) = (&schedule.light, &schedule.dark) else {
+        return Err(anyhow!(
+            "schedule needs either `light` and `dark` times or `latitude` and `longitude`"
+        ));
+    };
+    let light = parse_time(light)?;
+    let dark = parse_time(dark)?;
+    // Equal times leave no span for one of the variants
+    if light == dark {
+        return Err(anyhow!(
+            "schedule `light` and `dark` are both {}, so one variant would never be used",
+            light.format("%H:%M")
+        ));
+    }
+    Ok((light, dark))
+}
+
+fn parse_time(value: &str) -> Result<NaiveTime> {
+    NaiveTime::parse_from_str(value, "%H:%M")
+        .map_err(|_| anyhow!("invalid schedule time '{}', expected HH:MM", value))
+}
+
+/// Sunrise and sunset on `date`, from the sunrise equation (accurate to a
+/// minute or two, which is plenty for switching themes). Longitude is east-positive.
+pub fn daylight(date: NaiveDate, latitude: f64, longitude: f64) -> Daylight {
+    const J2000: f64 = 2451545.0;
+    const UNIX_EPOCH_JD: f64 = 2440587.5;
+
+    let epoch = NaiveDate::from_ymd_opt(2000, 1, 1).expect("valid date");
+    let n = (date - epoch).num_days() as f64 + 0.0008;
+
+    // Mean solar time, solar mean anomaly and equation of the center
+    let j_star = n - longitude / 360.0;
+    let m = (357.5291 + 0.98560028 * j_star).rem_euclid(360.0);
+    let m_rad = m.to_radians();
+    let c = 1.9148 * m_rad.sin() + 0.02 * (2.0 * m_rad).sin() + 0.0003 * (3.0 * m_rad).sin();
+
+    // Ecliptic longitude, solar transit and declination
+    let lambda = (m + c + 180.0 + 102.9372).rem_euclid(360.0).to_radians();
+    let transit = J2000 + j_star + 0.0053 * m_rad.sin() - 0.0069 * (2.0 * lambda).sin();
+    let sin_decl = lambda.sin() * 23.4397_f64.to_radians().sin();
+    let cos_decl = sin_decl.asin().cos();
+
+    // Hour angle at which the upper limb touches the horizon
+    let phi = latitude.to_radians();
+    let cos_omega =
+        ((-0.833_f64).to_radians().sin() - phi.sin() * sin_decl) / (phi.cos() * cos_decl);
+    if cos_omega < -1.0 {
+        return Daylight::PolarDay;
+    }
+    if cos_omega > 1.0 {
+        return Daylight::PolarNight;
+    }
+    let omega = cos_omega.acos().to_degrees();
+
+    let to_utc = |jd: f64| {
+        let secs = ((jd - UNIX_EPOCH_JD) * 86400.0).round() as i64;
+        Utc.timestamp_opt(secs, 0).single().unwrap_or_default()
+    };
+    let rise = to_utc(transit - omega / 360.0);
+    let set = to_utc(transit + omega / 360.0);
+    Daylight::Sun { rise, set }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{FixedOffset, Timelike};
+
+    fn fixed(light: &str, dark: &str) -> Schedule {
+        Schedule {
+            light: Some(light.to_string()),
+            dark: Some(dark.to_string()),
+            ..Default::default()
+        }
+    }
+
+    fn at(h: u32, m: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2024, 6, 21, h, m, 0).unwrap()
+    }
+
+    #[test]
+    fn test_fixed_times() {
+        let schedule = fixed("07:00", "19:30");
+        assert_eq!(variant_at(&schedule, &at(6, 59)).unwrap(), DARK);
+        assert_eq!(variant_at(&schedule, &at(7, 0)).unwrap(), LIGHT);
+        assert_eq!(variant_at(&schedule, &at(19, 29)).unwrap(), LIGHT);
+        assert_eq!(variant_at(&schedule, &at(19, 30)).unwrap(), DARK);
+    }
+
+    #[test]
+    fn test_fixed_times_wrap_midnight() {
+        // Night shift: light from evening until the morning
+        let schedule = fixed("20:00", "06:00");
+        assert_eq!(variant_at(&schedule, &at(23, 0)).unwrap(), LIGHT);
+        assert_eq!(variant_at(&schedule, &at(3, 0)).unwrap(), LIGHT);
+        assert_eq!(variant_at(&schedule, &at(12, 0)).unwrap(), DARK);
+    }
+
+    #[test]
+    fn test_fixed_times_use_local_clock() {
+        let schedule = fixed("07:00", "19:00");
+        let cest = FixedOffset::east_opt(2 * 3600).unwrap();
+        // 18:30 UTC is 20:30 in CEST
+        let now = at(18, 30).with_timezone(&cest);
+        assert_eq!(variant_at(&schedule, &now).unwrap(), DARK);
+    }
+
+    #[test]
+    fn test_invalid_schedule() {
+        assert!(variant_at(&Schedule::default(), &at(12, 0)).is_err());
+        assert!(variant_at(&fixed("7am", "19:00"), &at(12, 0)).is_err());
+        assert!(validate(&Schedule::default()).is_err());
+        assert!(validate(&fixed("07:00", "19:00")).is_ok());
+    }
+
+    #[test]
+    fn test_equal_times_are_rejected() {
+        let schedule = fixed("07:00", "07:00");
+        let err = validate(&schedule).unwrap_err();
+        assert!(err.to_string().contains("both 07:00"), "{}", err);
+        assert!(variant_at(&schedule, &at(12, 0)).is_err());
+    }
+
+    #[test]
+    fn test_daylight_berlin_midsummer() {
+        let date = NaiveDate::from_ymd_opt(2024, 6, 21).unwrap();
+        let Daylight::Sun { rise, set } = daylight(date, 52.52, 13.405) else {
+            panic!("expected sunrise and sunset");
+        };
+        // Published: sunrise 02:43 UTC, sunset 19:33 UTC
+        let minutes = |t: DateTime<Utc>| (t.hour() * 60 + t.minute()) as i64;
+        assert!((minutes(rise) - (2 * 60 + 43)).abs() <= 3, "rise {}", rise);
+        assert!((minutes(set) - (19 * 60 + 33)).abs() <= 3, "set {}", set);
+
+        let schedule = Schedule {
+            latitude: Some(52.52),
+            longitude: Some(13.405),
+            ..Default::default()
+        };
+        assert_eq!(variant_at(&schedule, &at(12, 0)).unwrap(), LIGHT);
+        assert_eq!(variant_at(&schedule, &at(21, 0)).unwrap(), DARK);
+    }
+
+    #[test]
+    fn test_daylight_polar() {
+        let summer = NaiveDate::from_ymd_opt(2024, 6, 21).unwrap();
+        let winter = NaiveDate::from_ymd_opt(2024, 12, 21).unwrap();
+        // Tromsø
+        assert_eq!(daylight(summer, 69.65, 18.96), Daylight::PolarDay);
+        assert_eq!(daylight(winter, 69.65, 18.96), Daylight::PolarNight);
+    }
+}
@@ -0,0 +1,155 @@
+use crate::config::{DictionaryConfig, IconsConfig, LayoutConfig, ThemeConfig};
+use crate::ingredient::Ingredient;
+use anyhow::{Result, anyhow};
+use schemars::generate::SchemaSettings;
+use schemars::{JsonSchema, Schema};
+use std::fmt;
+use std::str::FromStr;
+
+/// File formats `kitchn schema` describes, each generated from its serde type.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    /// `theme.toml` and `themes/*.toml` ([`ThemeConfig`])
+    Theme,
+    /// `icons.toml` ([`IconsConfig`])
+    Icons,
+    /// `layout.toml` ([`LayoutConfig`])
+    Layout,
+    /// `cookbook.toml` presets ([`DictionaryConfig`])
+    Cookbook,
+    /// `.ing` ingredients ([`Ingredient`])
+    Ingredient,
+}
+
+impl Kind {
+    pub const ALL: &[Kind] = &[
+        Kind::Theme,
+        Kind::Icons,
+        Kind::Layout,
+        Kind::Cookbook,
+        Kind::Ingredient,
+    ];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Kind::Theme => "theme",
+            Kind::Icons => "icons",
+            Kind::Layout => "layout",
+            Kind::Cookbook => "cookbook",
+            Kind::Ingredient => "ingredient",
+        }
+    }
+}
+
+impl fmt::Display for Kind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for Kind {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Kind::ALL
+            .iter()
+            .copied()
+            .find(|k| k.as_str() == s)
+            .ok_or_else(|| anyhow!("unknown schema kind '{}'", s))
+    }
+}
+
+/// The JSON Schema for `kind`. Draft 7, which taplo (and so Even Better
+/// TOML) validates against.
+pub fn schema(kind: Kind) -> Schema {
+    match kind {
+        Kind::Theme => generate::<ThemeConfig>(),
+        Kind::Icons => generate::<IconsConfig>(),
+        Kind::Layout => generate::<LayoutConfig>(),
+        Kind::Cookbook => generate::<DictionaryConfig>(),
+        Kind::Ingredient => generate::<Ingredient>(),
+    }
+}
+
+fn generate<T: JsonSchema>() -> Schema {
+    SchemaSettings::draft07()
+        .for_deserialize()
+        .into_generator()
+        .into_root_schema_for::<T>()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::Value;
+
+    fn properties(kind: Kind) -> Vec<String> {
+        let schema = schema(kind).to_value();
+        let mut keys: Vec<String> = schema["properties"]
+            .as_object()
+            .unwrap()
+            .keys()
+            .cloned()
+            .collect();
+        keys.sort();
+        keys
+    }
+
+    #[test]
+    fn test_kind_roundtrip() {
+        for kind in Kind::ALL {
+            assert_eq!(kind.as_str().parse::<Kind>().unwrap(), *kind);
+        }
+        assert!("pastry".parse::<Kind>().is_err());
+    }
+
+    #[test]
+    fn test_schemas_follow_the_structs() {
+        assert_eq!(
+            properties(Kind::Theme),
+            [
+                "colors", "fonts", "include", "meta", "settings", "variant", "variants"
+            ]
+        );
+        assert_eq!(properties(Kind::Cookbook), ["include", "presets"]);
+        // `.ing` files spell the manifest `[package]`
+        assert_eq!(
+            properties(Kind::Ingredient),
+            ["files", "hooks", "package", "templates"]
+        );
+
+        let theme = schema(Kind::Theme).to_value();
+        assert_eq!(theme["$schema"], "http://json-schema.org/draft-07/schema#");
+        assert_eq!(
+            theme["required"],
+            Value::from(vec!["meta", "settings", "colors", "fonts"])
+        );
+        let colors = &theme["properties"]["colors"]["additionalProperties"];
+        assert_eq!(colors["$ref"], "#/definitions/Color");
+        assert_eq!(theme["definitions"]["Color"]["type"], "string");
+
+        // Any table name is an icon set
+        let icons = schema(Kind::Icons).to_value();
+        assert_eq!(icons["additionalProperties"]["type"], "object");
+    }
+
+    #[test]
+    fn test_example_config_keys_are_in_the_schema() {
+        let dir =
+            std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("../../assets/examples/config");
+        for (file, kind) in [
+            ("theme.toml", Kind::Theme),
+            ("icons.toml", Kind::Icons),
+            ("layout.toml", Kind::Layout),
+        ] {
+            let content = std::fs::read_to_string(dir.join(file)).unwrap();
+            let value: toml::Value = toml::from_str(&content).unwrap();
+            let known = properties(kind);
+            // Icon sets are named freely, like colors
+            let open = schema(kind).to_value()["additionalProperties"].is_object();
+            for key in value.as_table().unwrap().keys() {
+                assert!(open || known.contains(key), "{}: {}", file, key);
+            }
+        }
+    }
+}
@@ -1,6 +1,10 @@
-use k_lib::db::Pantry;
+use k_lib::db::{Pantry, Removal, content_hash};
+use k_lib::format::{FileFormat, PANTRY};
 use k_lib::ingredient::{Ingredient, IngredientManifest};
-use tempfile::NamedTempFile;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fs;
+use tempfile::{NamedTempFile, tempdir};
 
 fn create_ingredient(name: &str) -> Ingredient {
     Ingredient {
@@ -79,3 +83,250 @@ fn test_update_fragment() {
 
     assert_eq!(db.list()[0].meta.version, "2.0.0");
 }
+
+#[test]
+fn test_remove_deletes_and_restores_targets() {
+    let dir = tempdir().unwrap();
+    let mut db = Pantry::load(&dir.path().join("pantry.db")).unwrap();
+    let app = create_ingredient("app");
+    db.store(app.clone()).unwrap();
+
+    let fresh = dir.path().join("fresh.conf");
+    let existing = dir.path().join("existing.conf");
+    fs::write(&existing, "hand written").unwrap();
+
+    db.write_target(&app, "test", &fresh, "generated").unwrap();
+    db.write_target(&app, "test", &existing, "generated")
+        .unwrap();
+    assert_eq!(fs::read_to_string(&existing).unwrap(), "generated");
+
+    // Rewriting an owned path must not overwrite the original backup
+    db.write_target(&app, "test", &existing, "regenerated")
+        .unwrap();
+    assert_eq!(db.targets("app").len(), 2);
+    assert_eq!(db.targets("app")[1].hash, content_hash(b"regenerated"));
+
+    let (pkg, removals) = db.remove("app").unwrap().unwrap();
+    assert_eq!(pkg.meta.name, "app");
+    assert_eq!(
+        removals,
+        vec![
+            Removal::Deleted(fresh.clone()),
+            Removal::Restored(existing.clone())
+        ]
+    );
+    assert!(!fresh.exists());
+    assert_eq!(fs::read_to_string(&existing).unwrap(), "hand written");
+    assert!(db.targets("app").is_empty());
+    assert!(db.remove("app").unwrap().is_none());
+}
+
+#[test]
+fn test_remove_continues_past_failures() {
+    let dir = tempdir().unwrap();
+    let mut db = Pantry::load(&dir.path().join("pantry.db")).unwrap();
+    let app = create_ingredient("app");
+    db.store(app.clone()).unwrap();
+
+    // A directory where a file was recorded cannot be deleted with remove_file
+    let stuck = dir.path().join("stuck");
+    let fresh = dir.path().join("fresh.conf");
+    db.write_target(&app, "test", &stuck, "generated").unwrap();
+    db.write_target(&app, "test", &fresh, "generated").unwrap();
+    fs::remove_file(&stuck).unwrap();
+    fs::create_dir(&stuck).unwrap();
+
+    let (_, removals) = db.remove("app").unwrap().unwrap();
+    assert!(matches!(&removals[0], Removal::Failed(path, _) if *path == stuck));
+    assert_eq!(removals[1], Removal::Deleted(fresh.clone()));
+    assert!(!fresh.exists());
+
+    // Only the failed target stays recorded, and the ingredient stays stocked
+    assert!(db.get("app").is_some());
+    assert_eq!(db.targets("app").len(), 1);
+    assert_eq!(db.targets("app")[0].path, stuck);
+
+    fs::remove_dir(&stuck).unwrap();
+    let (_, removals) = db.remove("app").unwrap().unwrap();
+    assert_eq!(removals, vec![Removal::Missing(stuck)]);
+    assert!(db.get("app").is_none());
+}
+
+#[test]
+fn test_failed_write_is_not_recorded() {
+    let dir = tempdir().unwrap();
+    let mut db = Pantry::load(&dir.path().join("pantry.db")).unwrap();
+    let app = create_ingredient("app");
+    db.store(app.clone()).unwrap();
+
+    // A regular file where the target's parent directory should be
+    fs::write(dir.path().join("blocker"), "").unwrap();
+    let target = dir.path().join("blocker").join("out.conf");
+    assert!(db.write_target(&app, "test", &target, "generated").is_err());
+    assert!(db.targets("app").is_empty());
+    assert!(db.owner_of(&target).is_none());
+}
+
+#[test]
+fn test_backups_of_similar_paths_do_not_collide() {
+    let dir = tempdir().unwrap();
+    let mut db = Pantry::load(&dir.path().join("pantry.db")).unwrap();
+    let app = create_ingredient("app");
+    db.store(app.clone()).unwrap();
+
+    let nested = dir.path().join("a").join("b");
+    let flat = dir.path().join("a%b");
+    fs::create_dir(dir.path().join("a")).unwrap();
+    fs::write(&nested, "nested original").unwrap();
+    fs::write(&flat, "flat original").unwrap();
+
+    db.write_target(&app, "test", &nested, "generated").unwrap();
+    db.write_target(&app, "test", &flat, "generated").unwrap();
+    assert_ne!(db.targets("app")[0].backup, db.targets("app")[1].backup);
+
+    db.remove("app").unwrap().unwrap();
+    assert_eq!(fs::read_to_string(&nested).unwrap(), "nested original");
+    assert_eq!(fs::read_to_string(&flat).unwrap(), "flat original");
+}
+
+#[test]
+fn test_remove_leaves_targets_another_ingredient_owns() {
+    let dir = tempdir().unwrap();
+    let mut db = Pantry::load(&dir.path().join("pantry.db")).unwrap();
+    let app = create_ingredient("app");
+    let other = create_ingredient("other");
+    db.store(app.clone()).unwrap();
+    db.store(other.clone()).unwrap();
+
+    let shared = dir.path().join("shared.conf");
+    fs::write(&shared, "hand written").unwrap();
+    db.write_target(&app, "test", &shared, "from app").unwrap();
+    db.write_target(&other, "test", &shared, "from other")
+        .unwrap();
+
+    let (_, removals) = db.remove("app").unwrap().unwrap();
+    assert_eq!(
+        removals,
+        vec![Removal::Shared(shared.clone(), "other".to_string())]
+    );
+    assert_eq!(fs::read_to_string(&shared).unwrap(), "from other");
+    assert!(db.get("app").is_none());
+
+    // The original goes back into place once the last owner is removed
+    let (_, removals) = db.remove("other").unwrap().unwrap();
+    assert_eq!(removals, vec![Removal::Restored(shared.clone())]);
+    assert_eq!(fs::read_to_string(&shared).unwrap(), "hand written");
+}
+
+#[test]
+fn test_targets_persist() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("pantry.db");
+    let target = dir.path().join("out.conf");
+
+    {
+        let mut db = Pantry::load(&path).unwrap();
+        let app = create_ingredient("app");
+        db.store(app.clone()).unwrap();
+        db.write_target(&app, "Sweet Dracula", &target, "content")
+            .unwrap();
+        db.save().unwrap();
+    }
+
+    let db = Pantry::load(&path).unwrap();
+    assert_eq!(db.targets("app")[0].path, target);
+    assert_eq!(db.targets("app")[0].hash, content_hash(b"content"));
+
+    let (owner, record) = db.owner_of(&target).unwrap();
+    assert_eq!(owner.meta.name, "app");
+    assert_eq!(record.version, "1.0.0");
+    assert_eq!(record.theme, "Sweet Dracula");
+    assert!(db.owner_of(&dir.path().join("other.conf")).is_none());
+}
+
+#[test]
+fn test_load_legacy_pantry() {
+    #[derive(Serialize)]
+    struct Manifest {
+        name: String,
+        version: String,
+        authors: Vec<String>,
+        description: String,
+        repository: Option<String>,
+        license: Option<String>,
+        ignored: bool,
+    }
+    #[derive(Serialize)]
+    struct Hooks {
+        reload: Option<String>,
+    }
+    #[derive(Serialize)]
+    struct LegacyIngredient {
+        meta: Manifest,
+        templates: Vec<()>,
+        files: Vec<()>,
+        hooks: Hooks,
+    }
+
+    let legacy = HashMap::from([(
+        "old".to_string(),
+        LegacyIngredient {
+            meta: Manifest {
+                name: "old".into(),
+                version: "0.1.0".into(),
+                authors: vec![],
+                description: "".into(),
+                repository: None,
+                license: None,
+                ignored: false,
+            },
+            templates: vec![],
+            files: vec![],
+            hooks: Hooks {
+                reload: Some("true".into()),
+            },
+        },
+    )]);
+
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("pantry.db");
+    let bytes = bincode::serde::encode_to_vec(&legacy, bincode::config::standard()).unwrap();
+    fs::write(&path, &bytes).unwrap();
+
+    let db = Pantry::load(&path).unwrap();
+    let pkg = db.get("old").unwrap();
+    assert_eq!(pkg.hooks.reload.as_deref(), Some("true"));
+    assert!(pkg.hooks.on_remove.is_none());
+    assert!(db.targets("old").is_empty());
+    // The original is kept until the migrated pantry is known to work
+    assert_eq!(fs::read(dir.path().join("pantry.db.v1")).unwrap(), bytes);
+
+    db.save().unwrap();
+    let saved = fs::read(&path).unwrap();
+    assert_eq!(
+        PANTRY
+            .read_header(&mut &saved[..])
+            .unwrap()
+            .unwrap()
+            .version,
+        PANTRY.version
+    );
+    assert!(Pantry::load(&path).unwrap().get("old").is_some());
+}
+
+#[test]
+fn test_newer_pantry_is_rejected() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("pantry.db");
+    let newer = FileFormat {
+        version: PANTRY.version + 1,
+        ..PANTRY
+    };
+    let mut bytes = Vec::new();
+    newer.write_header(&mut bytes).unwrap();
+    bytes.extend_from_slice(b"future data");
+    fs::write(&path, &bytes).unwrap();
+
+    let err = Pantry::load(&path).err().unwrap();
+    assert!(err.to_string().contains("pantry.db is format 2"), "{}", err);
+}
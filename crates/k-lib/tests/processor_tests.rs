@@ -1,8 +1,8 @@
 use k_lib::config::{
-    Cookbook, DictionaryConfig, IconsConfig, LayoutConfig, LoggingConfig, StructureConfig,
-    TagConfig, ThemeConfig, ThemeMeta, ThemeSettings,
+    Cookbook, DictionaryConfig, IconChain, IconsConfig, LayoutConfig, LoggingConfig,
+    StructureConfig, TagConfig, ThemeConfig, ThemeMeta, ThemeSettings,
 };
-use k_lib::ingredient::{Hooks, Ingredient, IngredientManifest};
+use k_lib::ingredient::{Hooks, Ingredient, IngredientManifest, Template};
 use k_lib::processor;
 use std::collections::HashMap;
 
@@ -13,15 +13,18 @@ fn mock_cookbook() -> Cookbook {
                 name: "test".into(),
             },
             settings: ThemeSettings {
-                active_icons: "ascii".into(),
+                active_icons: IconChain::One("ascii".into()),
+                schedule: None,
             },
             colors: HashMap::new(),
+            spellings: HashMap::new(),
             fonts: HashMap::new(),
             include: None,
+            variants: HashMap::new(),
+            variant: None,
         },
         icons: IconsConfig {
-            nerdfont: HashMap::new(),
-            ascii: HashMap::new(),
+            sets: HashMap::new(),
             include: None,
         },
         layout: LayoutConfig {
@@ -31,6 +34,8 @@ fn mock_cookbook() -> Cookbook {
                 transform: "none".into(),
                 min_width: 0,
                 alignment: "left".into(),
+                max_width: None,
+                levels: HashMap::new(),
             },
             labels: HashMap::new(),
             structure: StructureConfig {
@@ -73,6 +78,7 @@ fn test_processor_apply_hook_success() {
         files: vec![],
         hooks: Hooks {
             reload: Some("true".to_string()),
+            on_remove: None,
         },
     };
 
@@ -99,6 +105,7 @@ fn test_processor_apply_hook_failure() {
         files: vec![],
         hooks: Hooks {
             reload: Some("false".to_string()),
+            on_remove: None,
         },
     };
 
@@ -109,3 +116,141 @@ fn test_processor_apply_hook_failure() {
     );
     assert!(!result.unwrap(), "Hook should fail");
 }
+
+#[test]
+fn test_render_header_comment_syntax() {
+    let config = mock_cookbook();
+    let template = |target: &str, content: &str| Template {
+        target: target.to_string(),
+        content: content.to_string(),
+        header: true,
+    };
+
+    let pkg = Ingredient {
+        meta: IngredientManifest {
+            name: "headers".to_string(),
+            version: "1.2.0".to_string(),
+            authors: vec![],
+            description: "test".to_string(),
+            repository: None,
+            license: None,
+            ignored: false,
+        },
+        templates: vec![
+            template("/tmp/kitchn/app.toml", "a = 1"),
+            template("/tmp/kitchn/style.css", "* {}"),
+            template("/tmp/kitchn/init.lua", "x = 1"),
+            template("/tmp/kitchn/config.kdl", "layout {}"),
+            template("/tmp/kitchn/run.sh", "#!/bin/sh\necho hi"),
+            template("/tmp/kitchn/data.json", "{}"),
+            template("/tmp/kitchn/icon.svg", "<svg/>"),
+            template("/tmp/kitchn/colors.vim", "hi Normal"),
+            template("/tmp/kitchn/launch", "#!/bin/sh\nexec app"),
+        ],
+        files: vec![],
+        hooks: Hooks::default(),
+    };
+
+    let rendered = processor::render(&pkg, &config).unwrap();
+    let content: Vec<&str> = rendered.iter().map(|(_, c)| c.as_str()).collect();
+
+    let note = "Generated by kitchn from ingredient 'headers' v1.2.0";
+    assert!(content[0].starts_with(&format!("# {}", note)));
+    assert!(content[0].ends_with("\na = 1"));
+    assert!(content[1].starts_with(&format!("/* {}", note)));
+    assert!(content[1].lines().next().unwrap().ends_with(" */"));
+    assert!(content[2].starts_with(&format!("-- {}", note)));
+    assert!(content[3].starts_with(&format!("// {}", note)));
+    assert!(content[4].starts_with(&format!("#!/bin/sh\n# {}", note)));
+    assert_eq!(content[5], "{}");
+    // Unknown syntax gets no header rather than a comment that breaks it
+    assert_eq!(content[6], "<svg/>");
+    assert_eq!(content[7], "hi Normal");
+    assert!(content[8].starts_with(&format!("#!/bin/sh\n# {}", note)));
+}
+
+#[test]
+fn test_render_typed_colors() {
+    let mut config = mock_cookbook();
+    config
+        .theme
+        .colors
+        .insert("bg".into(), "#161925".parse().unwrap());
+    config
+        .theme
+        .colors
+        .insert("overlay".into(), "rgba(0, 0, 0, 0.5)".parse().unwrap());
+    config
+        .theme
+        .spellings
+        .insert("overlay".into(), "rgba(0, 0, 0, 0.5)".into());
+
+    let pkg = Ingredient {
+        meta: IngredientManifest {
+            name: "colors".to_string(),
+            version: "0.1.0".to_string(),
+            authors: vec![],
+            description: "test".to_string(),
+            repository: None,
+            license: None,
+            ignored: false,
+        },
+        templates: vec![Template {
+            target: "/tmp/kitchn/colors.css".to_string(),
+            content: "{{ colors.bg }} {{ colors.bg | hex_to_rgb | join(sep=\",\") }} \
+                      {{ colors.overlay }} {{ colors.overlay | hex }} \
+                      {{ colors.overlay | hex_to_rgb | join(sep=\",\") }}"
+                .to_string(),
+            header: false,
+        }],
+        files: vec![],
+        hooks: Hooks::default(),
+    };
+
+    let rendered = processor::render(&pkg, &config).unwrap();
+    // Colors read as spelled in the theme; without a spelling, as typed hex
+    assert_eq!(
+        rendered[0].1,
+        "#161925 22,25,37 rgba(0, 0, 0, 0.5) #00000080 0,0,0,128"
+    );
+}
+
+#[test]
+fn test_render_icon_chain_and_sets() {
+    let mut config = mock_cookbook();
+    config.theme.settings.active_icons = IconChain::Chain(vec!["nerdfont".into(), "ascii".into()]);
+    config.icons.sets.insert(
+        "nerdfont".into(),
+        HashMap::from([("success".to_string(), "N".to_string())]),
+    );
+    config.icons.sets.insert(
+        "ascii".into(),
+        HashMap::from([
+            ("success".to_string(), "*".to_string()),
+            ("error".to_string(), "!".to_string()),
+        ]),
+    );
+
+    let pkg = Ingredient {
+        meta: IngredientManifest {
+            name: "icons".to_string(),
+            version: "0.1.0".to_string(),
+            authors: vec![],
+            description: "test".to_string(),
+            repository: None,
+            license: None,
+            ignored: false,
+        },
+        templates: vec![Template {
+            target: "/tmp/kitchn/icons.txt".to_string(),
+            content: "{{ icons.success }} {{ icons.error }} {{ icon_sets.ascii.success }}"
+                .to_string(),
+            header: false,
+        }],
+        files: vec![],
+        hooks: Hooks::default(),
+    };
+
+    let rendered = processor::render(&pkg, &config).unwrap();
+    assert_eq!(rendered[0].1, "N ! *");
+}
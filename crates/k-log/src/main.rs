@@ -1,7 +1,9 @@
 use anyhow::{Context, Result};
 use clap::Parser;
-use k_lib::config::Cookbook;
-use k_lib::logger;
+use k_lib::config::{Cookbook, ENV_PREFIX};
+use k_lib::dirs::Dirs;
+use k_lib::logger::{self, LogStyle};
+use k_lib::pastry::Pastry;
 
 #[derive(Parser)]
 #[command(name = "kitchn-log", version, about = "Kitchn Logging Tool")]
@@ -20,32 +22,53 @@ struct Cli {
 
 fn main() -> Result<()> {
     let cli = Cli::parse();
-    let config = Cookbook::load().context("Failed to load Kitchn config")?;
+    let dirs = Dirs::resolve()?;
+
+    // Hot path: read the preset and styles straight from the mapped
+    // pastry.bin. Environment overrides need the owned cookbook.
+    let overridden = std::env::vars_os()
+        .any(|(name, _)| name.as_encoded_bytes().starts_with(ENV_PREFIX.as_bytes()));
+    if !overridden && let Ok(Some(pastry)) = Pastry::open(&dirs.pastry_path(), &dirs) {
+        let preset = pastry.preset(&cli.preset).context(format!(
+            "Preset '{}' not found in dictionary.toml",
+            cli.preset
+        ))?;
+        let scope = preset
+            .scope
+            .as_ref()
+            .context("Preset missing 'scope' field")?;
+        return log(&cli, pastry.cookbook(), &preset.level, scope, &preset.msg);
+    }
+
+    let config = Cookbook::load_in(&dirs).context("Failed to load Kitchn config")?;
 
     let preset = config.dictionary.presets.get(&cli.preset).context(format!(
         "Preset '{}' not found in dictionary.toml",
         cli.preset
     ))?;
 
-    let level = &preset.level;
     let scope = preset
         .scope
         .as_ref()
         .context("Preset missing 'scope' field")?;
 
+    log(&cli, &config, &preset.level, scope, &preset.msg)
+}
+
+fn log(cli: &Cli, style: &impl LogStyle, level: &str, scope: &str, preset_msg: &str) -> Result<()> {
     // Join override words or use preset msg
     let msg_string;
     let msg = if let Some(args) = &cli.msg {
         msg_string = args.join(" ");
         &msg_string
     } else {
-        &preset.msg
+        preset_msg
     };
 
-    logger::log_to_terminal(&config, level, scope, msg);
+    logger::log_to_terminal(style, level, scope, msg);
 
-    if config.layout.logging.write_by_default {
-        logger::log_to_file(&config, level, scope, msg, cli.app.as_deref())?;
+    if style.logging().write_by_default {
+        logger::log_to_file(style, level, scope, msg, cli.app.as_deref())?;
     }
 
     Ok(())
@@ -71,6 +71,16 @@ typedef struct KitchnContext KitchnContext;
  */
 typedef struct KitchnPantry KitchnPantry;
 
+/**
+ * A theme color; `a` is 255 for opaque colors
+ */
+typedef struct KitchnColor {
+    uint8_t r;
+    uint8_t g;
+    uint8_t b;
+    uint8_t a;
+} KitchnColor;
+
 #ifdef __cplusplus
 extern "C" {
 #endif // __cplusplus
@@ -102,6 +112,13 @@ void kitchn_log(struct KitchnContext *ctx, const char *level, const char *scope,
  */
 int kitchn_log_preset(struct KitchnContext *ctx, const char *preset_key, const char *msg_override);
 
+/**
+ * Writes the active theme's color `key` (e.g. "primary") to `out`.
+ * Returns 1 if the theme has no such color.
+ * # Safety
+ */
+int kitchn_get_color(struct KitchnContext *ctx, const char *key, struct KitchnColor *out);
+
 /**
  * # Safety
  */